@@ -1,11 +1,18 @@
-/// This module exposes some getters/setters
+//! This module exposes some getters/setters
 
 use crate::{
   Gameboy, Res,
-  apu::AudioDevice,
+  apu::{AudioDevice, AudioFilter},
+  serial::SerialLink,
   cpu::CpuState,
+  Model,
+  event::EventRecorder,
+  disasm::{self, Instruction},
+  io_registers::IoRegisters,
+  renderer::{Renderer, RgbaImage},
+  ppu::oam::OamObject,
   bus::cartridge::{RomHeader, CartridgeImpl},
-  consts::FB_SIZE
+  consts::{FB_SIZE, SGB_FB_SIZE, VRAM_SIZE}
 };
 
 impl Gameboy {
@@ -25,10 +32,39 @@ impl Gameboy {
     self.cpu.state
   }
 
+  ///Display data as DMG shades (0-3),
+  ///in CGB mode the colors are converted to the closest shade (see `get_display_data_cgb` for the actual colors)
   #[inline] pub fn get_display_data(&self) -> &[u8; FB_SIZE] {
     &self.cpu.bus.ppu.display
   }
 
+  ///15-bit RGB display data, only updated in CGB mode
+  #[inline] pub fn get_display_data_cgb(&self) -> &[u16; FB_SIZE] {
+    &self.cpu.bus.ppu.display_cgb
+  }
+
+  ///15-bit RGB display data (256x224, including the border), only updated in SGB mode (see `is_sgb_mode`)
+  #[inline] pub fn get_display_data_sgb(&self) -> &[u16; SGB_FB_SIZE] {
+    &self.cpu.bus.sgb.display
+  }
+
+  #[inline] pub fn get_model(&self) -> Model {
+    self.cpu.bus.model
+  }
+
+  #[inline] pub fn is_cgb_mode(&self) -> bool {
+    self.cpu.bus.cgb_mode
+  }
+
+  ///Returns true if the ROM uses SGB functions
+  #[inline] pub fn is_sgb_mode(&self) -> bool {
+    self.cpu.bus.sgb_mode
+  }
+
+  #[inline] pub fn is_double_speed(&self) -> bool {
+    self.cpu.bus.double_speed
+  }
+
   #[inline] pub fn read_mem(&self, addr: u16) -> u8 {
     self.cpu.bus.rb(addr, false)
   }
@@ -43,25 +79,65 @@ impl Gameboy {
     self.cpu.bus.ww(addr, value, false);
   }
 
-  #[inline] pub fn render_tileset(&self) {
-    self.cpu.bus.ppu.render_tileset();
+  ///Decode the instruction at `addr` (see the `disasm` module)
+  #[inline] pub fn disassemble(&self, addr: u16) -> Instruction {
+    let mut instruction = disasm::disassemble(addr, |addr| self.cpu.bus.rb(addr, false));
+    if let Some(target) = instruction.target() {
+      instruction.target_bank = self.cpu.bus.bank_at(target);
+    }
+    instruction
+  }
+
+  ///Decoded IO registers along with the internal state of the components behind them
+  pub fn get_io_registers(&self) -> IoRegisters {
+    let bus = &self.cpu.bus;
+    IoRegisters {
+      ppu: bus.ppu.snapshot(),
+      timers: bus.timers.snapshot(),
+      interrupts: self.cpu.interrupt_snapshot(),
+      joypad: bus.input.snapshot(),
+      serial: bus.serial.snapshot(),
+      apu: bus.apu.snapshot(),
+    }
+  }
+
+  ///Raw contents of a VRAM bank (0x8000-0x9FFF), bank 1 only exists in CGB mode
+  #[inline] pub fn get_vram(&self, bank: usize) -> &[u8; VRAM_SIZE] {
+    self.cpu.bus.ppu.get_vram(bank)
+  }
+
+  #[inline] pub fn get_oam(&self) -> &[OamObject; 40] {
+    self.cpu.bus.ppu.get_oam()
+  }
+
+  ///Render all tiles in VRAM using the default `Renderer` settings (see `Renderer::render_tileset`)
+  #[inline] pub fn render_tileset(&self) -> RgbaImage {
+    Renderer::default().render_tileset(self)
   }
 
   #[inline] pub fn load_rom(&mut self, data: &[u8]) -> Res<()> {
-    self.cpu.bus.load_rom(data)
+    self.cpu.bus.load_rom(data)?;
+    self.skip_missing_bootrom();
+    Ok(())
   }
   #[inline] pub fn load_rom_force_mbc(&mut self, data: &[u8], mbc_type: u8) -> Res<()> {
-    self.cpu.bus.load_rom_force_mbc(data, mbc_type)
+    self.cpu.bus.load_rom_force_mbc(data, mbc_type)?;
+    self.skip_missing_bootrom();
+    Ok(())
   }
   #[allow(deprecated)]
   #[deprecated(note="Please handle file reading yourself")]
   #[inline] pub fn load_rom_file(&mut self, path: &str) -> Res<()> {
-    self.cpu.bus.load_file(path)
+    self.cpu.bus.load_file(path)?;
+    self.skip_missing_bootrom();
+    Ok(())
   }
   #[allow(deprecated)]
   #[deprecated(note="Please handle file reading yourself")]
   #[inline] pub fn load_rom_file_force_mbc(&mut self, path: &str, mbc_type: u8) -> Res<()> {
-    self.cpu.bus.load_file_force_mbc(path, mbc_type)
+    self.cpu.bus.load_file_force_mbc(path, mbc_type)?;
+    self.skip_missing_bootrom();
+    Ok(())
   }
 
   #[inline] pub fn get_mbc_name(&self) -> &str {
@@ -172,13 +248,61 @@ impl Gameboy {
     self.cpu.bus.ppu.frame_ready
   }
 
+  ///Get the ROM/RAM bank currently mapped at `addr` (0 for unbanked regions)
+  #[inline] pub fn get_bank_at(&self, addr: u16) -> u16 {
+    self.cpu.bus.bank_at(addr)
+  }
+
+  ///Add or remove an unconditional breakpoint at `addr` (in any bank)
   #[cfg(feature = "dbg-breakpoints")]
   #[inline] pub fn set_pc_breakpoint(&mut self, addr: u16, enable: bool) {
-    self.cpu.pc_breakpoints[addr as usize] = enable;
+    let list = &mut self.cpu.breakpoints.list;
+    list.retain(|x| !(x.addr == addr && x.bank.is_none() && x.conditions.is_empty() && x.ignore_count == 0));
+    if enable {
+      list.push(crate::Breakpoint::new(addr));
+    }
+  }
+  ///Check if there are any breakpoints at `addr`
+  #[cfg(feature = "dbg-breakpoints")]
+  #[inline] pub fn get_pc_breakpoint(&self, addr: u16) -> bool {
+    self.cpu.breakpoints.list.iter().any(|x| x.addr == addr)
+  }
+  #[cfg(feature = "dbg-breakpoints")]
+  #[inline] pub fn add_breakpoint(&mut self, breakpoint: crate::Breakpoint) {
+    self.cpu.breakpoints.list.push(breakpoint);
+  }
+  #[cfg(feature = "dbg-breakpoints")]
+  #[inline] pub fn remove_breakpoint(&mut self, index: usize) -> crate::Breakpoint {
+    self.cpu.breakpoints.list.remove(index)
+  }
+  #[cfg(feature = "dbg-breakpoints")]
+  #[inline] pub fn get_breakpoints(&self) -> &[crate::Breakpoint] {
+    &self.cpu.breakpoints.list
+  }
+  #[cfg(feature = "dbg-breakpoints")]
+  #[inline] pub fn get_breakpoints_mut(&mut self) -> &mut Vec<crate::Breakpoint> {
+    &mut self.cpu.breakpoints.list
+  }
+  #[cfg(feature = "dbg-breakpoints")]
+  #[inline] pub fn clear_breakpoints(&mut self) {
+    self.cpu.breakpoints.list.clear();
+  }
+
+  #[cfg(feature = "dbg-breakpoints")]
+  #[inline] pub fn add_watchpoint(&mut self, watchpoint: crate::Watchpoint) {
+    self.cpu.bus.watchpoints.list.push(watchpoint);
+  }
+  #[cfg(feature = "dbg-breakpoints")]
+  #[inline] pub fn remove_watchpoint(&mut self, index: usize) -> crate::Watchpoint {
+    self.cpu.bus.watchpoints.list.remove(index)
   }
   #[cfg(feature = "dbg-breakpoints")]
-  #[inline] pub fn get_pc_breakpoint(&mut self, addr: u16) -> bool {
-    self.cpu.pc_breakpoints[addr as usize]
+  #[inline] pub fn get_watchpoints(&self) -> &[crate::Watchpoint] {
+    &self.cpu.bus.watchpoints.list
+  }
+  #[cfg(feature = "dbg-breakpoints")]
+  #[inline] pub fn clear_watchpoints(&mut self) {
+    self.cpu.bus.watchpoints.list.clear();
   }
 
   #[deprecated] #[inline] pub fn _set_audio_device_dyn(&mut self, device: Box<dyn AudioDevice>) {
@@ -201,6 +325,88 @@ impl Gameboy {
     self.cpu.bus.apu.device.take()
   }
 
+  ///Set the audio output sample rate in Hz (44100 by default), samples that weren't drained yet are discarded
+  ///
+  ///Fails with `YargeError::InvalidSampleRate` if the rate is 0 or above `CPU_CLOCK_SPEED`
+  #[inline] pub fn set_audio_sample_rate(&mut self, sample_rate: usize) -> Res<()> {
+    self.cpu.bus.apu.set_sample_rate(sample_rate)
+  }
+  #[inline] pub fn get_audio_sample_rate(&self) -> usize {
+    self.cpu.bus.apu.get_sample_rate()
+  }
+
+  ///Set the number of interleaved stereo samples passed to `AudioDevice::queue_samples` at once
+  ///(4096 by default), must be even and non-zero (fails with `YargeError::InvalidChunkSize` otherwise)
+  #[inline] pub fn set_audio_chunk_size(&mut self, chunk_size: usize) -> Res<()> {
+    self.cpu.bus.apu.set_chunk_size(chunk_size)
+  }
+  #[inline] pub fn get_audio_chunk_size(&self) -> usize {
+    self.cpu.bus.apu.get_chunk_size()
+  }
+
+  ///Move generated audio to `out` as interleaved stereo samples (left, right), oldest first.
+  ///Returns the number of values written (always even).
+  ///
+  ///Up to a second of audio is kept, older samples are dropped if they're not drained in time.\
+  ///If an `AudioDevice` is set, it receives samples first (in chunks)
+  #[inline] pub fn drain_audio(&mut self, out: &mut [f32]) -> usize {
+    self.cpu.bus.apu.drain_samples(out)
+  }
+
+  ///Number of values available to `drain_audio`
+  #[inline] pub fn get_audio_available(&self) -> usize {
+    self.cpu.bus.apu.samples_available()
+  }
+
+  ///Set the output high-pass filter (defaults to the one matching the emulated model)
+  #[inline] pub fn set_audio_filter(&mut self, filter: AudioFilter) {
+    self.cpu.bus.apu.set_filter(filter);
+  }
+  #[inline] pub fn get_audio_filter(&self) -> AudioFilter {
+    self.cpu.bus.apu.get_filter()
+  }
+
+  ///Silence a channel (0-3) in the audio output, independent of NR51
+  ///
+  ///Channel numbers above 3 are ignored by all channel functions (getters return `false`)
+  #[inline] pub fn set_channel_muted(&mut self, channel: usize, muted: bool) {
+    if let Some(x) = self.cpu.bus.apu.muted.get_mut(channel) {
+      *x = muted;
+    }
+  }
+  #[inline] pub fn get_channel_muted(&self, channel: usize) -> bool {
+    self.cpu.bus.apu.muted.get(channel).copied().unwrap_or(false)
+  }
+
+  ///Solo a channel (0-3), while any channel is soloed all other channels are silenced
+  #[inline] pub fn set_channel_solo(&mut self, channel: usize, solo: bool) {
+    if let Some(x) = self.cpu.bus.apu.solo.get_mut(channel) {
+      *x = solo;
+    }
+  }
+  #[inline] pub fn get_channel_solo(&self, channel: usize) -> bool {
+    self.cpu.bus.apu.solo.get(channel).copied().unwrap_or(false)
+  }
+
+  ///Returns true if the channel (0-3) is audible, taking mute/solo into account
+  #[inline] pub fn is_channel_audible(&self, channel: usize) -> bool {
+    self.cpu.bus.apu.audible_channels().get(channel).copied().unwrap_or(false)
+  }
+
+  ///Copy the most recent raw amplitudes (-1.0 to 1.0, before mixing and mute/solo) of a channel (0-3) to `out`,
+  ///oldest first. Returns the number of samples copied (at most `CHANNEL_BUFFER_SIZE`, 0 for channels above 3)
+  #[inline] pub fn get_channel_samples(&self, channel: usize, out: &mut [f32]) -> usize {
+    self.cpu.bus.apu.copy_channel_samples(channel, out)
+  }
+
+  #[inline] pub fn set_serial_link(&mut self, link: impl SerialLink + 'static) {
+    self.cpu.bus.serial.link = Some(Box::new(link));
+  }
+
+  #[inline] pub fn remove_serial_link(&mut self) -> Option<Box<dyn SerialLink>> {
+    self.cpu.bus.serial.link.take()
+  }
+
   #[inline] pub fn has_save_data(&self) -> bool {
     self.cpu.bus.cart.has_save_data()
   }
@@ -212,4 +418,26 @@ impl Gameboy {
   #[inline] pub fn get_save_data(&self) -> Option<Vec<u8>> {
     self.cpu.bus.cart.save_data()
   }
+
+  #[inline] pub fn get_rewind_length(&self) -> usize {
+    self.rewind.as_ref().map(|rewind| rewind.len()).unwrap_or(0)
+  }
+
+  #[inline] pub fn get_rewind_memory_usage(&self) -> usize {
+    self.rewind.as_ref().map(|rewind| rewind.memory_usage()).unwrap_or(0)
+  }
+
+  #[inline] pub fn get_event_recorder(&self) -> Option<&EventRecorder> {
+    self.cpu.bus.event_recorder()
+  }
+  #[inline] pub fn get_event_recorder_mut(&mut self) -> Option<&mut EventRecorder> {
+    self.cpu.bus.event_recorder_mut()
+  }
+  #[inline] pub fn has_rumble(&self) -> bool {
+    self.cpu.bus.cart.has_rumble()
+  }
+
+  #[inline] pub fn get_rumble_motor(&self) -> bool {
+    self.cpu.bus.cart.rumble_motor()
+  }
 }
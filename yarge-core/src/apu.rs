@@ -1,8 +1,24 @@
-use crate::consts::{AUDIO_CYCLES_PER_SAMPLE, audio_registers::*};
+use crate::{Res, YargeError};
+use crate::consts::{CPU_CLOCK_SPEED, AUDIO_SAMPLE_RATE, AUDIO_BUFFER_SIZE, audio_registers::*};
+use crate::state::{impl_save_state, check_state};
+use crate::io_registers::ApuRegisters;
 use seq_macro::seq;
 
+/// Bits that always read as 1 (unused or write-only), FF10-FF2F
+const READ_MASKS: [u8; 0x20] = [
+  0x80, 0x3f, 0x00, 0xff, 0xbf, // NR10-NR14
+  0xff, 0x3f, 0x00, 0xff, 0xbf, // NR20-NR24
+  0x7f, 0xff, 0x9f, 0xff, 0xbf, // NR30-NR34
+  0xff, 0xff, 0x00, 0x00, 0xbf, // NR40-NR44
+  0x00, 0x00, 0x70,             // NR50-NR52
+  0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // FF27-FF2F
+];
+
 mod channels;
 mod audio_buffer;
+mod channel_buffer;
+mod blip_buffer;
+mod high_pass;
 mod audio_device;
 mod terminal;
 mod common;
@@ -10,9 +26,14 @@ mod common;
 use channels::{
   ApuChannel,
   square::SquareWaveChannel,
+  wave::WaveChannel,
   noise::NoiseChannel
 };
 use audio_buffer::AudioBuffer;
+use channel_buffer::ChannelBuffer;
+use blip_buffer::{BlipBuffer, PHASES};
+use high_pass::HighPass;
+pub use high_pass::AudioFilter;
 pub use audio_device::AudioDevice;
 use terminal::Terminal;
 
@@ -20,18 +41,35 @@ pub struct Apu {
   enabled: bool,
   pub device: Option<Box<dyn AudioDevice>>,
   buffer: AudioBuffer,
+  ///Scratch buffer for chunks passed to `device`
+  chunk: Vec<f32>,
+  sample_rate: usize,
+  chunk_size: usize,
+  channel_buffer: ChannelBuffer,
+  ///Left/right output
+  blip: (BlipBuffer, BlipBuffer),
+  filter: AudioFilter,
+  high_pass: (HighPass, HighPass),
+  amplitudes: [f32; 4],
+  ///Channels silenced in the mixed output (independent of NR51)
+  pub muted: [bool; 4],
+  ///If any channel is soloed, all other channels are silenced
+  pub solo: [bool; 4],
   /// 0 - CH1 - Square wave
   /// 1 - CH2 - Square wave, No sweep
+  /// 2 - CH3 - Wave
+  /// 3 - CH4 - Noise
   channels: (
     SquareWaveChannel<true>,
     SquareWaveChannel<false>,
-    (),
+    WaveChannel,
     NoiseChannel
   ),
   /// 0 - Right/SO1
   /// 1 - Left /SO2
   terminals: (Terminal, Terminal),
   sequencer: u8,
+  ///T-cycles elapsed since the last sample, multiplied by the sample rate
   sample_cycles: usize,
   prev_div: u16,
 }
@@ -41,11 +79,24 @@ impl Apu {
     Self {
       enabled: false,
       device: None,
-      buffer: AudioBuffer::new(),
+      buffer: AudioBuffer::new(buffer_capacity(AUDIO_SAMPLE_RATE, AUDIO_BUFFER_SIZE)),
+      chunk: Vec::with_capacity(AUDIO_BUFFER_SIZE),
+      sample_rate: AUDIO_SAMPLE_RATE,
+      chunk_size: AUDIO_BUFFER_SIZE,
+      channel_buffer: ChannelBuffer::new(),
+      blip: (BlipBuffer::new(), BlipBuffer::new()),
+      filter: AudioFilter::default(),
+      high_pass: (
+        HighPass::new(AudioFilter::default(), AUDIO_SAMPLE_RATE),
+        HighPass::new(AudioFilter::default(), AUDIO_SAMPLE_RATE),
+      ),
+      amplitudes: [0.; 4],
+      muted: [false; 4],
+      solo: [false; 4],
       channels: (
         SquareWaveChannel::new(),
         SquareWaveChannel::new(),
-        (),
+        WaveChannel::new(),
         NoiseChannel::new()
       ),
       terminals: (Terminal::new(), Terminal::new()),
@@ -62,24 +113,100 @@ impl Apu {
   // > (internally DIV is 16 bit but only the upper 8 bits are mapped to memory).
   // https://nightshade256.github.io/2021/03/27/gb-sound-emulation.html
 
-  fn update_div_falling_edge(&mut self, div: u16) -> bool {
-    //GBC: use bit 13 in double speed mode
+  fn update_div_falling_edge(&mut self, div: u16, double_speed: bool) -> bool {
     //XXX: bit 12? not 13? in sp
-    const BIT: u16 = 1 << 12;
-    let is_falling_edge = (div & BIT == 0) && (self.prev_div & BIT != 0);
+    //Use the next bit in double speed mode
+    let bit: u16 = (1 << 12) << double_speed as u16;
+    let is_falling_edge = (div & bit == 0) && (self.prev_div & bit != 0);
     self.prev_div = div;
     is_falling_edge
   }
 
+  pub fn set_filter(&mut self, filter: AudioFilter) {
+    self.filter = filter;
+    self.high_pass = (
+      HighPass::new(filter, self.sample_rate),
+      HighPass::new(filter, self.sample_rate),
+    );
+  }
+  pub fn get_filter(&self) -> AudioFilter {
+    self.filter
+  }
+
+  ///Change the output sample rate, samples that weren't drained yet are discarded
+  pub fn set_sample_rate(&mut self, sample_rate: usize) -> Res<()> {
+    if sample_rate == 0 || sample_rate > CPU_CLOCK_SPEED {
+      return Err(YargeError::InvalidSampleRate(sample_rate))
+    }
+    self.sample_rate = sample_rate;
+    self.sample_cycles = 0;
+    self.buffer.clear();
+    self.buffer.set_capacity(buffer_capacity(self.sample_rate, self.chunk_size));
+    self.set_filter(self.filter);
+    Ok(())
+  }
+  pub fn get_sample_rate(&self) -> usize {
+    self.sample_rate
+  }
+
+  pub fn set_chunk_size(&mut self, chunk_size: usize) -> Res<()> {
+    if chunk_size == 0 || chunk_size % 2 != 0 {
+      return Err(YargeError::InvalidChunkSize(chunk_size))
+    }
+    self.chunk_size = chunk_size;
+    self.buffer.set_capacity(buffer_capacity(self.sample_rate, self.chunk_size));
+    Ok(())
+  }
+  pub fn get_chunk_size(&self) -> usize {
+    self.chunk_size
+  }
+
+  pub fn drain_samples(&mut self, out: &mut [f32]) -> usize {
+    self.buffer.drain(out)
+  }
+  pub fn samples_available(&self) -> usize {
+    self.buffer.len()
+  }
+
+  pub(crate) fn settings(&self) -> ApuSettings {
+    ApuSettings {
+      muted: self.muted,
+      solo: self.solo,
+      filter: self.filter,
+      sample_rate: self.sample_rate,
+      chunk_size: self.chunk_size,
+    }
+  }
+  pub(crate) fn apply_settings(&mut self, settings: ApuSettings) {
+    self.muted = settings.muted;
+    self.solo = settings.solo;
+    //Settings are taken from an existing Apu, so they were already validated
+    self.set_chunk_size(settings.chunk_size).unwrap();
+    self.set_sample_rate(settings.sample_rate).unwrap();
+    self.set_filter(settings.filter);
+  }
+
+  ///Channels that are audible in the mixed output (taking mute/solo into account)
+  pub fn audible_channels(&self) -> [bool; 4] {
+    let any_solo = self.solo.contains(&true);
+    [0, 1, 2, 3].map(|i| !self.muted[i] && (!any_solo || self.solo[i]))
+  }
+
+  pub fn copy_channel_samples(&self, channel: usize, out: &mut [f32]) -> usize {
+    self.channel_buffer.copy_samples(channel, out)
+  }
+
   fn tick_all(&mut self) {
     self.channels.0.tick();
     self.channels.1.tick();
+    self.channels.2.tick();
     self.channels.3.tick();
   }
 
   fn tick_length_all(&mut self) {
     self.channels.0.tick_length();
     self.channels.1.tick_length();
+    self.channels.2.tick_length();
     self.channels.3.tick_length();
   }
 
@@ -111,40 +238,84 @@ impl Apu {
     }
   }
 
-  pub fn tick(&mut self, div: u16) {
-    let is_div_falling_edge = self.update_div_falling_edge(div);
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.sequencer <= 7, "invalid frame sequencer step")?;
+    check_state(self.sample_cycles < CPU_CLOCK_SPEED, "invalid sample timer")
+  }
+
+  fn dac_enabled(&self) -> bool {
+    self.channels.0.is_dac_enabled() ||
+    self.channels.1.is_dac_enabled() ||
+    self.channels.2.is_dac_enabled() ||
+    self.channels.3.is_dac_enabled()
+  }
+
+  ///Feed the current output level to the blip buffers, called every M-cycle
+  ///(steps are placed with 1/32 sample precision, which is ~3 T-cycles anyway)
+  fn update_output(&mut self) {
+    self.amplitudes = [
+      self.channels.0.amplitude(),
+      self.channels.1.amplitude(),
+      self.channels.2.amplitude(),
+      self.channels.3.amplitude(),
+    ];
+    let audible = self.audible_channels();
+    let [ch1, ch2, ch3, ch4] = [0, 1, 2, 3].map(|i| if audible[i] { self.amplitudes[i] } else { 0. });
+    let amplitudes = (ch1, ch2, ch3, ch4);
+    let phase = self.sample_cycles * PHASES / CPU_CLOCK_SPEED;
+    self.blip.0.set_level(phase, self.terminals.1.mix_outputs(amplitudes));
+    self.blip.1.set_level(phase, self.terminals.0.mix_outputs(amplitudes));
+  }
+
+  fn output_sample(&mut self) {
+    self.channel_buffer.push(self.amplitudes);
+    let dac_enabled = self.dac_enabled();
+    let samples = (
+      self.high_pass.0.apply(self.blip.0.read_sample(), dac_enabled),
+      self.high_pass.1.apply(self.blip.1.read_sample(), dac_enabled),
+    );
+    self.buffer.push(samples.0, samples.1);
+    if let Some(device) = self.device.as_mut() {
+      if self.buffer.len() >= self.chunk_size {
+        self.chunk.resize(self.chunk_size, 0.);
+        self.buffer.drain(&mut self.chunk);
+        device.queue_samples(&self.chunk);
+      }
+    }
+  }
+
+  pub fn tick(&mut self, div: u16, double_speed: bool) {
+    let is_div_falling_edge = self.update_div_falling_edge(div, double_speed);
+
+    //Keep producing (silent) samples while the APU is off
+    self.sample_cycles += self.sample_rate * 4;
+    while self.sample_cycles >= CPU_CLOCK_SPEED {
+      self.sample_cycles -= CPU_CLOCK_SPEED;
+      self.output_sample();
+    }
 
     if !self.enabled { return }
 
     for _ in 0..4 {
       self.tick_all();
     }
+    self.update_output();
 
     if is_div_falling_edge {
       self.tick_sequencer();
     }
+  }
 
-    self.sample_cycles += 4;
-    if self.sample_cycles >= AUDIO_CYCLES_PER_SAMPLE {
-      self.sample_cycles -= AUDIO_CYCLES_PER_SAMPLE;
-      let amplitudes = (
-        self.channels.0.amplitude(),
-        self.channels.1.amplitude(),
-        0.,
-        self.channels.3.amplitude(),
-      );
-      let samples = (
-        self.terminals.0.mix_outputs(amplitudes),
-        self.terminals.1.mix_outputs(amplitudes),
-      );
-      self.buffer.push(samples.1, samples.0);
-      //self.buffer.push(self.channel1.amplitude(), self.channel2.amplitude());
-      if self.buffer.is_full() {
-        if let Some(device) = self.device.as_mut() {
-          device.queue_samples(self.buffer.get_buffer());
-        }
-        self.buffer.reset();
-      }
+  pub fn snapshot(&self) -> ApuRegisters {
+    ApuRegisters {
+      enabled: self.enabled,
+      sequencer: self.sequencer,
+      channel1: self.channels.0.snapshot(),
+      channel2: self.channels.1.snapshot(),
+      channel3: self.channels.2.snapshot(),
+      channel4: self.channels.3.snapshot(),
+      left: self.terminals.1.snapshot(),
+      right: self.terminals.0.snapshot(),
     }
   }
 
@@ -154,12 +325,31 @@ impl Apu {
     (0xff30..=0xff3f).contains(&addr) // Wave pattern ram
   }
 
-  pub fn read(&self, addr: u16) -> u8 {
+  fn read_register(&self, addr: u16) -> u8 {
     match addr {
-      //TODO R_NRXX
+      R_NR10 => self.channels.0.read_register(0),
+      R_NR11 => self.channels.0.read_register(1),
+      R_NR12 => self.channels.0.read_register(2),
+      R_NR13 => self.channels.0.read_register(3),
+      R_NR14 => self.channels.0.read_register(4),
+      R_NR21 => self.channels.1.read_register(1),
+      R_NR22 => self.channels.1.read_register(2),
+      R_NR23 => self.channels.1.read_register(3),
+      R_NR24 => self.channels.1.read_register(4),
+      R_NR30 => self.channels.2.read_register(0),
+      R_NR31 => self.channels.2.read_register(1),
+      R_NR32 => self.channels.2.read_register(2),
+      R_NR33 => self.channels.2.read_register(3),
+      R_NR34 => self.channels.2.read_register(4),
+      R_NR41 => self.channels.3.read_register(1),
+      R_NR42 => self.channels.3.read_register(2),
+      R_NR43 => self.channels.3.read_register(3),
+      R_NR44 => self.channels.3.read_register(4),
       R_NR50 => {
         self.terminals.0.volume
+        | ((self.terminals.0.vin as u8) << 3)
         | (self.terminals.1.volume << 4)
+        | ((self.terminals.1.vin as u8) << 7)
       }
       R_NR51 => {
         #[allow(clippy::identity_op, clippy::erasing_op)] {
@@ -174,15 +364,39 @@ impl Apu {
         (self.enabled as u8) << 7
         | (self.channels.0.is_enabled() as u8)
         | (self.channels.1.is_enabled() as u8) << 1
+        | (self.channels.2.is_enabled() as u8) << 2
         | (self.channels.3.is_enabled() as u8) << 3
       },
       _ => 0
     }
   }
 
+  pub fn read(&self, addr: u16) -> u8 {
+    match addr {
+      0xff10..=0xff2f => self.read_register(addr) | READ_MASKS[(addr - 0xff10) as usize],
+      0xff30..=0xff3f => self.channels.2.read_wave_ram(addr),
+      _ => 0xff
+    }
+  }
+
+  fn power_off(&mut self) {
+    self.channels.0.power_off();
+    self.channels.1.power_off();
+    self.channels.2.power_off();
+    self.channels.3.power_off();
+    self.terminals = (Terminal::default(), Terminal::default());
+    //Output level isn't updated while the APU is off
+    self.update_output();
+  }
+
   pub fn write(&mut self, addr: u16, value: u8, blocking: bool) {
     //If the APU is disabled most registers are R/O
     if blocking && !self.check_write_access(addr) { return }
+    //While the APU is off, only the length timer bits can be written
+    let value = match addr {
+      R_NR11 | R_NR21 if !self.enabled => value & 0x3f,
+      _ => value
+    };
     match addr {
       R_NR10 => self.channels.0.write_register(0, value),
       R_NR11 => self.channels.0.write_register(1, value),
@@ -193,13 +407,20 @@ impl Apu {
       R_NR22 => self.channels.1.write_register(2, value),
       R_NR23 => self.channels.1.write_register(3, value),
       R_NR24 => self.channels.1.write_register(4, value),
+      R_NR30 => self.channels.2.write_register(0, value),
+      R_NR31 => self.channels.2.write_register(1, value),
+      R_NR32 => self.channels.2.write_register(2, value),
+      R_NR33 => self.channels.2.write_register(3, value),
+      R_NR34 => self.channels.2.write_register(4, value),
       R_NR41 => self.channels.3.write_register(1, value),
       R_NR42 => self.channels.3.write_register(2, value),
       R_NR43 => self.channels.3.write_register(3, value),
       R_NR44 => self.channels.3.write_register(4, value),
       R_NR50 => {
         self.terminals.0.volume = value & 0x07;
+        self.terminals.0.vin = value & 0x08 != 0;
         self.terminals.1.volume = (value >> 4) & 0x07;
+        self.terminals.1.vin = value & 0x80 != 0;
       },
       R_NR51 => {
         //these were supposed to be used for this right?
@@ -214,14 +435,38 @@ impl Apu {
         //println!("TERMINALS: {:?}", self.terminals);
       }
       R_NR52 => {
-        self.enabled = (value & 0x80) != 0;
-        //TODO when/if disabled, clear registers
+        let enabled = (value & 0x80) != 0;
+        if self.enabled && !enabled {
+          self.power_off();
+        } else if !self.enabled && enabled {
+          //Next frame sequencer step will be step 0
+          self.sequencer = 7;
+        }
+        self.enabled = enabled;
       },
+      0xff30..=0xff3f => self.channels.2.write_wave_ram(addr, value),
       _ => ()
     }
   }
 }
 
+///Frontend-controlled settings, kept across resets
+#[derive(Clone, Copy)]
+pub(crate) struct ApuSettings {
+  muted: [bool; 4],
+  solo: [bool; 4],
+  filter: AudioFilter,
+  sample_rate: usize,
+  chunk_size: usize,
+}
+
+///Keep up to a second of audio (or two chunks, whichever is larger) for `drain_samples`
+fn buffer_capacity(sample_rate: usize, chunk_size: usize) -> usize {
+  (sample_rate * 2).max(chunk_size * 2)
+}
+
 impl Default for Apu {
   fn default() -> Self { Self::new() }
 }
+
+impl_save_state!(Apu { enabled, channels, terminals, sequencer, sample_cycles, prev_div } validate validate_state);
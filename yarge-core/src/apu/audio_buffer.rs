@@ -1,42 +1,48 @@
-use crate::consts::AUDIO_BUFFER_SIZE;
+use std::collections::VecDeque;
 
-#[derive(Clone)]
+///Ring buffer of interleaved stereo samples (left, right), the oldest samples are dropped once it's full
 pub struct AudioBuffer {
-  buffer: Box<[f32; AUDIO_BUFFER_SIZE]>,
-  head: usize
+  buffer: VecDeque<f32>,
+  capacity: usize,
 }
 
 impl AudioBuffer {
-  pub fn new() -> Self {
+  pub fn new(capacity: usize) -> Self {
     Self {
-      buffer: Box::new([0.; AUDIO_BUFFER_SIZE]),
-      head: 0
+      buffer: VecDeque::with_capacity(capacity),
+      capacity,
     }
   }
 
   pub fn push(&mut self, l_sample: f32, r_sample: f32) {
-    assert!(!self.is_full(), "AudioBuffer is full");
-    self.buffer[self.head] = l_sample;
-    self.buffer[self.head + 1] = r_sample;
-    self.head += 2;
-  }
-  
-  pub fn reset(&mut self) {
-    self.head = 0;
+    if self.buffer.len() + 2 > self.capacity {
+      self.buffer.drain(..2);
+    }
+    self.buffer.push_back(l_sample);
+    self.buffer.push_back(r_sample);
   }
 
-  pub fn len(&self) -> usize {
-    self.head
+  ///Move samples to `out` (oldest first), only whole left/right pairs are moved.
+  ///Returns the number of values written
+  pub fn drain(&mut self, out: &mut [f32]) -> usize {
+    let len = self.buffer.len().min(out.len()) & !1;
+    for (value, sample) in out.iter_mut().zip(self.buffer.drain(..len)) {
+      *value = sample;
+    }
+    len
   }
 
-  pub fn is_full(&self) -> bool {
-    self.head >= AUDIO_BUFFER_SIZE
+  pub fn set_capacity(&mut self, capacity: usize) {
+    self.capacity = capacity;
+    let excess = self.buffer.len().saturating_sub(capacity) & !1;
+    self.buffer.drain(..excess);
   }
-  pub fn get_buffer(&self) -> &[f32; AUDIO_BUFFER_SIZE] {
-    self.buffer.as_ref()
+
+  pub fn clear(&mut self) {
+    self.buffer.clear();
   }
-}
 
-impl Default for AudioBuffer {
-  fn default() -> Self { Self::new() }
+  pub fn len(&self) -> usize {
+    self.buffer.len()
+  }
 }
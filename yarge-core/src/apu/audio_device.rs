@@ -1,16 +1,8 @@
-use crate::consts::AUDIO_BUFFER_SIZE;
-
+///Push-based audio output, built on top of the internal sample buffer:
+///`queue_samples` gets called with `chunk_size` interleaved stereo samples (left, right)
+///every time that many are available (see `Gameboy::set_audio_chunk_size`).
+///
+///Samples passed to the device are no longer available to `Gameboy::drain_audio`
 pub trait AudioDevice {
-  fn queue_samples(&mut self, buffer: &[f32; AUDIO_BUFFER_SIZE]);
+  fn queue_samples(&mut self, buffer: &[f32]);
 }
-
-// trait QueueIfPresent {
-//   fn queue_if_present(&mut self, buffer: &[f32; AUDIO_BUFFER_SIZE]);
-// }
-// impl QueueIfPresent for Option<Box<dyn AudioDevice>> {
-//   fn queue_if_present(&mut self, buffer: &[f32; AUDIO_BUFFER_SIZE]) {
-//     if let Some(device) = self {
-//       device.queue_samples(buffer);
-//     }
-//   }
-// }
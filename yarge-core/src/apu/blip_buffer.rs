@@ -0,0 +1,90 @@
+//! Band-limited step synthesis, based on the same idea as blargg's Blip_Buffer
+//!
+//! Instead of point-sampling the output, every change of the output level is added to the buffer
+//! as a band-limited step (integrated windowed sinc) at the exact time it happens.\
+//! Deltas are stored as integers, so the integrator never drifts
+
+use std::f64::consts::PI;
+
+///Number of sub-sample positions a step can start at
+pub const PHASES: usize = 32;
+///Kernel width in samples, output is delayed by `WIDTH / 2 - 1` samples
+const WIDTH: usize = 16;
+const BUFFER_SIZE: usize = 32;
+///Kernel taps of every phase add up to exactly this value
+const KERNEL_UNIT: i64 = 1 << 15;
+///Output levels are quantized to `1 / LEVEL_UNIT`
+const LEVEL_UNIT: f32 = 65536.;
+///Cutoff frequency, relative to the sample rate (0.5 is Nyquist)
+const CUTOFF: f64 = 0.45;
+
+fn make_kernel() -> Box<[[i32; WIDTH]; PHASES]> {
+  let mut kernel = Box::new([[0; WIDTH]; PHASES]);
+  for (phase, taps) in kernel.iter_mut().enumerate() {
+    let center = (WIDTH / 2 - 1) as f64 + phase as f64 / PHASES as f64;
+    let mut impulse = [0.; WIDTH];
+    for (k, value) in impulse.iter_mut().enumerate() {
+      let x = k as f64 - center;
+      let sinc = match x == 0. {
+        true => 1.,
+        false => (2. * PI * CUTOFF * x).sin() / (2. * PI * CUTOFF * x),
+      };
+      //Blackman window
+      let t = 2. * PI * x / WIDTH as f64;
+      *value = sinc * (0.42 + 0.5 * t.cos() + 0.08 * (2. * t).cos());
+    }
+    let sum: f64 = impulse.iter().sum();
+    for (tap, value) in taps.iter_mut().zip(impulse) {
+      *tap = (value / sum * KERNEL_UNIT as f64).round() as i32;
+    }
+    //Put the rounding error into the center tap, so that steps are exact
+    let error = KERNEL_UNIT - taps.iter().map(|&x| x as i64).sum::<i64>();
+    taps[WIDTH / 2 - 1] += error as i32;
+  }
+  kernel
+}
+
+pub struct BlipBuffer {
+  kernel: Box<[[i32; WIDTH]; PHASES]>,
+  buffer: [i64; BUFFER_SIZE],
+  head: usize,
+  accum: i64,
+  level: i32,
+}
+
+impl BlipBuffer {
+  pub fn new() -> Self {
+    Self {
+      kernel: make_kernel(),
+      buffer: [0; BUFFER_SIZE],
+      head: 0,
+      accum: 0,
+      level: 0,
+    }
+  }
+
+  ///Change the output level, `phase` is the position within the current sample (`0..PHASES`)
+  #[inline]
+  pub fn set_level(&mut self, phase: usize, amplitude: f32) {
+    let level = (amplitude * LEVEL_UNIT) as i32;
+    let delta = (level - self.level) as i64;
+    if delta == 0 {
+      return
+    }
+    self.level = level;
+    for (k, &tap) in self.kernel[phase].iter().enumerate() {
+      self.buffer[(self.head + k) & (BUFFER_SIZE - 1)] += delta * tap as i64;
+    }
+  }
+
+  ///Output the current sample and move on to the next one
+  pub fn read_sample(&mut self) -> f32 {
+    self.accum += std::mem::take(&mut self.buffer[self.head]);
+    self.head = (self.head + 1) & (BUFFER_SIZE - 1);
+    self.accum as f32 / (LEVEL_UNIT * KERNEL_UNIT as f32)
+  }
+}
+
+impl Default for BlipBuffer {
+  fn default() -> Self { Self::new() }
+}
@@ -0,0 +1,39 @@
+use crate::consts::CHANNEL_BUFFER_SIZE;
+
+///Ring buffer of raw (unmixed) channel amplitudes, recorded at the audio sample rate
+pub struct ChannelBuffer {
+  buffer: Box<[[f32; 4]; CHANNEL_BUFFER_SIZE]>,
+  head: usize,
+}
+
+impl ChannelBuffer {
+  pub fn new() -> Self {
+    Self {
+      buffer: Box::new([[0.; 4]; CHANNEL_BUFFER_SIZE]),
+      head: 0,
+    }
+  }
+
+  pub fn push(&mut self, amplitudes: [f32; 4]) {
+    self.buffer[self.head] = amplitudes;
+    self.head = (self.head + 1) % CHANNEL_BUFFER_SIZE;
+  }
+
+  ///Copy the most recent samples of `channel` to `out` (oldest first),
+  ///returns the number of samples copied
+  pub fn copy_samples(&self, channel: usize, out: &mut [f32]) -> usize {
+    if channel > 3 {
+      return 0
+    }
+    let len = out.len().min(CHANNEL_BUFFER_SIZE);
+    let start = self.head + CHANNEL_BUFFER_SIZE - len;
+    for (i, sample) in out[..len].iter_mut().enumerate() {
+      *sample = self.buffer[(start + i) % CHANNEL_BUFFER_SIZE][channel];
+    }
+    len
+  }
+}
+
+impl Default for ChannelBuffer {
+  fn default() -> Self { Self::new() }
+}
@@ -7,7 +7,11 @@ pub trait ApuChannel {
   fn read_register(&self, _reg: u8) -> u8 { 0 }
   fn write_register(&mut self, _reg: u8, _value: u8) {}
   fn is_enabled(&self) -> bool;
+  fn is_dac_enabled(&self) -> bool;
+  ///Reset all registers except the length timer
+  fn power_off(&mut self);
 }
 
 pub mod square;
+pub mod wave;
 pub mod noise;
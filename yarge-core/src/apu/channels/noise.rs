@@ -1,5 +1,8 @@
 use super::ApuChannel;
 use crate::apu::common::{envelope::Envelope, length::LengthTimer};
+use crate::Res;
+use crate::state::{impl_save_state, check_state};
+use crate::io_registers::NoiseChannelState;
 
 pub struct NoiseChannel {
   envelope: Envelope,
@@ -26,17 +29,35 @@ impl NoiseChannel {
     }
   }
 
+  pub fn snapshot(&self) -> NoiseChannelState {
+    NoiseChannelState {
+      enabled: self.channel_enabled,
+      dac_enabled: self.envelope.dac_enabled(),
+      shift: self.shift,
+      width_short: self.width_short,
+      divider: self.divider,
+      freq_timer: self.freq_timer,
+      lfsr: self.lfsr,
+      length: self.length.snapshot(),
+      envelope: self.envelope.snapshot(),
+    }
+  }
+
   pub fn reset_freq_timer(&mut self) {
     let div_value = if self.divider > 0  { self.divider << 4 } else { 8 };
     self.freq_timer = (div_value as u16) << self.shift;
   }
 
   pub fn trigger(&mut self) {
-    self.channel_enabled = true;
+    self.channel_enabled = self.envelope.dac_enabled();
     self.reset_freq_timer();
     self.envelope.trigger();
     self.length.trigger();
   }
+
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.shift <= 15 && self.divider <= 7, "invalid noise channel frequency")
+  }
 }
 
 impl ApuChannel for NoiseChannel {
@@ -83,9 +104,17 @@ impl ApuChannel for NoiseChannel {
     ((wf << 1) as i32 - 1) as f32 * self.envelope.volume_f32()
   }
 
-  fn read_register(&self, _reg: u8) -> u8 {
-    //TODO
-    0
+  fn read_register(&self, reg: u8) -> u8 {
+    match reg {
+      2 => self.envelope.to_mmio(),
+      3 => {
+        (self.shift << 4) |
+        ((self.width_short as u8) << 3) |
+        self.divider
+      },
+      4 => (self.length.enable as u8) << 6,
+      _ => 0
+    }
   }
 
   fn write_register(&mut self, reg: u8, value: u8) {
@@ -95,6 +124,9 @@ impl ApuChannel for NoiseChannel {
       }
       2 => {
         self.envelope.set_from_mmio(value);
+        if !self.envelope.dac_enabled() {
+          self.channel_enabled = false;
+        }
       },
       3 => {
         self.divider = value & 0b111;
@@ -114,6 +146,16 @@ impl ApuChannel for NoiseChannel {
   fn is_enabled(&self) -> bool {
     self.channel_enabled
   }
+
+  fn is_dac_enabled(&self) -> bool {
+    self.envelope.dac_enabled()
+  }
+
+  fn power_off(&mut self) {
+    let timer = self.length.timer;
+    *self = Self::new();
+    self.length.timer = timer;
+  }
 }
 
 impl Default for NoiseChannel {
@@ -121,3 +163,10 @@ impl Default for NoiseChannel {
     Self::new()
   }
 }
+
+impl_save_state!(NoiseChannel {
+  envelope, length,
+  shift, width_short, divider,
+  freq_timer, lfsr,
+  channel_enabled,
+} validate validate_state);
@@ -1,11 +1,15 @@
 use super::ApuChannel;
-use crate::apu::common::{length::LengthTimer, envelope::Envelope};
+use crate::apu::common::{length::LengthTimer, envelope::Envelope, sweep::Sweep};
+use crate::Res;
+use crate::state::{impl_save_state, check_state};
+use crate::io_registers::SquareChannelState;
 
 mod wave;
 use wave::WaveDuty;
 
 pub struct SquareWaveChannel<const HAS_SWEEP: bool> {
   wave_duty: WaveDuty,
+  sweep: Sweep,
   length: LengthTimer,
   envelope: Envelope,
   ///a.k.a wavelength
@@ -22,6 +26,7 @@ impl<const HAS_SWEEP: bool> SquareWaveChannel<HAS_SWEEP> {
       envelope: Envelope::new(),
       length: LengthTimer::new(),
       wave_duty: WaveDuty::new(),
+      sweep: Sweep::new(),
       freq_timer: 8192, //or 0?
       frequency: 0,
       //dac_enabled: true,
@@ -33,12 +38,30 @@ impl<const HAS_SWEEP: bool> SquareWaveChannel<HAS_SWEEP> {
     self.freq_timer = (2048 - self.frequency) * 4;
   }
 
+  pub fn snapshot(&self) -> SquareChannelState {
+    SquareChannelState {
+      enabled: self.channel_enabled,
+      dac_enabled: self.envelope.dac_enabled(),
+      duty: self.wave_duty.get_pattern_type() as u8,
+      duty_position: self.wave_duty.get_position(),
+      frequency: self.frequency,
+      freq_timer: self.freq_timer,
+      length: self.length.snapshot(),
+      envelope: self.envelope.snapshot(),
+      sweep: HAS_SWEEP.then(|| self.sweep.snapshot()),
+    }
+  }
+
   fn trigger(&mut self) {
     self.reset_freq_timer();
-    self.channel_enabled = true;
+    self.channel_enabled = self.envelope.dac_enabled();
     self.envelope.trigger();
     self.length.trigger();
   }
+
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.frequency <= 0x7FF, "invalid square channel frequency")
+  }
 }
 
 impl<const HAS_SWEEP: bool> ApuChannel for SquareWaveChannel<HAS_SWEEP> {
@@ -77,20 +100,19 @@ impl<const HAS_SWEEP: bool> ApuChannel for SquareWaveChannel<HAS_SWEEP> {
 
   fn read_register(&self, reg: u8) -> u8 {
     match reg {
-      2 => {
-        self.envelope.to_mmio()
-      },
-      _ => {
-        //TODO
-        0
-      }
+      0 if HAS_SWEEP => self.sweep.to_mmio(),
+      1 => (self.wave_duty.get_pattern_type() as u8) << 6,
+      2 => self.envelope.to_mmio(),
+      4 => (self.length.enable as u8) << 6,
+      _ => 0
     }
   }
 
   fn write_register(&mut self, reg: u8, value: u8) {
     match reg {
       0 if HAS_SWEEP => {
-        //TODO
+        //TODO actually do the frequency sweep
+        self.sweep.set_from_mmio(value);
       },
       1 => {
         // 0bAABBBBBB;
@@ -101,9 +123,9 @@ impl<const HAS_SWEEP: bool> ApuChannel for SquareWaveChannel<HAS_SWEEP> {
       },
       2 => {
         self.envelope.set_from_mmio(value);
-        // if self.envelope.start_volume == 0 && self.envelope.direction == EnvelopeDirection::Down {
-        //   self.channel_enabled = false;
-        // }
+        if !self.envelope.dac_enabled() {
+          self.channel_enabled = false;
+        }
       },
       3 => {
         self.frequency = (self.frequency & 0x700) | value as u16;
@@ -122,6 +144,16 @@ impl<const HAS_SWEEP: bool> ApuChannel for SquareWaveChannel<HAS_SWEEP> {
   fn is_enabled(&self) -> bool {
     self.channel_enabled
   }
+
+  fn is_dac_enabled(&self) -> bool {
+    self.envelope.dac_enabled()
+  }
+
+  fn power_off(&mut self) {
+    let timer = self.length.timer;
+    *self = Self::new();
+    self.length.timer = timer;
+  }
 }
 
 impl<const HAS_SWEEP: bool> Default for SquareWaveChannel<HAS_SWEEP> {
@@ -129,3 +161,9 @@ impl<const HAS_SWEEP: bool> Default for SquareWaveChannel<HAS_SWEEP> {
     Self::new()
   }
 }
+
+impl_save_state!([const HAS_SWEEP: bool] SquareWaveChannel<HAS_SWEEP> {
+  wave_duty, sweep, length, envelope,
+  frequency, freq_timer,
+  channel_enabled,
+} validate validate_state);
@@ -1,3 +1,6 @@
+use crate::Res;
+use crate::state::{impl_save_state, check_state};
+
 const WAVE_DUTY_PATTERNS: [u8; 4] = [
   0b00000001, // 12.5%
   0b00000011, // 25%
@@ -29,10 +32,18 @@ impl WaveDuty {
     self.pattern_type = pattern_type;
     self.pattern = WAVE_DUTY_PATTERNS[pattern_type & 0b11];
   }
+  pub fn get_position(&self) -> u8 {
+    self.position as u8
+  }
   pub fn get_pattern_type(&self) -> usize {
     self.pattern_type
   }
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.pattern_type < 4 && self.position < 8, "invalid wave duty")
+  }
 }
 impl Default for WaveDuty {
   fn default() -> Self { Self::new() }
 }
+
+impl_save_state!(WaveDuty { pattern_type, pattern, position } validate validate_state);
@@ -0,0 +1,175 @@
+use super::ApuChannel;
+use crate::apu::common::length::LengthTimer;
+use crate::Res;
+use crate::state::{impl_save_state, check_state};
+use crate::io_registers::WaveChannelState;
+
+pub struct WaveChannel {
+  length: LengthTimer<256>,
+  wave_ram: [u8; 16],
+  ///Current sample index (0-31), each byte of wave ram holds two samples
+  position: u8,
+  ///Last sample read from wave ram
+  sample: u8,
+  ///NR32 output level (0-3)
+  output_level: u8,
+  ///a.k.a wavelength
+  frequency: u16,
+  freq_timer: u16,
+  dac_enabled: bool,
+  channel_enabled: bool,
+}
+
+impl WaveChannel {
+  pub fn new() -> Self {
+    Self {
+      length: LengthTimer::new(),
+      wave_ram: [0; 16],
+      position: 0,
+      sample: 0,
+      output_level: 0,
+      frequency: 0,
+      freq_timer: 0,
+      dac_enabled: false,
+      channel_enabled: false,
+    }
+  }
+
+  fn reset_freq_timer(&mut self) {
+    self.freq_timer = (2048 - self.frequency) * 2;
+  }
+
+  fn trigger(&mut self) {
+    self.reset_freq_timer();
+    self.position = 0;
+    self.channel_enabled = self.dac_enabled;
+    self.length.trigger();
+  }
+
+  pub fn snapshot(&self) -> WaveChannelState {
+    WaveChannelState {
+      enabled: self.channel_enabled,
+      dac_enabled: self.dac_enabled,
+      output_level: self.output_level,
+      position: self.position,
+      sample: self.sample,
+      frequency: self.frequency,
+      freq_timer: self.freq_timer,
+      length: self.length.snapshot(),
+      wave_ram: self.wave_ram,
+    }
+  }
+
+  pub fn read_wave_ram(&self, addr: u16) -> u8 {
+    self.wave_ram[(addr & 0xF) as usize]
+  }
+
+  pub fn write_wave_ram(&mut self, addr: u16, value: u8) {
+    self.wave_ram[(addr & 0xF) as usize] = value;
+  }
+
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.position <= 31 && self.output_level <= 3, "invalid wave channel state")?;
+    check_state(self.frequency <= 0x7FF, "invalid wave channel frequency")
+  }
+}
+
+impl ApuChannel for WaveChannel {
+  fn tick_length(&mut self) {
+    if !self.channel_enabled { return }
+    if self.length.tick() {
+      self.channel_enabled = false;
+    }
+  }
+
+  fn tick(&mut self) {
+    if !self.channel_enabled { return }
+
+    if self.freq_timer > 0 {
+      self.freq_timer -= 1;
+      if self.freq_timer == 0 {
+        self.reset_freq_timer();
+        self.position = (self.position + 1) & 31;
+        //High nibble is played first
+        let byte = self.wave_ram[(self.position >> 1) as usize];
+        self.sample = if self.position & 1 == 0 { byte >> 4 } else { byte & 0xF };
+      }
+    }
+  }
+
+  fn amplitude(&self) -> f32 {
+    if !self.channel_enabled {
+      return 0.
+    }
+    // 0 => mute, 1 => 100%, 2 => 50%, 3 => 25%
+    const VOLUME_LUT: [f32; 4] = [0., 1., 0.5, 0.25];
+    //0x0 => -1.f, 0xF => 1.f
+    ((self.sample as f32 / 7.5) - 1.) * VOLUME_LUT[self.output_level as usize]
+  }
+
+  fn read_register(&self, reg: u8) -> u8 {
+    match reg {
+      0 => (self.dac_enabled as u8) << 7,
+      2 => self.output_level << 5,
+      4 => (self.length.enable as u8) << 6,
+      _ => 0
+    }
+  }
+
+  fn write_register(&mut self, reg: u8, value: u8) {
+    match reg {
+      0 => {
+        self.dac_enabled = value & 0x80 != 0;
+        if !self.dac_enabled {
+          self.channel_enabled = false;
+        }
+      },
+      1 => {
+        self.length.set_from_inv(value);
+      },
+      2 => {
+        self.output_level = (value >> 5) & 0b11;
+      },
+      3 => {
+        self.frequency = (self.frequency & 0x700) | value as u16;
+      },
+      4 => {
+        self.frequency = (self.frequency & 0xff) | ((value as u16 & 0b111) << 8);
+        self.length.enable = value & (1 << 6) != 0;
+        if value & 0x80 != 0 {
+          self.trigger();
+        }
+      },
+      _ => ()
+    }
+  }
+
+  fn is_enabled(&self) -> bool {
+    self.channel_enabled
+  }
+
+  fn is_dac_enabled(&self) -> bool {
+    self.dac_enabled
+  }
+
+  fn power_off(&mut self) {
+    let timer = self.length.timer;
+    let wave_ram = self.wave_ram;
+    *self = Self::new();
+    self.length.timer = timer;
+    self.wave_ram = wave_ram;
+  }
+}
+
+impl Default for WaveChannel {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl_save_state!(WaveChannel {
+  length, wave_ram,
+  position, sample, output_level,
+  frequency, freq_timer,
+  dac_enabled, channel_enabled,
+} validate validate_state);
@@ -1,3 +1,6 @@
+use crate::state::{impl_save_state, impl_save_state_enum};
+use crate::io_registers::EnvelopeState;
+
 #[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
 pub enum EnvelopeDirection {
   #[default] 
@@ -34,6 +37,11 @@ impl Envelope {
     self.start_volume = value >> 4;
   }
 
+  ///DAC is disabled if the upper 5 bits of NRx2 are all zero
+  pub fn dac_enabled(&self) -> bool {
+    self.to_mmio() & 0xf8 != 0
+  }
+
   pub fn trigger(&mut self) {
     self.curent_volume = self.start_volume;
     self.period_timer = self.period;
@@ -48,7 +56,16 @@ impl Envelope {
   /// Volume in 0.0-1.0 range
   #[inline(always)]
   pub fn volume_f32(&self) -> f32 {
-    self.curent_volume as f32 / 15.
+    self.volume() as f32 / 15.
+  }
+
+  pub fn snapshot(&self) -> EnvelopeState {
+    EnvelopeState {
+      start_volume: self.start_volume,
+      increase: self.direction == EnvelopeDirection::Up,
+      period: self.period,
+      volume: self.curent_volume,
+    }
   }
 
   pub fn tick(&mut self) {
@@ -72,3 +89,6 @@ impl Envelope {
     }
   }
 }
+
+impl_save_state_enum!(EnvelopeDirection { Down, Up });
+impl_save_state!(Envelope { start_volume, period, direction, period_timer, curent_volume });
@@ -1,16 +1,20 @@
+use crate::state::impl_save_state;
+use crate::io_registers::LengthState;
+
+/// Length timer, `MAX` is 64 for all channels except CH3 (256)
 #[derive(Default)]
-pub struct LengthTimer {
-  pub timer: u8,
+pub struct LengthTimer<const MAX: u16 = 64> {
+  pub timer: u16,
   pub enable: bool,
 }
 
-impl LengthTimer {
+impl<const MAX: u16> LengthTimer<MAX> {
   pub fn new() -> Self {
     Self::default()
   }
 
   pub fn set_from_inv(&mut self, value: u8) {
-    self.timer = 64 - (value & 0x3f);
+    self.timer = MAX - (value as u16 & (MAX - 1));
   }
 
   ///Returns true if the channel needs to be disabled
@@ -23,11 +27,16 @@ impl LengthTimer {
     }
   }
 
+  pub fn snapshot(&self) -> LengthState {
+    LengthState { timer: self.timer, enable: self.enable }
+  }
   pub fn trigger(&mut self) {
     //XXX: Should this ALWAYS set to 64?
     //self.length_timer = 64;
     if self.timer == 0 {
-      self.timer = 64;
+      self.timer = MAX;
     }
   }
 }
+
+impl_save_state!([const MAX: u16] LengthTimer<MAX> { timer, enable });
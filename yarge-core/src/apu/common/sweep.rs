@@ -0,0 +1,32 @@
+use crate::state::impl_save_state;
+use crate::io_registers::SweepState;
+
+#[derive(Default)]
+pub struct Sweep {
+  pub period: u8,
+  pub negate: bool,
+  pub shift: u8,
+}
+
+impl Sweep {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn to_mmio(&self) -> u8 {
+    (self.period << 4) |
+    ((self.negate as u8) << 3) |
+    self.shift
+  }
+
+  pub fn snapshot(&self) -> SweepState {
+    SweepState { period: self.period, negate: self.negate, shift: self.shift }
+  }
+  pub fn set_from_mmio(&mut self, value: u8) {
+    self.period = (value >> 4) & 0x7;
+    self.negate = value & (1 << 3) != 0;
+    self.shift = value & 0x7;
+  }
+}
+
+impl_save_state!(Sweep { period, negate, shift });
@@ -0,0 +1,62 @@
+use crate::{Model, consts::CPU_CLOCK_SPEED};
+
+///Output high-pass filter, models the capacitor that removes the DC offset from the DAC output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum AudioFilter {
+  ///DMG and SGB
+  #[default]
+  Dmg,
+  ///MGB, SGB2 and CGB (capacitor charges faster)
+  Cgb,
+  ///No filtering (DC offset is kept), useful for testing
+  Raw,
+}
+impl AudioFilter {
+  pub fn for_model(model: Model) -> Self {
+    match model {
+      Model::Dmg0 | Model::Dmg | Model::Sgb => Self::Dmg,
+      Model::Mgb | Model::Sgb2 | Model::Cgb => Self::Cgb,
+    }
+  }
+
+  ///Capacitor charge factor per T-cycle
+  fn charge_factor(self) -> Option<f64> {
+    match self {
+      Self::Dmg => Some(0.999958),
+      Self::Cgb => Some(0.998943),
+      Self::Raw => None,
+    }
+  }
+}
+
+pub struct HighPass {
+  capacitor: f32,
+  ///Charge factor per output sample, `None` if filtering is disabled
+  charge: Option<f32>,
+}
+
+impl HighPass {
+  pub fn new(filter: AudioFilter, sample_rate: usize) -> Self {
+    Self {
+      capacitor: 0.,
+      charge: filter.charge_factor().map(|x| {
+        x.powf(CPU_CLOCK_SPEED as f64 / sample_rate as f64) as f32
+      }),
+    }
+  }
+
+  ///While all DACs are disabled, the output is silent and the capacitor keeps its charge
+  #[inline]
+  pub fn apply(&mut self, input: f32, dac_enabled: bool) -> f32 {
+    let charge = match self.charge {
+      Some(charge) => charge,
+      None => return input,
+    };
+    if !dac_enabled {
+      return 0.
+    }
+    let output = input - self.capacitor;
+    self.capacitor = input - output * charge;
+    output
+  }
+}
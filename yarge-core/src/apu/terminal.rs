@@ -1,6 +1,9 @@
+use crate::state::impl_save_state;
+use crate::io_registers::TerminalState;
+
 #[derive(Default, Clone, Copy, Debug)]
 pub struct Terminal {
-  //pub vin: bool,
+  pub vin: bool,
   pub volume: u8,
   pub enabled_channels: (bool, bool, bool, bool),
 }
@@ -8,11 +11,21 @@ pub struct Terminal {
 impl Terminal {
   pub fn new() -> Self {
     Self {
+      vin: false,
       volume: 7,
       enabled_channels: (true, true, true, true),
     }
   }
 
+  pub fn snapshot(&self) -> TerminalState {
+    let (ch1, ch2, ch3, ch4) = self.enabled_channels;
+    TerminalState {
+      volume: self.volume,
+      vin: self.vin,
+      channels: [ch1, ch2, ch3, ch4],
+    }
+  }
+
   /// This is ridicuosly over-optimized but this greatly improves the generated assembly
   /// mixes channels together (averages them) with an option to 
   /// disable individual channels (Self.enabled_channels)
@@ -45,3 +58,5 @@ impl Terminal {
     volume * amplitude
   }
 }
+
+impl_save_state!(Terminal { vin, volume, enabled_channels });
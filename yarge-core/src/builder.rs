@@ -0,0 +1,39 @@
+use crate::{Gameboy, Model, Res, YargeError, AudioFilter};
+
+///Builder for [`Gameboy`]
+#[derive(Default)]
+pub struct GameboyBuilder {
+  model: Model,
+  boot_rom: Option<Vec<u8>>,
+}
+impl GameboyBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  ///Hardware model to emulate (DMG by default)
+  pub fn model(mut self, model: Model) -> Self {
+    self.model = model;
+    self
+  }
+
+  ///Use a boot ROM image instead of the built-in one,
+  ///the size must match the model (see [`Model::boot_rom_size`])
+  pub fn boot_rom(mut self, data: Vec<u8>) -> Self {
+    self.boot_rom = Some(data);
+    self
+  }
+
+  pub fn build(self) -> Res<Gameboy> {
+    if let Some(boot_rom) = self.boot_rom.as_ref() {
+      if boot_rom.len() != self.model.boot_rom_size() {
+        return Err(YargeError::InvalidBootRomSize(boot_rom.len()));
+      }
+    }
+    let mut gb = Gameboy::new();
+    gb.cpu.bus.model = self.model;
+    gb.cpu.bus.apu.set_filter(AudioFilter::for_model(self.model));
+    gb.cpu.bus.boot_rom = self.boot_rom.map(|data| data.into_boxed_slice());
+    Ok(gb)
+  }
+}
@@ -1,17 +1,36 @@
-use crate::{Input, Timers, Ppu, Apu, Res, consts::BIOS, serial::Serial};
+use crate::{Input, Timers, Ppu, Apu, Sgb, Res, Model, consts::BIOS, apu::AudioFilter, serial::Serial, state::{impl_save_state, check_state}};
+use crate::event::{Event, EventRecorder};
 use std::fs;
 pub mod cartridge;
+mod vram_dma;
+#[cfg(feature = "dbg-breakpoints")]
+pub mod watchpoints;
 use cartridge::{CartridgeImpl as _, RomHeader, Cartridge, MockCartridge};
+use vram_dma::VramDma;
+#[cfg(feature = "dbg-breakpoints")]
+use watchpoints::Watchpoints;
 
 pub struct MemBus {
   pub bios_disabled: bool,
+  ///User-supplied boot ROM image (the built-in one is used if `None`)
+  pub boot_rom: Option<Box<[u8]>>,
   pub cart: Cartridge,
   cart_header: RomHeader,
-  wram: Box<[u8; 0x2000]>,
+  pub model: Model,
+  pub cgb_mode: bool,
+  pub sgb_mode: bool,
+  wram: Box<[u8; 0x8000]>,
+  wram_bank: u8,
   hram: [u8; 0x007F],
+  //speed switch (CGB ONLY)
+  pub double_speed: bool,
+  speed_switch: bool,
+  half_cycle: bool,
   //oam dma
   oam_value: u8,
   oam_transfer: u8,
+  //vram dma (CGB ONLY)
+  pub vram_dma: VramDma,
   //interrupts
   pub iie: u8,
   pub iif: u8,
@@ -21,27 +40,54 @@ pub struct MemBus {
   pub timers: Timers,
   pub input: Input,
   pub serial: Serial,
+  pub sgb: Sgb,
+  //event recording
+  events: Option<EventRecorder>,
+  cycles: u64,
+  //memory watchpoints
+  #[cfg(feature = "dbg-breakpoints")]
+  pub watchpoints: Watchpoints,
 }
 impl MemBus {
-  pub fn new() -> Self {
+  pub fn new(model: Model) -> Self {
     Self {
       bios_disabled: false,
+      boot_rom: None,
       cart: MockCartridge.into(),
       cart_header: RomHeader::default(),
-      wram: Box::new([0; 0x2000]),
+      model,
+      cgb_mode: false,
+      sgb_mode: false,
+      wram: Box::new([0; 0x8000]),
+      wram_bank: 0,
       hram: [0; 0x7F],
+      //speed switch
+      double_speed: false,
+      speed_switch: false,
+      half_cycle: false,
       //oam dma
       oam_value: 0,
       oam_transfer: 0,
+      //vram dma
+      vram_dma: VramDma::new(),
       //interrupts
       iie: 0x00,
       iif: 0x00,
       //components
       ppu: Ppu::new(),
-      apu: Apu::new(),
+      apu: {
+        let mut apu = Apu::new();
+        apu.set_filter(AudioFilter::for_model(model));
+        apu
+      },
       timers: Timers::new(),
       input: Input::new(),
       serial: Serial::new(),
+      sgb: Sgb::new(),
+      events: None,
+      cycles: 0,
+      #[cfg(feature = "dbg-breakpoints")]
+      watchpoints: Watchpoints::default(),
     }
   }
 
@@ -49,9 +95,12 @@ impl MemBus {
     if blocking && self.check_oam_dma_block(addr) {
       return 0xFF
     }
-    match addr {
+    let value = match addr {
       //BOOTROM/ROM
-      0x0000..=0x00ff if !self.bios_disabled => BIOS[addr as usize],
+      //(CGB boot ROM is split into two parts, with the cartridge header mapped in between)
+      0x0000..=0x00FF | 0x0200..=0x08FF if !self.bios_disabled && ((addr as usize) < self.get_boot_rom().len()) => {
+        self.get_boot_rom()[addr as usize]
+      },
       //ROM
       0x0000..=0x7fff => self.cart.read_rom(addr),
       //VRAM
@@ -59,7 +108,7 @@ impl MemBus {
       //ERAM
       0xA000..=0xBFFF => self.cart.read_eram(addr, blocking),
       //WRAM/ECHO
-      0xC000..=0xFDFF => self.wram[(addr & 0x1FFF) as usize],
+      0xC000..=0xFDFF => self.wram[self.wram_addr(addr)],
       //OAM
       0xFE00..=0xFE9F => self.ppu.read_oam(addr, blocking),
       //IO REGISTERS
@@ -71,7 +120,7 @@ impl MemBus {
         0xFF05 => self.timers.read_tima(),
         0xFF06 => self.timers.tma,
         0xFF07 => self.timers.read_tac(),
-        0xFF0F => self.iif,
+        0xFF0F => 0xE0 | self.iif,
         //0xFF10..=0xFF26 => self.tmp_apu_reg[addr as usize - 0xFF10],
         0xFF10..=0xFF3F => self.apu.read(addr),
         0xFF40 => self.ppu.get_lcdc(), //LCDC
@@ -89,7 +138,17 @@ impl MemBus {
         0xFF49 => self.ppu.obp.1,
         0xFF4A => self.ppu.wy,
         0xFF4B => self.ppu.wx,
+        0xFF4D if self.cgb_mode => {
+          0x7E | ((self.double_speed as u8) << 7) | (self.speed_switch as u8)
+        },
+        0xFF4F if self.cgb_mode => self.ppu.get_vbk(),
         0xFF50 => 0xFE | (self.bios_disabled as u8),
+        0xFF55 if self.cgb_mode => self.vram_dma.read_hdma5(),
+        0xFF68 if self.cgb_mode => self.ppu.get_bcps(),
+        0xFF69 if self.cgb_mode => self.ppu.read_bcpd(blocking),
+        0xFF6A if self.cgb_mode => self.ppu.get_ocps(),
+        0xFF6B if self.cgb_mode => self.ppu.read_ocpd(blocking),
+        0xFF70 if self.cgb_mode => 0xF8 | self.wram_bank,
         _ => 0xFF
       },
       //HRAM
@@ -98,23 +157,46 @@ impl MemBus {
       },
       0xFFFF => self.iie,
       _ => 0xFF
+    };
+    #[cfg(feature = "dbg-breakpoints")]
+    if blocking {
+      self.watchpoints.check(addr, value, false);
     }
+    value
   }
   
   pub fn wb(&mut self, addr: u16, value: u8, blocking: bool) {
     if blocking && self.check_oam_dma_block(addr) {
       return
     }
+    #[cfg(feature = "dbg-breakpoints")]
+    if blocking {
+      self.watchpoints.check(addr, value, true);
+    }
     match addr {
       //BOOTROM/ROM
       0x0000..=0x00ff if !self.bios_disabled => {},
-      0x0000..=0x7fff => { self.cart.write_rom(addr, value) },
+      0x0000..=0x7fff => {
+        if self.events.is_some() {
+          let banks = (self.cart.rom_bank(), self.cart.ram_bank());
+          self.cart.write_rom(addr, value);
+          let (rom_bank, ram_bank) = (self.cart.rom_bank(), self.cart.ram_bank());
+          if rom_bank != banks.0 {
+            self.record_event(Event::RomBankSwitch { bank: rom_bank });
+          }
+          if ram_bank != banks.1 {
+            self.record_event(Event::RamBankSwitch { bank: ram_bank });
+          }
+        } else {
+          self.cart.write_rom(addr, value);
+        }
+      },
       //VRAM
       0x8000..=0x9FFF => { self.ppu.write_vram(addr, value, blocking) },
       //ERAM
       0xA000..=0xBFFF => { self.cart.write_eram(addr, value, blocking) },
       //WRAM/ECHO
-      0xC000..=0xFDFF => { self.wram[(addr & 0x1FFF) as usize] = value },
+      0xC000..=0xFDFF => { self.wram[self.wram_addr(addr)] = value },
       //OAM
       0xFE00..=0xFE9F => { self.ppu.write_oam(addr, value, blocking) },
       //IO REGISTERS
@@ -126,7 +208,7 @@ impl MemBus {
         0xFF05 => { self.timers.write_tima(value) },
         0xFF06 => { self.timers.tma = value },
         0xFF07 => { self.timers.write_tac(value) },
-        0xFF0F => { self.iif = value },
+        0xFF0F => { self.iif = value & 0x1F },
         // 0xFF10..=0xFF26 => { self.tmp_apu_reg[addr as usize - 0xFF10] = value; }
         0xFF10..=0xFF3F => { self.apu.write(addr, value, blocking) }
         0xFF40 => { self.ppu.set_lcdc(value) },
@@ -140,7 +222,26 @@ impl MemBus {
         0xFF49 => { self.ppu.obp.1 = value },
         0xFF4A => { self.ppu.wy  = value },
         0xFF4B => { self.ppu.wx  = value },
+        0xFF4D if self.cgb_mode => { self.speed_switch = (value & 1) != 0 },
+        0xFF4F if self.cgb_mode => { self.ppu.set_vbk(value) },
         0xFF50 => { self.bios_disabled = true },
+        0xFF51 if self.cgb_mode => { self.vram_dma.write_src_high(value) },
+        0xFF52 if self.cgb_mode => { self.vram_dma.write_src_low(value) },
+        0xFF53 if self.cgb_mode => { self.vram_dma.write_dst_high(value) },
+        0xFF54 if self.cgb_mode => { self.vram_dma.write_dst_low(value) },
+        0xFF55 if self.cgb_mode => {
+          let was_active = self.vram_dma.is_active();
+          self.vram_dma.write_hdma5(value, self.ppu.in_hblank());
+          if self.vram_dma.is_active() && !(was_active && (value & 0x80) == 0) {
+            let (source, destination, length, hblank) = self.vram_dma.transfer_info();
+            self.record_event(Event::VramDma { source, destination, length, hblank });
+          }
+        },
+        0xFF68 if self.cgb_mode => { self.ppu.set_bcps(value) },
+        0xFF69 if self.cgb_mode => { self.ppu.write_bcpd(value, blocking) },
+        0xFF6A if self.cgb_mode => { self.ppu.set_ocps(value) },
+        0xFF6B if self.cgb_mode => { self.ppu.write_ocpd(value, blocking) },
+        0xFF70 if self.cgb_mode => { self.wram_bank = value & 7 },
         _ => ()
       }
       //HRAM
@@ -162,6 +263,94 @@ impl MemBus {
     self.wb(addr.wrapping_add(1), (value >> 8) as u8, blocking);
   }
   
+  ///Boot ROM image (empty if there's no boot ROM available for the model)
+  fn get_boot_rom(&self) -> &[u8] {
+    match self.boot_rom.as_deref() {
+      Some(boot_rom) => boot_rom,
+      None if self.model.has_builtin_boot_rom() => &BIOS,
+      None => &[],
+    }
+  }
+
+  pub fn has_boot_rom(&self) -> bool {
+    !self.get_boot_rom().is_empty()
+  }
+
+  ///Set IO registers to the values left by the boot ROM and disable it
+  pub fn skip_bootrom(&mut self) {
+    let model = self.model;
+    self.timers.set_div_raw(model.post_boot_div());
+    self.wb(0xFF00, if model.is_sgb() { 0xFF } else { 0xCF }, false);
+    self.wb(0xFF01, 0x00, false);
+    self.wb(0xFF02, if model.is_cgb() { 0x7F } else { 0x7E }, false);
+    self.wb(0xFF07, 0xF8, false);
+    //Sound
+    self.wb(0xFF26, 0x80, false);
+    self.wb(0xFF10, 0x80, false);
+    self.wb(0xFF11, 0xBF, false);
+    self.wb(0xFF13, 0xFF, false);
+    if model.is_sgb() {
+      self.wb(0xFF14, 0x07, false);
+    } else {
+      //Channel 1 is left enabled (but silent) after the startup sound
+      self.wb(0xFF12, 0x08, false);
+      self.wb(0xFF14, 0x87, false);
+    }
+    self.wb(0xFF12, 0xF3, false);
+    self.wb(0xFF16, 0x3F, false);
+    self.wb(0xFF18, 0xFF, false);
+    self.wb(0xFF19, 0x07, false);
+    self.wb(0xFF1A, 0x7F, false);
+    self.wb(0xFF1B, 0xFF, false);
+    self.wb(0xFF1C, 0x9F, false);
+    self.wb(0xFF1D, 0xFF, false);
+    self.wb(0xFF1E, 0x07, false);
+    self.wb(0xFF20, 0xFF, false);
+    self.wb(0xFF24, 0x77, false);
+    self.wb(0xFF25, 0xF3, false);
+    //Video
+    self.wb(0xFF40, 0x91, false);
+    self.wb(0xFF47, 0xFC, false);
+    self.oam_value = if model.is_cgb() { 0x00 } else { 0xFF };
+    self.iif = 0x01;
+    self.bios_disabled = true;
+  }
+
+  ///Bank mapped at `addr` (ROM, cartridge RAM and WRAM banks, 0 for other regions)
+  pub fn bank_at(&self, addr: u16) -> u16 {
+    match addr {
+      0x4000..=0x7FFF => self.cart.rom_bank(),
+      0xA000..=0xBFFF => self.cart.ram_bank() as u16,
+      0xD000..=0xDFFF => self.wram_bank.max(1) as u16,
+      _ => 0
+    }
+  }
+
+  fn wram_addr(&self, addr: u16) -> usize {
+    let addr = (addr & 0x1FFF) as usize;
+    match addr {
+      0x0000..=0x0FFF => addr,
+      //Switchable bank (1-7, bank 0 selects bank 1)
+      _ => ((self.wram_bank.max(1) as usize) << 12) | (addr & 0x0FFF),
+    }
+  }
+
+  ///Perform a speed switch if it was requested through KEY1,
+  ///returns `false` if STOP should be executed normally
+  pub fn try_speed_switch(&mut self) -> bool {
+    if !(self.cgb_mode && self.speed_switch) {
+      return false
+    }
+    self.double_speed = !self.double_speed;
+    self.speed_switch = false;
+    self.timers.reset_div();
+    true
+  }
+
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.wram_bank <= 7, "invalid wram bank")
+  }
+
   fn check_oam_dma_block(&self, addr: u16) -> bool {
     //TODO THIS MAY CAUSE (gb game?) CRASHES
     (self.oam_transfer > 0) && !((0xFF80..=0xFFFE).contains(&addr) || (addr == 0xFF46))
@@ -180,8 +369,11 @@ impl MemBus {
       }
       let src_value = self.rb(src_addr, true);
       let dest_addr = 0xFE00 | i;
+      #[cfg(feature = "dbg-breakpoints")]
+      self.watchpoints.check(dest_addr, src_value, true);
       self.ppu.write_oam(dest_addr, src_value, false);
     }
+    self.record_event(Event::OamDma { source: src_start });
     //Update variables
     self.oam_value = value;
     self.oam_transfer = 160;
@@ -195,9 +387,32 @@ impl MemBus {
     }
   }
 
+  fn tick_vram_dma(&mut self) {
+    //2 bytes per M-cycle (1 in double speed mode)
+    let bytes = if self.double_speed { 1 } else { 2 };
+    for _ in 0..bytes {
+      if let Some((src, dst)) = self.vram_dma.next_transfer() {
+        let value = self.rb(src, false);
+        #[cfg(feature = "dbg-breakpoints")] {
+          self.watchpoints.check(src, value, false);
+          self.watchpoints.check(dst, value, true);
+        }
+        self.ppu.write_vram(dst, value, false);
+      }
+    }
+  }
+
+  fn set_header(&mut self, header: RomHeader) {
+    self.cart_header = header;
+    self.cgb_mode = (self.model == Model::Cgb) && header.supports_cgb();
+    self.ppu.cgb_mode = self.cgb_mode;
+    self.sgb_mode = self.model.is_sgb() && header.supports_sgb();
+    self.input.sgb_enabled = self.sgb_mode;
+  }
+
   pub fn load_rom(&mut self, data: &[u8]) -> Res<()> {
     let header = RomHeader::parse(data);
-    self.cart_header = header;
+    self.set_header(header);
     self.cart = cartridge::get_cartridge(header)?;
     self.cart.load_rom(data)?;
     Ok(())
@@ -205,7 +420,7 @@ impl MemBus {
   pub fn load_rom_force_mbc(&mut self, data: &[u8], mbc_type: u8) -> Res<()> {
     let mut header = RomHeader::parse(data);
     header.mbc_type = mbc_type;
-    self.cart_header = header;
+    self.set_header(header);
     self.cart = cartridge::get_cartridge(header).unwrap();
     self.cart.load_rom(data)?;
     Ok(())
@@ -230,13 +445,88 @@ impl MemBus {
     self.cart_header
   }
 
+  ///Start recording events (or stop if `None`), returning the previous recorder
+  pub fn set_event_recorder(&mut self, recorder: Option<EventRecorder>) -> Option<EventRecorder> {
+    self.ppu.events.enabled = recorder.is_some();
+    self.cycles = 0;
+    std::mem::replace(&mut self.events, recorder)
+  }
+  pub fn event_recorder(&self) -> Option<&EventRecorder> {
+    self.events.as_ref()
+  }
+  pub fn event_recorder_mut(&mut self) -> Option<&mut EventRecorder> {
+    self.events.as_mut()
+  }
+
+  #[inline]
+  pub fn record_event(&mut self, event: Event) {
+    if let Some(events) = self.events.as_mut() {
+      events.record(self.cycles, event);
+    }
+  }
+
+  fn collect_events(&mut self, prev_iif: u8) {
+    let timer_overflow = self.timers.take_overflow();
+    let events = match self.events.as_mut() {
+      Some(events) => events,
+      None => return
+    };
+    for event in self.ppu.events.drain() {
+      events.record(self.cycles, event);
+    }
+    if timer_overflow {
+      events.record(self.cycles, Event::TimerOverflow);
+    }
+    let requested = self.iif & !prev_iif;
+    for interrupt in 0..5 {
+      if requested & (1 << interrupt) != 0 {
+        events.record(self.cycles, Event::InterruptRequested { interrupt });
+      }
+    }
+  }
+
   pub fn tick_components(&mut self) {
+    //In double speed mode, PPU, APU and the cartridge (RTC) only tick every other M-cycle
+    let normal_speed_tick = !self.double_speed || {
+      self.half_cycle = !self.half_cycle;
+      self.half_cycle
+    };
+    let prev_iif = self.iif;
+    self.cycles += 4;
     self.tick_oam_dma();
-    self.ppu.tick(&mut self.iif);
+    self.tick_vram_dma();
+    if normal_speed_tick {
+      self.ppu.tick(&mut self.iif);
+      if self.ppu.take_hblank_start() {
+        self.vram_dma.hblank_start();
+      }
+      //Non-SGB ROMs don't use any SGB features (not even the border)
+      if self.ppu.take_vblank_start() && self.sgb_mode {
+        self.sgb.frame_done(&self.ppu.display);
+      }
+    }
     self.timers.tick(&mut self.iif);
-    self.apu.tick(self.timers.get_div_raw());
+    if normal_speed_tick {
+      self.apu.tick(self.timers.get_div_raw(), self.double_speed);
+    }
     self.input.tick(&mut self.iif);
+    if let Some(packet) = self.input.take_sgb_packet() {
+      self.sgb.handle_packet(packet, &mut self.input);
+    }
     self.serial.tick(&mut self.iif);
-    self.cart.tick();
+    if normal_speed_tick {
+      self.cart.tick();
+    }
+    self.collect_events(prev_iif);
   }
 }
+
+impl_save_state!(MemBus {
+  bios_disabled, cart,
+  cgb_mode, sgb_mode, wram, wram_bank, hram,
+  double_speed, speed_switch, half_cycle,
+  oam_value, oam_transfer,
+  vram_dma,
+  iie, iif,
+  ppu, apu, timers, input, serial, sgb,
+} validate validate_state);
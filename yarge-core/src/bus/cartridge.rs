@@ -1,5 +1,5 @@
 use enum_dispatch::enum_dispatch;
-use crate::{Res, YargeError};
+use crate::{Res, YargeError, state::{SaveState, StateReader}};
 
 mod helpers;
 
@@ -26,6 +26,14 @@ pub trait CartridgeImpl {
   fn save_data(&self) -> Option<Vec<u8>> { None }
   fn load_data(&mut self, data: Vec<u8>) {}
 
+  ///ROM bank mapped to 0x4000-0x7FFF
+  fn rom_bank(&self) -> u16 { 1 }
+  ///ERAM bank mapped to 0xA000-0xBFFF (selecting a MBC3 RTC register doesn't change it)
+  fn ram_bank(&self) -> u8 { 0 }
+
+  fn has_rumble(&self) -> bool { false }
+  fn rumble_motor(&self) -> bool { false }
+
   fn tick(&mut self) {}
 }
 
@@ -36,7 +44,32 @@ pub enum Cartridge {
   MockCartridge,
   CartridgeNone,
   CartridgeMbc1,
+  CartridgeMbc2,
   CartridgeMbc3,
+  CartridgeMbc5,
+}
+
+impl SaveState for Cartridge {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    match self {
+      Self::MockCartridge(cart) => cart.save_state(out),
+      Self::CartridgeNone(cart) => cart.save_state(out),
+      Self::CartridgeMbc1(cart) => cart.save_state(out),
+      Self::CartridgeMbc2(cart) => cart.save_state(out),
+      Self::CartridgeMbc3(cart) => cart.save_state(out),
+      Self::CartridgeMbc5(cart) => cart.save_state(out),
+    }
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    match self {
+      Self::MockCartridge(cart) => cart.load_state(input),
+      Self::CartridgeNone(cart) => cart.load_state(input),
+      Self::CartridgeMbc1(cart) => cart.load_state(input),
+      Self::CartridgeMbc2(cart) => cart.load_state(input),
+      Self::CartridgeMbc3(cart) => cart.load_state(input),
+      Self::CartridgeMbc5(cart) => cart.load_state(input),
+    }
+  }
 }
 
 pub fn get_cartridge(header: RomHeader) -> Res<Cartridge> {
@@ -46,6 +79,8 @@ pub fn get_cartridge(header: RomHeader) -> Res<Cartridge> {
     0x01 => Ok(CartridgeMbc1::new(Mbc1Type::None, &header).into()),
     0x02 => Ok(CartridgeMbc1::new(Mbc1Type::Ram, &header).into()),
     0x03 => Ok(CartridgeMbc1::new(Mbc1Type::RamBattery, &header).into()),
+    0x05 => Ok(CartridgeMbc2::new(false, &header).into()),
+    0x06 => Ok(CartridgeMbc2::new(true, &header).into()),
     0x0F => Ok(CartridgeMbc3::new(Mbc3Config {
       timer: true,
       ram: false,
@@ -71,6 +106,36 @@ pub fn get_cartridge(header: RomHeader) -> Res<Cartridge> {
       ram: true,
       battery: true,
     }, &header).into()),
+    0x19 => Ok(CartridgeMbc5::new(Mbc5Config {
+      ram: false,
+      battery: false,
+      rumble: false,
+    }, &header).into()),
+    0x1A => Ok(CartridgeMbc5::new(Mbc5Config {
+      ram: true,
+      battery: false,
+      rumble: false,
+    }, &header).into()),
+    0x1B => Ok(CartridgeMbc5::new(Mbc5Config {
+      ram: true,
+      battery: true,
+      rumble: false,
+    }, &header).into()),
+    0x1C => Ok(CartridgeMbc5::new(Mbc5Config {
+      ram: false,
+      battery: false,
+      rumble: true,
+    }, &header).into()),
+    0x1D => Ok(CartridgeMbc5::new(Mbc5Config {
+      ram: true,
+      battery: false,
+      rumble: true,
+    }, &header).into()),
+    0x1E => Ok(CartridgeMbc5::new(Mbc5Config {
+      ram: true,
+      battery: true,
+      rumble: true,
+    }, &header).into()),
     _ => Err(YargeError::InvalidMbcType(mbc_type))
   }
 }
@@ -5,14 +5,23 @@ use parse_display::Display;
 #[display("Name: {name}\nMBC type: {mbc_type}\nROM size: {rom_size} kb\nRAM size: {ram_size} bytes")]
 pub struct RomHeader {
   pub name: ArrayString<16>,
+  pub cgb_flag: u8,
+  pub sgb_flag: u8,
+  pub old_licensee_code: u8,
+  pub header_checksum: u8,
   pub mbc_type: u8,
   pub rom_size: usize,
   pub ram_size: usize,
+  pub global_checksum: u16,
 }
 impl RomHeader {
   pub fn parse(rom: &[u8]) -> Self {
     Self {
       mbc_type: rom[0x147],
+      cgb_flag: rom[0x143],
+      sgb_flag: rom[0x146],
+      old_licensee_code: rom[0x14B],
+      header_checksum: rom[0x14D],
       name: {
         let mut string = ArrayString::new();
         for byte in rom.iter().skip(0x134).take(15) {
@@ -30,7 +39,16 @@ impl RomHeader {
         4 => 128 * 1024,
         5 => 64 * 1024,
         _ => 0,
-      }
+      },
+      global_checksum: u16::from_be_bytes([rom[0x14E], rom[0x14F]]),
     }
   }
+  ///Returns true if the ROM supports (or requires) CGB mode
+  pub fn supports_cgb(&self) -> bool {
+    (self.cgb_flag & 0x80) != 0
+  }
+  ///Returns true if the ROM supports SGB functions
+  pub fn supports_sgb(&self) -> bool {
+    (self.sgb_flag == 0x03) && (self.old_licensee_code == 0x33)
+  }
 }
@@ -1,19 +1,28 @@
 use super::super::RomHeader;
-use crate::{Res, YargeError};
+use crate::{Res, YargeError, state::check_state};
 
-pub fn rom_addr(addr: u16, bank: u8) -> usize {
+pub fn rom_addr(addr: u16, bank: u16) -> usize {
   (bank as usize * 0x4000) + (addr as usize - 0x4000)
 }
 pub fn eram_addr(addr: u16, bank: u8) -> usize {
   (addr as usize - 0xA000) + (bank as usize * 0x2000)
 }
-pub fn rom_bank_mask(header: &RomHeader) -> u8 {
-  ((header.rom_size >> 4) - 1) as u8
+pub fn rom_bank_mask(header: &RomHeader) -> u16 {
+  ((header.rom_size >> 4) - 1) as u16
 }
 pub fn eram_bank_mask(header: &RomHeader) -> u8 {
   //TODO remove floating point stuff
   ((header.ram_size as f32 / 8192.).ceil() as usize).saturating_sub(1) as u8
 }
+///Checks that ERAM loaded from a save state is present only if the cartridge has RAM
+///and that its size matches the RAM bank mask
+pub fn check_eram_state(eram: Option<&[u8]>, has_ram: bool, ram_mask: u8) -> Res<()> {
+  let valid = match eram {
+    Some(eram) => has_ram && (eram.len() == ((ram_mask as usize + 1) * 0x2000)),
+    None => !has_ram,
+  };
+  check_state(valid, "invalid eram size")
+}
 pub fn load_rom_static<const SIZE: usize>(arr: &mut [u8; SIZE], source: &[u8]) -> Res<()> {
   if source.len() != SIZE {
     return Err(YargeError::InvalidRomSize(source.len()));
@@ -2,8 +2,12 @@ use super::*;
 mod mock;
 mod none;
 mod mbc1;
+mod mbc2;
 mod mbc3;
+mod mbc5;
 pub use mock::MockCartridge;
 pub use none::CartridgeNone;
 pub use mbc1::{CartridgeMbc1, Type as Mbc1Type};
+pub use mbc2::CartridgeMbc2;
 pub use mbc3::{CartridgeMbc3, Configuration as Mbc3Config};
+pub use mbc5::{CartridgeMbc5, Configuration as Mbc5Config};
@@ -1,7 +1,9 @@
 use crate::Res;
 use crate::consts::ROM_SIZE;
+use crate::state::impl_save_state;
 use super::{
   helpers::{
+    check_eram_state,
     eram_addr,
     rom_addr,
     rom_bank_mask,
@@ -12,6 +14,10 @@ use super::{
   CartridgeImpl,
 };
 
+const LOGO_ADDR: usize = 0x104;
+const LOGO_SIZE: usize = 48;
+const MULTICART_LOGO_ADDR: usize = 0x40104;
+
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Type {
@@ -22,12 +28,13 @@ pub struct CartridgeMbc1 {
   mbc1_type: Type,
   rom: Vec<u8>,
   eram: Option<Vec<u8>>,
-  rom_mask: u8,
+  rom_mask: u16,
   ram_mask: u8,
   rom_bank: u8,
   ram_bank: u8,
   ram_enable: bool,
   mode: bool,
+  multicart: bool,
 }
 impl CartridgeMbc1 {
   pub fn new(mbc1_type: Type, header: &RomHeader) -> Self {
@@ -41,26 +48,40 @@ impl CartridgeMbc1 {
       ram_bank: 0,
       ram_enable: false,
       mode: false,
+      multicart: false,
     }
   }
+
+  ///Upper ROM bank bits (from the RAM bank register)
+  fn upper_bank_bits(&self) -> u16 {
+    let shift = if self.multicart { 4 } else { 5 };
+    (self.ram_bank as u16) << shift
+  }
+
+  fn validate_state(&self) -> Res<()> {
+    check_eram_state(self.eram.as_deref(), self.mbc1_type != Type::None, self.ram_mask)
+  }
 }
 impl CartridgeImpl for CartridgeMbc1 {
   fn name(&self) -> &'static str { "MBC1" }
 
   fn load_rom(&mut self, rom: &[u8]) -> Res<()> {
-    load_rom_vec(&mut self.rom, rom)
+    load_rom_vec(&mut self.rom, rom)?;
+    //MBC1M multicarts contain multiple games, each with its own Nintendo logo
+    self.multicart = (self.rom.len() == 0x100000) && (
+      self.rom[MULTICART_LOGO_ADDR..(MULTICART_LOGO_ADDR + LOGO_SIZE)] ==
+      self.rom[LOGO_ADDR..(LOGO_ADDR + LOGO_SIZE)]
+    );
+    Ok(())
   }
 
   fn read_rom(&self, addr: u16) -> u8 {
     if addr < 0x4000 {
-      return self.rom[addr as usize];
-    }
-    let mut bank = self.rom_bank;
-    if self.mode {
-      bank += self.ram_bank << 5;
+      //In mode 1, the upper bank bits also apply to the 0x0000-0x3FFF area
+      let bank = if self.mode { self.upper_bank_bits() & self.rom_mask } else { 0 };
+      return self.rom[(bank as usize * 0x4000) + addr as usize];
     }
-    bank &= self.rom_mask;
-    self.rom[rom_addr(addr, bank)]
+    self.rom[rom_addr(addr, self.rom_bank())]
   }
   fn write_rom(&mut self, addr: u16, value: u8) {
     match addr {
@@ -82,24 +103,24 @@ impl CartridgeImpl for CartridgeMbc1 {
   fn read_eram(&self, addr: u16, blocking: bool) -> u8 {
     if self.mbc1_type == Type::None { return 0xFF }
     if blocking && !self.ram_enable { return 0xFF }
-    let bank = if self.mode {
-      self.ram_bank & self.ram_mask
-    } else {
-      0x00
-    };
-    self.eram.as_ref().unwrap()[eram_addr(addr, bank)]
+    self.eram.as_ref().unwrap()[eram_addr(addr, self.ram_bank())]
   }
   fn write_eram(&mut self, addr: u16, value: u8, blocking: bool) {
     if self.mbc1_type == Type::None { return }
     if blocking && !self.ram_enable { return }
-    let bank = if self.mode {
-      self.ram_bank & self.ram_mask
-    } else {
-      0x00
-    };
+    let bank = self.ram_bank();
     self.eram.as_mut().unwrap()[eram_addr(addr, bank)] = value;
   }
 
+  fn rom_bank(&self) -> u16 {
+    //Only 4 bits of the bank register are wired on MBC1M
+    let lower_mask = if self.multicart { 0xF } else { 0x1F };
+    (self.upper_bank_bits() | (self.rom_bank as u16 & lower_mask)) & self.rom_mask
+  }
+  fn ram_bank(&self) -> u8 {
+    if self.mode { self.ram_bank & self.ram_mask } else { 0 }
+  }
+
   fn has_save_data(&self) -> bool { true }
   fn save_data(&self) -> Option<Vec<u8>> {
     match self.mbc1_type {
@@ -107,7 +128,13 @@ impl CartridgeImpl for CartridgeMbc1 {
       _ => None
     }
   }
-  fn load_data(&mut self, data: Vec<u8>) {
-    self.eram = Some(data);
+  fn load_data(&mut self, mut data: Vec<u8>) {
+    if let Some(eram) = self.eram.as_mut() {
+      //Save files with the wrong size are padded or truncated to the size from the header
+      data.resize(eram.len(), 0);
+      *eram = data;
+    }
   }
 }
+
+impl_save_state!(CartridgeMbc1 { eram, rom_bank, ram_bank, ram_enable, mode } validate validate_state);
@@ -0,0 +1,86 @@
+use crate::Res;
+use crate::consts::ROM_SIZE;
+use crate::state::{impl_save_state, check_state};
+use super::{
+  helpers::{
+    rom_addr,
+    rom_bank_mask,
+    load_rom_vec,
+  },
+  header::RomHeader,
+  CartridgeImpl,
+};
+
+///512 half-bytes of built-in RAM
+const RAM_SIZE: usize = 512;
+
+pub struct CartridgeMbc2 {
+  battery: bool,
+  rom: Vec<u8>,
+  eram: Vec<u8>,
+  rom_mask: u16,
+  rom_bank: u8,
+  ram_enable: bool,
+}
+impl CartridgeMbc2 {
+  pub fn new(battery: bool, header: &RomHeader) -> Self {
+    Self {
+      battery,
+      rom: Vec::with_capacity(ROM_SIZE),
+      eram: vec![0; RAM_SIZE],
+      rom_mask: rom_bank_mask(header),
+      rom_bank: 1,
+      ram_enable: false,
+    }
+  }
+
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.eram.len() == RAM_SIZE, "invalid eram size")
+  }
+}
+impl CartridgeImpl for CartridgeMbc2 {
+  fn name(&self) -> &'static str { "MBC2" }
+
+  fn load_rom(&mut self, rom: &[u8]) -> Res<()> {
+    load_rom_vec(&mut self.rom, rom)
+  }
+
+  fn read_rom(&self, addr: u16) -> u8 {
+    match addr {
+      0x0000..=0x3FFF => self.rom[addr as usize],
+      0x4000..=0xFFFF => self.rom[rom_addr(addr, self.rom_bank as u16 & self.rom_mask)],
+    }
+  }
+  fn write_rom(&mut self, addr: u16, value: u8) {
+    if addr >= 0x4000 { return }
+    //Bit 8 of the address selects the register
+    if (addr & 0x100) == 0 {
+      self.ram_enable = (value & 0xF) == 0xA;
+    } else {
+      self.rom_bank = (value & 0xF).max(1);
+    }
+  }
+
+  //Only the lower 9 bits of the address are used, so RAM is mirrored across A000-BFFF
+  fn read_eram(&self, addr: u16, blocking: bool) -> u8 {
+    if blocking && !self.ram_enable { return 0xFF }
+    self.eram[(addr & 0x1FF) as usize] | 0xF0
+  }
+  fn write_eram(&mut self, addr: u16, value: u8, blocking: bool) {
+    if blocking && !self.ram_enable { return }
+    self.eram[(addr & 0x1FF) as usize] = value & 0x0F;
+  }
+
+  fn rom_bank(&self) -> u16 { self.rom_bank as u16 & self.rom_mask }
+
+  fn has_save_data(&self) -> bool { true }
+  fn save_data(&self) -> Option<Vec<u8>> {
+    self.battery.then(|| self.eram.clone())
+  }
+  fn load_data(&mut self, mut data: Vec<u8>) {
+    data.resize(RAM_SIZE, 0);
+    self.eram = data;
+  }
+}
+
+impl_save_state!(CartridgeMbc2 { eram, rom_bank, ram_enable } validate validate_state);
@@ -1,8 +1,9 @@
-//TODO Handle RTC/Timer
 use crate::Res;
 use crate::consts::ROM_SIZE;
+use crate::state::{impl_save_state, check_state};
 use super::{
   helpers::{
+    check_eram_state,
     eram_addr, 
     rom_addr, 
     rom_bank_mask,
@@ -13,6 +14,9 @@ use super::{
   CartridgeImpl,
 };
 
+mod rtc;
+use rtc::{Rtc, RTC_FOOTER_SIZE, RTC_FOOTER_SIZE_SHORT};
+
 #[derive(Clone, Copy)]
 pub struct Configuration {
   pub timer: bool,
@@ -24,11 +28,13 @@ pub struct CartridgeMbc3 {
   config: Configuration,
   rom: Vec<u8>,
   eram: Option<Vec<u8>>,
-  rom_mask: u8,
+  rom_mask: u16,
   ram_mask: u8,
   rom_bank: u8,
   ram_bank: u8,
-  ram_enable: bool
+  ram_enable: bool,
+  rtc: Rtc,
+  rtc_select: Option<u8>,
 }
 impl CartridgeMbc3 {
   pub fn new(config: Configuration, header: &RomHeader) -> Self {
@@ -41,8 +47,16 @@ impl CartridgeMbc3 {
       rom_bank: 1,
       ram_bank: 0,
       ram_enable: false,
+      rtc: Rtc::new(),
+      rtc_select: None,
     }
   }
+
+  fn validate_state(&self) -> Res<()> {
+    check_eram_state(self.eram.as_deref(), self.config.ram, self.ram_mask)?;
+    check_state((self.rom_bank as u16 & !self.rom_mask) == 0, "invalid rom bank")?;
+    check_state((self.ram_bank & !self.ram_mask) == 0, "invalid ram bank")
+  }
 }
 impl CartridgeImpl for CartridgeMbc3 {
   fn name(&self) -> &'static str { "MBC3" }
@@ -54,7 +68,7 @@ impl CartridgeImpl for CartridgeMbc3 {
   fn read_rom(&self, addr: u16) -> u8 { 
     match addr {
       0x0000..=0x3FFF => self.rom[addr as usize],
-      0x4000..=0xFFFF => self.rom[rom_addr(addr, self.rom_bank)],
+      0x4000..=0xFFFF => self.rom[rom_addr(addr, self.rom_bank as u16)],
     }
   }
   fn write_rom(&mut self, addr: u16, value: u8) {
@@ -63,35 +77,76 @@ impl CartridgeImpl for CartridgeMbc3 {
         self.ram_enable = (value & 0xF) == 0xA;
       },
       0x2000..=0x3FFF => {
-        self.rom_bank = (value & self.rom_mask).max(1);
+        self.rom_bank = (value as u16 & self.rom_mask).max(1) as u8;
       },
       0x4000..=0x5FFF => {
-        if !(0x08..=0x0C).contains(&value) {
+        if (0x08..=0x0C).contains(&value) {
+          self.rtc_select = self.config.timer.then_some(value);
+        } else {
+          self.rtc_select = None;
           self.ram_bank = value & 0b11 & self.ram_mask;
         }
       },
-      0x6000..=0xFFFF => {}
+      0x6000..=0x7FFF => {
+        if self.config.timer {
+          self.rtc.write_latch(value);
+        }
+      },
+      0x8000..=0xFFFF => {}
     }
   }
 
   fn read_eram(&self, addr: u16, blocking: bool) -> u8 {
-    if !self.config.ram { return 0xFF }
     if blocking && !self.ram_enable { return 0xFF }
+    if let Some(reg) = self.rtc_select {
+      return self.rtc.read(reg);
+    }
+    if !self.config.ram { return 0xFF }
     self.eram.as_ref().unwrap()[eram_addr(addr, self.ram_bank)]
   }
   fn write_eram(&mut self, addr: u16, value: u8, blocking: bool) {
-    if !self.config.ram { return }
     if blocking && !self.ram_enable { return }
+    if let Some(reg) = self.rtc_select {
+      self.rtc.write(reg, value);
+      return
+    }
+    if !self.config.ram { return }
     self.eram.as_mut().unwrap()[eram_addr(addr, self.ram_bank)] = value;
   }
 
+  fn rom_bank(&self) -> u16 { self.rom_bank as u16 }
+  fn ram_bank(&self) -> u8 { self.ram_bank }
+
   fn has_save_data(&self) -> bool { true }
   fn save_data(&self) -> Option<Vec<u8>> {
-    (self.config.battery && self.config.ram).then(|| {
-      self.eram.as_ref().unwrap().clone()
-    })
+    if !self.config.battery || !(self.config.ram || self.config.timer) {
+      return None
+    }
+    let mut data = self.eram.clone().unwrap_or_default();
+    if self.config.timer {
+      data.extend(self.rtc.save_footer());
+    }
+    Some(data)
+  }
+  fn load_data(&mut self, mut data: Vec<u8>) {
+    //RTC state is stored as a footer after the RAM contents
+    let footer_size = data.len() % 0x2000;
+    if self.config.timer && matches!(footer_size, RTC_FOOTER_SIZE | RTC_FOOTER_SIZE_SHORT) {
+      let footer = data.split_off(data.len() - footer_size);
+      self.rtc.load_footer(&footer);
+    }
+    if let Some(eram) = self.eram.as_mut() {
+      //Save files with the wrong size are padded or truncated to the size from the header
+      data.resize(eram.len(), 0);
+      *eram = data;
+    }
   }
-  fn load_data(&mut self, data: Vec<u8>) {
-    self.eram = Some(data);
+
+  fn tick(&mut self) {
+    if self.config.timer {
+      self.rtc.tick();
+    }
   }
 }
+
+impl_save_state!(CartridgeMbc3 { eram, rom_bank, ram_bank, ram_enable, rtc, rtc_select } validate validate_state);
@@ -0,0 +1,183 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::consts::CPU_CLOCK_SPEED;
+use crate::state::impl_save_state;
+
+///Size of the RTC footer appended to the save file (VBA/BGB format, 64-bit timestamp)
+pub const RTC_FOOTER_SIZE: usize = 48;
+///Older variant of the footer with a 32-bit timestamp
+pub const RTC_FOOTER_SIZE_SHORT: usize = 44;
+
+#[derive(Clone, Copy, Default)]
+pub struct RtcRegisters {
+  pub seconds: u8,
+  pub minutes: u8,
+  pub hours: u8,
+  pub days: u16,
+  pub halt: bool,
+  pub carry: bool,
+}
+impl RtcRegisters {
+  pub fn read(&self, reg: u8) -> u8 {
+    match reg {
+      0x08 => self.seconds,
+      0x09 => self.minutes,
+      0x0A => self.hours,
+      0x0B => self.days as u8,
+      0x0C => {
+        ((self.days >> 8) as u8 & 1) |
+        ((self.halt as u8) << 6) |
+        ((self.carry as u8) << 7)
+      },
+      _ => 0xFF
+    }
+  }
+
+  pub fn write(&mut self, reg: u8, value: u8) {
+    match reg {
+      0x08 => { self.seconds = value & 0x3F },
+      0x09 => { self.minutes = value & 0x3F },
+      0x0A => { self.hours = value & 0x1F },
+      0x0B => { self.days = (self.days & 0x100) | value as u16 },
+      0x0C => {
+        self.days = (self.days & 0xFF) | ((value as u16 & 1) << 8);
+        self.halt = (value & (1 << 6)) != 0;
+        self.carry = (value & (1 << 7)) != 0;
+      },
+      _ => ()
+    }
+  }
+
+  ///Advance the clock by one second
+  ///(out-of-range values keep counting up to the register width without carrying over)
+  fn tick_second(&mut self) {
+    self.seconds = (self.seconds + 1) & 0x3F;
+    if self.seconds != 60 { return }
+    self.seconds = 0;
+    self.minutes = (self.minutes + 1) & 0x3F;
+    if self.minutes != 60 { return }
+    self.minutes = 0;
+    self.hours = (self.hours + 1) & 0x1F;
+    if self.hours != 24 { return }
+    self.hours = 0;
+    self.days += 1;
+    if self.days == 512 {
+      self.days = 0;
+      self.carry = true;
+    }
+  }
+
+  fn out_of_range(&self) -> bool {
+    self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24
+  }
+
+  ///Advance the clock by an arbitrary amount of seconds
+  fn advance(&mut self, mut seconds: u64) {
+    if self.halt { return }
+    //Out-of-range values need to be ticked one second at a time to emulate the weird overflow behaviour,
+    //they wrap back into range after at most a few hours of emulated time
+    while seconds > 0 && self.out_of_range() {
+      self.tick_second();
+      seconds -= 1;
+    }
+    let total = seconds +
+      self.seconds as u64 +
+      self.minutes as u64 * 60 +
+      self.hours as u64 * 3600 +
+      self.days as u64 * 86400;
+    let days = total / 86400;
+    self.seconds = (total % 60) as u8;
+    self.minutes = (total / 60 % 60) as u8;
+    self.hours = (total / 3600 % 24) as u8;
+    self.days = (days % 512) as u16;
+    self.carry |= days >= 512;
+  }
+
+  fn to_footer(self, footer: &mut Vec<u8>) {
+    for reg in 0x08..=0x0C {
+      footer.extend_from_slice(&(self.read(reg) as u32).to_le_bytes());
+    }
+  }
+
+  fn from_footer(data: &[u8]) -> Self {
+    let mut regs = Self::default();
+    for (reg, chunk) in (0x08..=0x0C).zip(data.chunks_exact(4)) {
+      regs.write(reg, chunk[0]);
+    }
+    regs
+  }
+}
+
+#[derive(Default)]
+pub struct Rtc {
+  live: RtcRegisters,
+  latched: RtcRegisters,
+  latch_armed: bool,
+  cycles: usize,
+}
+impl Rtc {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  ///Reads always return the latched copy of the registers
+  pub fn read(&self, reg: u8) -> u8 {
+    self.latched.read(reg)
+  }
+
+  pub fn write(&mut self, reg: u8, value: u8) {
+    if reg == 0x08 {
+      //Writing to the seconds register resets the sub-second counter
+      self.cycles = 0;
+    }
+    self.live.write(reg, value);
+  }
+
+  ///Writing 0x00 and then 0x01 latches the current time
+  pub fn write_latch(&mut self, value: u8) {
+    if self.latch_armed && value == 0x01 {
+      self.latched = self.live;
+    }
+    self.latch_armed = value == 0x00;
+  }
+
+  ///Called once every M-cycle
+  pub fn tick(&mut self) {
+    if self.live.halt { return }
+    self.cycles += 4;
+    if self.cycles >= CPU_CLOCK_SPEED {
+      self.cycles -= CPU_CLOCK_SPEED;
+      self.live.tick_second();
+    }
+  }
+
+  pub fn save_footer(&self) -> Vec<u8> {
+    let mut footer = Vec::with_capacity(RTC_FOOTER_SIZE);
+    self.live.to_footer(&mut footer);
+    self.latched.to_footer(&mut footer);
+    footer.extend_from_slice(&unix_time().to_le_bytes());
+    footer
+  }
+
+  ///Accepts both the 48 and 44 byte variants of the footer
+  pub fn load_footer(&mut self, footer: &[u8]) {
+    self.live = RtcRegisters::from_footer(&footer[0..20]);
+    self.latched = RtcRegisters::from_footer(&footer[20..40]);
+    self.cycles = 0;
+    //Catch up with the time that passed since the save was made
+    let timestamp = match footer.len() {
+      RTC_FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+      _ => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+    };
+    self.live.advance(unix_time().saturating_sub(timestamp));
+  }
+}
+
+fn unix_time() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or(0)
+}
+
+impl_save_state!(RtcRegisters { seconds, minutes, hours, days, halt, carry });
+impl_save_state!(Rtc { live, latched, latch_armed, cycles });
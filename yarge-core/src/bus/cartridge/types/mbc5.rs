@@ -0,0 +1,129 @@
+use crate::Res;
+use crate::consts::ROM_SIZE;
+use crate::state::{impl_save_state, check_state};
+use super::{
+  helpers::{
+    check_eram_state,
+    eram_addr,
+    rom_addr,
+    rom_bank_mask,
+    eram_bank_mask,
+    load_rom_vec,
+  },
+  header::RomHeader,
+  CartridgeImpl,
+};
+
+#[derive(Clone, Copy)]
+pub struct Configuration {
+  pub ram: bool,
+  pub battery: bool,
+  pub rumble: bool,
+}
+
+pub struct CartridgeMbc5 {
+  config: Configuration,
+  rom: Vec<u8>,
+  eram: Option<Vec<u8>>,
+  rom_mask: u16,
+  ram_mask: u8,
+  rom_bank: u16,
+  ram_bank: u8,
+  ram_enable: bool,
+  rumble_motor: bool,
+}
+impl CartridgeMbc5 {
+  pub fn new(config: Configuration, header: &RomHeader) -> Self {
+    Self {
+      config,
+      rom: Vec::with_capacity(ROM_SIZE),
+      eram: config.ram.then(|| vec![0; header.ram_size.max(8192)]),
+      rom_mask: rom_bank_mask(header),
+      ram_mask: eram_bank_mask(header),
+      rom_bank: 1,
+      ram_bank: 0,
+      ram_enable: false,
+      rumble_motor: false,
+    }
+  }
+
+  fn validate_state(&self) -> Res<()> {
+    check_eram_state(self.eram.as_deref(), self.config.ram, self.ram_mask)?;
+    check_state((self.ram_bank & !self.ram_mask) == 0, "invalid ram bank")
+  }
+}
+impl CartridgeImpl for CartridgeMbc5 {
+  fn name(&self) -> &'static str { "MBC5" }
+
+  fn load_rom(&mut self, rom: &[u8]) -> Res<()> {
+    load_rom_vec(&mut self.rom, rom)
+  }
+
+  fn read_rom(&self, addr: u16) -> u8 {
+    match addr {
+      0x0000..=0x3FFF => self.rom[addr as usize],
+      //Unlike MBC1/MBC3, bank 0 can be mapped here
+      0x4000..=0xFFFF => self.rom[rom_addr(addr, self.rom_bank & self.rom_mask)],
+    }
+  }
+  fn write_rom(&mut self, addr: u16, value: u8) {
+    match addr {
+      0x0000..=0x1FFF => {
+        self.ram_enable = (value & 0xF) == 0xA;
+      },
+      //Lower 8 bits of the ROM bank
+      0x2000..=0x2FFF => {
+        self.rom_bank = (self.rom_bank & 0x100) | value as u16;
+      },
+      //9th bit of the ROM bank
+      0x3000..=0x3FFF => {
+        self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 1) << 8);
+      },
+      0x4000..=0x5FFF => {
+        if self.config.rumble {
+          //On rumble carts bit 3 drives the motor instead of selecting the RAM bank
+          self.rumble_motor = (value & 0b1000) != 0;
+          self.ram_bank = value & 0b0111 & self.ram_mask;
+        } else {
+          self.ram_bank = value & 0b1111 & self.ram_mask;
+        }
+      },
+      0x6000..=0xFFFF => {}
+    }
+  }
+
+  fn read_eram(&self, addr: u16, blocking: bool) -> u8 {
+    if !self.config.ram { return 0xFF }
+    if blocking && !self.ram_enable { return 0xFF }
+    self.eram.as_ref().unwrap()[eram_addr(addr, self.ram_bank)]
+  }
+  fn write_eram(&mut self, addr: u16, value: u8, blocking: bool) {
+    if !self.config.ram { return }
+    if blocking && !self.ram_enable { return }
+    self.eram.as_mut().unwrap()[eram_addr(addr, self.ram_bank)] = value;
+  }
+
+  fn rom_bank(&self) -> u16 { self.rom_bank & self.rom_mask }
+  fn ram_bank(&self) -> u8 { self.ram_bank }
+
+  fn has_save_data(&self) -> bool { self.config.battery && self.config.ram }
+  fn save_data(&self) -> Option<Vec<u8>> {
+    (self.config.battery && self.config.ram).then(|| {
+      self.eram.as_ref().unwrap().clone()
+    })
+  }
+  fn load_data(&mut self, mut data: Vec<u8>) {
+    if let Some(eram) = self.eram.as_mut() {
+      //Save files with the wrong size are padded or truncated to the size from the header
+      data.resize(eram.len(), 0);
+      *eram = data;
+    }
+  }
+
+  fn has_rumble(&self) -> bool { self.config.rumble }
+  fn rumble_motor(&self) -> bool {
+    self.config.rumble && self.rumble_motor
+  }
+}
+
+impl_save_state!(CartridgeMbc5 { eram, rom_bank, ram_bank, ram_enable, rumble_motor } validate validate_state);
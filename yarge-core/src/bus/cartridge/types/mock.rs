@@ -1,5 +1,6 @@
 use super::CartridgeImpl;
 use crate::consts::DEFAULT_HEADER;
+use crate::state::impl_save_state;
 
 pub struct MockCartridge;
 impl CartridgeImpl for MockCartridge {
@@ -9,3 +10,5 @@ impl CartridgeImpl for MockCartridge {
     *DEFAULT_HEADER.get(addr).unwrap_or(&0)
   }
 }
+
+impl_save_state!(MockCartridge {});
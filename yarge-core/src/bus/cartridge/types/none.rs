@@ -1,5 +1,6 @@
 use crate::Res;
 use crate::consts::ROM_SIZE;
+use crate::state::impl_save_state;
 use super::{CartridgeImpl, helpers::load_rom_static};
 
 pub struct CartridgeNone {
@@ -21,3 +22,6 @@ impl CartridgeImpl for CartridgeNone {
     self.rom[(addr & 0x7FFF) as usize]
   }
 }
+
+//ROM data is not a part of the save state
+impl_save_state!(CartridgeNone {});
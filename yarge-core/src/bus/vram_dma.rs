@@ -0,0 +1,104 @@
+use crate::state::impl_save_state;
+
+const BLOCK_SIZE: u8 = 0x10;
+
+///CGB VRAM DMA (HDMA1-HDMA5)
+pub struct VramDma {
+  src: u16,
+  dst: u16,
+  ///Remaining length (in 16 byte blocks) minus 1
+  length: u8,
+  active: bool,
+  hblank: bool,
+  ///Bytes left to copy in the current block, the CPU is stalled while it's not zero
+  block_bytes: u8,
+}
+impl VramDma {
+  pub fn new() -> Self {
+    Self {
+      src: 0,
+      dst: 0,
+      length: 0x7F,
+      active: false,
+      hblank: false,
+      block_bytes: 0,
+    }
+  }
+
+  pub fn write_src_high(&mut self, value: u8) {
+    self.src = (self.src & 0x00FF) | ((value as u16) << 8);
+  }
+  pub fn write_src_low(&mut self, value: u8) {
+    self.src = (self.src & 0xFF00) | (value & 0xF0) as u16;
+  }
+  pub fn write_dst_high(&mut self, value: u8) {
+    self.dst = (self.dst & 0x00FF) | (((value & 0x1F) as u16) << 8);
+  }
+  pub fn write_dst_low(&mut self, value: u8) {
+    self.dst = (self.dst & 0xFF00) | (value & 0xF0) as u16;
+  }
+
+  pub fn read_hdma5(&self) -> u8 {
+    ((!self.active as u8) << 7) | self.length
+  }
+  pub fn write_hdma5(&mut self, value: u8, in_hblank: bool) {
+    //Writing with bit 7 cleared cancels an active HBlank transfer
+    if self.active && self.hblank && (value & 0x80 == 0) {
+      self.active = false;
+      return
+    }
+    self.length = value & 0x7F;
+    self.hblank = (value & 0x80) != 0;
+    self.active = true;
+    //General purpose transfers start right away,
+    //HBlank transfers copy the first block immediately if the PPU is already in HBlank (or the LCD is off)
+    if !self.hblank || in_hblank {
+      self.block_bytes = BLOCK_SIZE;
+    }
+  }
+
+  ///Called every time the PPU enters HBlank
+  pub fn hblank_start(&mut self) {
+    if self.active && self.hblank && self.block_bytes == 0 {
+      self.block_bytes = BLOCK_SIZE;
+    }
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.active
+  }
+
+  ///Source, destination, remaining length in bytes and HBlank mode of the current transfer
+  pub fn transfer_info(&self) -> (u16, u16, u16, bool) {
+    (self.src, 0x8000 | (self.dst & 0x1FFF), (self.length as u16 + 1) * BLOCK_SIZE as u16, self.hblank)
+  }
+
+  pub fn is_transferring(&self) -> bool {
+    self.block_bytes > 0
+  }
+
+  ///Get the source and destination address of the next byte to copy
+  pub fn next_transfer(&mut self) -> Option<(u16, u16)> {
+    if self.block_bytes == 0 {
+      return None
+    }
+    let addrs = (self.src, 0x8000 | (self.dst & 0x1FFF));
+    self.src = self.src.wrapping_add(1);
+    self.dst = self.dst.wrapping_add(1);
+    self.block_bytes -= 1;
+    if self.block_bytes == 0 {
+      self.length = self.length.wrapping_sub(1) & 0x7F;
+      if self.length == 0x7F {
+        self.active = false;
+      } else if !self.hblank {
+        self.block_bytes = BLOCK_SIZE;
+      }
+    }
+    Some(addrs)
+  }
+}
+impl Default for VramDma {
+  fn default() -> Self { Self::new() }
+}
+
+impl_save_state!(VramDma { src, dst, length, active, hblank, block_bytes });
@@ -0,0 +1,57 @@
+use std::{cell::Cell, ops::RangeInclusive};
+
+///Memory watchpoint, triggered by CPU, OAM DMA and VRAM DMA accesses
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+  pub range: RangeInclusive<u16>,
+  pub read: bool,
+  pub write: bool,
+  ///Only trigger if the value read/written matches
+  pub value: Option<u8>,
+}
+impl Watchpoint {
+  pub fn new(range: RangeInclusive<u16>, read: bool, write: bool) -> Self {
+    Self { range, read, write, value: None }
+  }
+
+  pub fn with_value(mut self, value: u8) -> Self {
+    self.value = Some(value);
+    self
+  }
+
+  fn matches(&self, addr: u16, value: u8, is_write: bool) -> bool {
+    (if is_write { self.write } else { self.read }) &&
+    self.range.contains(&addr) &&
+    self.value.map(|x| x == value).unwrap_or(true)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchpointHit {
+  pub is_write: bool,
+  pub addr: u16,
+  pub value: u8,
+}
+
+#[derive(Default)]
+pub struct Watchpoints {
+  pub list: Vec<Watchpoint>,
+  //`MemBus::rb` takes `&self`, so the hit is stored in a Cell
+  hit: Cell<Option<WatchpointHit>>,
+}
+impl Watchpoints {
+  #[inline]
+  pub fn check(&self, addr: u16, value: u8, is_write: bool) {
+    if self.list.is_empty() || self.hit.get().is_some() {
+      return
+    }
+    if self.list.iter().any(|x| x.matches(addr, value, is_write)) {
+      self.hit.set(Some(WatchpointHit { is_write, addr, value }));
+    }
+  }
+
+  ///Returns the first watchpoint hit since the last call
+  pub fn take_hit(&mut self) -> Option<WatchpointHit> {
+    self.hit.take()
+  }
+}
@@ -2,17 +2,26 @@ pub const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 pub const WIDTH: usize = 160;
 pub const HEIGHT: usize = 144;
 pub const FB_SIZE: usize = WIDTH * HEIGHT;
+pub const SGB_WIDTH: usize = 256;
+pub const SGB_HEIGHT: usize = 224;
+pub const SGB_FB_SIZE: usize = SGB_WIDTH * SGB_HEIGHT;
 pub const CPU_CLOCK_SPEED: usize = 4194304; //audio 4213440?
 //not tied to clock speed because it's fine tuned to act only as fallback!
 pub const CYCLES_PER_FRAME: usize = 70684; 
+#[deprecated(note = "the output sample rate is configurable and samples are not taken at a fixed integer interval, use `Gameboy::get_audio_sample_rate`")]
 pub const AUDIO_CYCLES_PER_SAMPLE: usize = CPU_CLOCK_SPEED / AUDIO_SAMPLE_RATE;
 pub(crate) const ROM_SIZE: usize = 0x8000;
 pub(crate) const VRAM_SIZE: usize = 0x2000;
 pub(crate) const OBJECTS_PER_LINE: usize = 10;
 pub(crate) const INT_JMP_VEC: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+pub(crate) const SERIAL_CLOCK_CYCLES: u16 = 512; //8192 Hz
 pub(crate) const TIMER_CLOCK_MASKS: [u16; 4] = [1 << 9, 1 << 3, 1 << 5, 1 << 7];
+///Default audio chunk size (interleaved stereo samples passed to `AudioDevice::queue_samples`)
 pub const AUDIO_BUFFER_SIZE: usize = 4096; //2048;
+///Default audio sample rate
 pub const AUDIO_SAMPLE_RATE: usize = 44100;
+///Number of per-channel samples kept for visualization
+pub const CHANNEL_BUFFER_SIZE: usize = 2048;
 /// Shamelessly stolen from here:
 /// https://github.com/vojty/feather-gb/blob/main/gb/src/apu/registers.rs
 pub(crate) mod audio_registers {
@@ -1,10 +1,14 @@
 mod reg;
 mod instructions;
+pub mod call_stack;
+#[cfg(feature = "dbg-breakpoints")]
+pub mod breakpoints;
 use instructions::{cpu_instructions, cpu_instructions_cb};
+use call_stack::{CallStack, CallFrame};
 pub use reg::Registers;
-use crate::{MemBus, Res, consts::INT_JMP_VEC};
+use crate::{MemBus, Res, Model, consts::INT_JMP_VEC, event::Event, io_registers::InterruptRegisters, state::{impl_save_state, impl_save_state_enum}};
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum CpuState {
   Running,
   Halt,
@@ -27,32 +31,49 @@ pub struct Cpu {
   ime_pending: bool,
   ime: bool,
   t: usize,
+  pub call_stack: CallStack,
   #[cfg(feature = "dbg-breakpoints")]
-  pub pc_breakpoints: Box<[bool; 0x10000]>,
+  pub breakpoints: breakpoints::Breakpoints,
 }
 
 impl Cpu {
-  pub fn new() -> Self {
+  pub fn new(model: Model) -> Self {
     Self {
       reg: Registers::new(),
-      bus: MemBus::new(),
+      bus: MemBus::new(model),
       state: CpuState::Running,
       ime_pending: false,
       ime: false,
       t: 0,
+      call_stack: CallStack::default(),
       #[cfg(feature = "dbg-breakpoints")]
-      pc_breakpoints: Box::new([false; 0x10000]),
+      breakpoints: breakpoints::Breakpoints::default(),
     }
   }
 
   #[cfg(feature = "dbg-breakpoints")]
-  fn check_pc_breakpoints(&mut self, addr: u16) -> Res<()> {
+  fn check_pc_breakpoints(&mut self) -> Res<()> {
     use crate::YargeError;
-    if self.pc_breakpoints[addr as usize] {
-      let instr = self.bus.rb(addr, true);
-      Err(YargeError::PcBreakpoint { instr, addr })
-    } else {
-      Ok(())
+    let addr = self.reg.pc;
+    match self.breakpoints.check(addr, &self.reg, &self.bus) {
+      Some(bank) => {
+        let instr = self.bus.rb(addr, false);
+        Err(YargeError::PcBreakpoint { instr, addr, bank })
+      },
+      None => Ok(())
+    }
+  }
+
+  #[cfg(feature = "dbg-breakpoints")]
+  fn check_watchpoints(&mut self) -> Res<()> {
+    use crate::YargeError;
+    match self.bus.watchpoints.take_hit() {
+      Some(hit) => Err(YargeError::MmuBreakpoint {
+        is_write: hit.is_write,
+        addr: hit.addr,
+        value: hit.value,
+      }),
+      None => Ok(())
     }
   }
 
@@ -100,6 +121,16 @@ impl Cpu {
     self.bus.tick_components();
   }
 
+  ///Record a call, must be called right after the return address is pushed
+  fn track_call(&mut self, target: u16, interrupt: bool) {
+    self.call_stack.call(CallFrame {
+      target,
+      return_addr: self.reg.pc,
+      sp: self.reg.sp,
+      interrupt,
+    });
+  }
+
   fn disable_ime(&mut self) {
     self.ime_pending = false;
     self.ime = false;
@@ -109,6 +140,16 @@ impl Cpu {
     self.ime_pending = true;
   }
 
+  pub fn interrupt_snapshot(&self) -> InterruptRegisters {
+    InterruptRegisters {
+      ie: self.bus.iie,
+      iif: self.bus.iif,
+      ime: self.ime,
+      ime_pending: self.ime_pending,
+      state: self.state,
+    }
+  }
+
   pub fn set_interrupt(iif: &mut u8, int: Interrupt) {
     *iif |= 1 << int as u8;
   }
@@ -119,10 +160,12 @@ impl Cpu {
     //Call interrupt handler
     self.reg.dec_sp(2);
     self.bus.ww(self.reg.sp, self.reg.pc, true);
+    self.track_call(INT_JMP_VEC[int], true);
     self.reg.pc = INT_JMP_VEC[int];
     //flip IF bit and disable IME
     self.bus.iif &= !(1 << int);
     self.disable_ime();
+    self.bus.record_event(Event::InterruptServiced { interrupt: int as u8 });
     //Run for 20 cycles
     //TODO spread out cycles???
     for _ in 0..5 { self.cycle(); } 
@@ -158,11 +201,15 @@ impl Cpu {
     //If isn't running, run for 4 cycles and exit
     if self.state != CpuState::Running {
       self.cycle();
+      #[cfg(feature = "dbg-breakpoints")] {
+        self.check_watchpoints()?;
+      }
       return Ok(self.t);
     }
-    //Remember the PC value for breakpoints
-    #[cfg(feature = "dbg-breakpoints")]
-    let pc_value = self.reg.pc;
+    //Check for breakpoints (before the instruction is executed)
+    #[cfg(feature = "dbg-breakpoints")] {
+      self.check_pc_breakpoints()?;
+    }
     //Fetch and execute
     let mut op = self.fetch();
     if op != 0xCB { 
@@ -171,12 +218,19 @@ impl Cpu {
       op = self.fetch();
       cpu_instructions_cb(self, op)?;
     }
-    //Check for breakpoints
+    //Stall while VRAM DMA is copying data
+    while self.bus.vram_dma.is_transferring() {
+      self.cycle();
+    }
+    //Check for watchpoints
     #[cfg(feature = "dbg-breakpoints")] {
-      self.check_pc_breakpoints(pc_value)?;
+      self.check_watchpoints()?;
     }
     //Panic if instruction took less then 4 cycles
     debug_assert!(self.t >= 4);
     Ok(self.t)
   }
 }
+
+impl_save_state_enum!(CpuState { Running, Halt, Stop });
+impl_save_state!(Cpu { reg, bus, state, ime_pending, ime, t });
@@ -0,0 +1,134 @@
+use super::Registers;
+use crate::MemBus;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+  A, F, B, C, D, E, H, L,
+  AF, BC, DE, HL, SP,
+}
+impl Register {
+  fn get(self, reg: &Registers) -> u16 {
+    match self {
+      Self::A => reg.a() as u16,
+      Self::F => reg.f() as u16,
+      Self::B => reg.b() as u16,
+      Self::C => reg.c() as u16,
+      Self::D => reg.d() as u16,
+      Self::E => reg.e() as u16,
+      Self::H => reg.h() as u16,
+      Self::L => reg.l() as u16,
+      Self::AF => reg.af(),
+      Self::BC => reg.bc(),
+      Self::DE => reg.de(),
+      Self::HL => reg.hl(),
+      Self::SP => reg.sp(),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compare {
+  Eq, Ne, Lt, Le, Gt, Ge,
+}
+impl Compare {
+  fn test(self, a: u16, b: u16) -> bool {
+    match self {
+      Self::Eq => a == b,
+      Self::Ne => a != b,
+      Self::Lt => a < b,
+      Self::Le => a <= b,
+      Self::Gt => a > b,
+      Self::Ge => a >= b,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakpointCondition {
+  ///Compare register value
+  Register(Register, Compare, u16),
+  ///Compare value at the memory address
+  Memory(u16, Compare, u8),
+}
+impl BreakpointCondition {
+  fn test(self, reg: &Registers, bus: &MemBus) -> bool {
+    match self {
+      Self::Register(register, cmp, value) => cmp.test(register.get(reg), value),
+      Self::Memory(addr, cmp, value) => cmp.test(bus.rb(addr, false) as u16, value as u16),
+    }
+  }
+}
+
+///PC breakpoint, triggered right before the instruction at `addr` gets executed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+  pub addr: u16,
+  ///Only trigger if this bank is mapped at `addr` (see `Gameboy::get_bank_at`), any bank if `None`
+  pub bank: Option<u16>,
+  ///All conditions must be met for the breakpoint to trigger
+  pub conditions: Vec<BreakpointCondition>,
+  ///Number of hits to ignore before triggering
+  pub ignore_count: u32,
+  ///Number of times the breakpoint was reached (with all conditions met)
+  pub hits: u32,
+}
+impl Breakpoint {
+  pub fn new(addr: u16) -> Self {
+    Self {
+      addr,
+      bank: None,
+      conditions: Vec::new(),
+      ignore_count: 0,
+      hits: 0,
+    }
+  }
+
+  pub fn with_bank(mut self, bank: u16) -> Self {
+    self.bank = Some(bank);
+    self
+  }
+
+  pub fn with_condition(mut self, condition: BreakpointCondition) -> Self {
+    self.conditions.push(condition);
+    self
+  }
+
+  pub fn with_ignore_count(mut self, ignore_count: u32) -> Self {
+    self.ignore_count = ignore_count;
+    self
+  }
+}
+
+#[derive(Default)]
+pub struct Breakpoints {
+  pub list: Vec<Breakpoint>,
+  ///Address of the breakpoint that was just hit, skipped once when resuming
+  skip: Option<u16>,
+}
+impl Breakpoints {
+  ///Returns the bank of the breakpoint that got triggered at `pc` (if any)
+  pub fn check(&mut self, pc: u16, reg: &Registers, bus: &MemBus) -> Option<u16> {
+    if self.skip.take() == Some(pc) || self.list.is_empty() {
+      return None
+    }
+    let bank = bus.bank_at(pc);
+    let mut triggered = false;
+    for breakpoint in self.list.iter_mut() {
+      if
+        (breakpoint.addr != pc) ||
+        breakpoint.bank.map(|x| x != bank).unwrap_or(false) ||
+        !breakpoint.conditions.iter().all(|x| x.test(reg, bus))
+      {
+        continue
+      }
+      breakpoint.hits = breakpoint.hits.saturating_add(1);
+      triggered |= breakpoint.hits > breakpoint.ignore_count;
+    }
+    if triggered {
+      self.skip = Some(pc);
+      Some(bank)
+    } else {
+      None
+    }
+  }
+}
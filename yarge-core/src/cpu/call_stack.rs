@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+
+const MAX_DEPTH: usize = 1024;
+
+///Return address pushed by CALL, RST or an interrupt dispatch
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+  ///Address of the subroutine (or interrupt handler)
+  pub target: u16,
+  pub return_addr: u16,
+  ///SP value after the return address was pushed
+  pub sp: u16,
+  pub interrupt: bool,
+}
+
+///Tracks subroutine calls for debugging (frames are matched to returns by SP)
+#[derive(Default)]
+pub struct CallStack {
+  frames: VecDeque<CallFrame>,
+}
+impl CallStack {
+  pub fn call(&mut self, frame: CallFrame) {
+    if self.frames.len() >= MAX_DEPTH {
+      self.frames.pop_front();
+    }
+    self.frames.push_back(frame);
+  }
+
+  ///Called before RET/RETI pops the return address at `sp`,
+  ///also drops frames abandoned by code that manipulates the stack directly
+  pub fn ret(&mut self, sp: u16) {
+    while self.frames.back().map(|x| x.sp <= sp).unwrap_or(false) {
+      self.frames.pop_back();
+    }
+  }
+
+  pub fn frames(&self) -> &VecDeque<CallFrame> {
+    &self.frames
+  }
+
+  pub fn depth(&self) -> usize {
+    self.frames.len()
+  }
+
+  pub fn clear(&mut self) {
+    self.frames.clear();
+  }
+}
@@ -182,6 +182,7 @@ macro_rules! call_u16 {
     let to = $self.fetch_word();
     $self.cycle();
     $self.push($self.reg.pc);
+    $self.track_call(to, false);
     $self.reg.pc = to;
   };
 } pub(crate) use call_u16;
@@ -205,6 +206,7 @@ macro_rules! call_u16_cond {
 
 macro_rules! ret {
   ($self: expr) => {
+    $self.call_stack.ret($self.reg.sp);
     $self.reg.pc = $self.pop();
     $self.cycle();
   } 
@@ -235,6 +237,7 @@ macro_rules! rst {
   ($self: expr, $addr: expr) => {
     $self.cycle();
     $self.push($self.reg.pc);
+    $self.track_call($addr, false);
     $self.reg.pc = $addr;
   };
 } pub(crate) use rst;
@@ -267,7 +270,9 @@ macro_rules! cpu_halt {
 macro_rules! cpu_stop {
   ($self: expr) => {
     //TODO realistic STOP
-    $self.state = CpuState::Stop;
+    if !$self.bus.try_speed_switch() {
+      $self.state = CpuState::Stop;
+    }
     $self.reg.inc_pc(1);
   };
 } pub(crate) use cpu_stop;
@@ -1,5 +1,6 @@
 mod union;
 use union::U16Union;
+use crate::state::impl_save_state;
 
 //TODO Clean up this fucking mess
 
@@ -106,3 +107,5 @@ impl Registers {
     self.set_f(((z as u8) << 7) | ((n as u8) << 6) | ((h as u8) << 5) | ((c as u8) << 4));
   }
 }
+
+impl_save_state!(Registers { af, bc, de, hl, pc, sp });
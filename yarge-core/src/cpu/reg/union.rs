@@ -1,3 +1,5 @@
+use crate::state::impl_save_state;
+
 #[derive(Clone, Copy)]
 pub struct U16Union {
   value: u16
@@ -48,3 +50,5 @@ impl From<(u8, u8)> for U16Union {
 impl Default for U16Union {
   fn default() -> Self { Self::new(0) }
 }
+
+impl_save_state!(U16Union { value });
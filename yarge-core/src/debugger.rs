@@ -0,0 +1,66 @@
+//! Run control primitives for debuggers
+//!
+//! All of these stop early if an error (like a breakpoint) occurs, or once `max_cycles` T-cycles elapse,
+//! and return `true` only if the target was reached
+
+use std::collections::VecDeque;
+use crate::{Gameboy, Res, cpu::call_stack::CallFrame};
+
+impl Gameboy {
+  fn run_until(&mut self, max_cycles: usize, mut done: impl FnMut(&Self) -> bool) -> Res<bool> {
+    let mut cycles = 0;
+    while cycles < max_cycles {
+      cycles += self.step()?;
+      if done(self) {
+        return Ok(true)
+      }
+    }
+    Ok(false)
+  }
+
+  ///Run for (at least) `cycles` T-cycles, returns the amount of cycles actually executed
+  pub fn run_for_cycles(&mut self, cycles: usize) -> Res<usize> {
+    let mut elapsed = 0;
+    while elapsed < cycles {
+      elapsed += self.step()?;
+    }
+    Ok(elapsed)
+  }
+
+  ///Run until PC reaches `pc`
+  pub fn run_to_pc(&mut self, pc: u16, max_cycles: usize) -> Res<bool> {
+    self.run_until(max_cycles, |gb| gb.cpu.reg.pc == pc)
+  }
+
+  ///Run until the call stack is at most `depth` frames deep
+  pub fn run_to_call_depth(&mut self, depth: usize, max_cycles: usize) -> Res<bool> {
+    if self.cpu.call_stack.depth() <= depth {
+      return Ok(true)
+    }
+    self.run_until(max_cycles, |gb| gb.cpu.call_stack.depth() <= depth)
+  }
+
+  ///Step a single instruction, running subroutines (CALL, RST) and interrupt handlers
+  ///entered during the step until they return
+  pub fn step_over(&mut self, max_cycles: usize) -> Res<bool> {
+    let depth = self.cpu.call_stack.depth();
+    self.step()?;
+    self.run_to_call_depth(depth, max_cycles)
+  }
+
+  ///Run until the current subroutine (or interrupt handler) returns,
+  ///returns `false` right away if the call stack is empty
+  pub fn step_out(&mut self, max_cycles: usize) -> Res<bool> {
+    let depth = self.cpu.call_stack.depth();
+    if depth == 0 {
+      return Ok(false)
+    }
+    self.run_to_call_depth(depth - 1, max_cycles)
+  }
+
+  ///Subroutine calls made so far, innermost last
+  ///(calls made before the last state load are not tracked)
+  pub fn get_call_stack(&self) -> &VecDeque<CallFrame> {
+    self.cpu.call_stack.frames()
+  }
+}
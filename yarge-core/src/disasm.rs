@@ -0,0 +1,264 @@
+//! SM83 disassembler
+//!
+//! Operand placeholders in the tables: `u8`, `u16` (immediate values) and `i8` (signed offset)
+
+use std::{collections::HashMap, fmt};
+
+//(mnemonic, M-cycles, M-cycles if the branch is taken)
+const OPCODES: [(&str, u8, u8); 256] = [
+  ("NOP", 1, 0), ("LD BC,u16", 3, 0), ("LD (BC),A", 2, 0), ("INC BC", 2, 0), ("INC B", 1, 0), ("DEC B", 1, 0), ("LD B,u8", 2, 0), ("RLCA", 1, 0),
+  ("LD (u16),SP", 5, 0), ("ADD HL,BC", 2, 0), ("LD A,(BC)", 2, 0), ("DEC BC", 2, 0), ("INC C", 1, 0), ("DEC C", 1, 0), ("LD C,u8", 2, 0), ("RRCA", 1, 0),
+  ("STOP", 1, 0), ("LD DE,u16", 3, 0), ("LD (DE),A", 2, 0), ("INC DE", 2, 0), ("INC D", 1, 0), ("DEC D", 1, 0), ("LD D,u8", 2, 0), ("RLA", 1, 0),
+  ("JR i8", 3, 0), ("ADD HL,DE", 2, 0), ("LD A,(DE)", 2, 0), ("DEC DE", 2, 0), ("INC E", 1, 0), ("DEC E", 1, 0), ("LD E,u8", 2, 0), ("RRA", 1, 0),
+  ("JR NZ,i8", 2, 3), ("LD HL,u16", 3, 0), ("LD (HL+),A", 2, 0), ("INC HL", 2, 0), ("INC H", 1, 0), ("DEC H", 1, 0), ("LD H,u8", 2, 0), ("DAA", 1, 0),
+  ("JR Z,i8", 2, 3), ("ADD HL,HL", 2, 0), ("LD A,(HL+)", 2, 0), ("DEC HL", 2, 0), ("INC L", 1, 0), ("DEC L", 1, 0), ("LD L,u8", 2, 0), ("CPL", 1, 0),
+  ("JR NC,i8", 2, 3), ("LD SP,u16", 3, 0), ("LD (HL-),A", 2, 0), ("INC SP", 2, 0), ("INC (HL)", 3, 0), ("DEC (HL)", 3, 0), ("LD (HL),u8", 3, 0), ("SCF", 1, 0),
+  ("JR C,i8", 2, 3), ("ADD HL,SP", 2, 0), ("LD A,(HL-)", 2, 0), ("DEC SP", 2, 0), ("INC A", 1, 0), ("DEC A", 1, 0), ("LD A,u8", 2, 0), ("CCF", 1, 0),
+  ("LD B,B", 1, 0), ("LD B,C", 1, 0), ("LD B,D", 1, 0), ("LD B,E", 1, 0), ("LD B,H", 1, 0), ("LD B,L", 1, 0), ("LD B,(HL)", 2, 0), ("LD B,A", 1, 0),
+  ("LD C,B", 1, 0), ("LD C,C", 1, 0), ("LD C,D", 1, 0), ("LD C,E", 1, 0), ("LD C,H", 1, 0), ("LD C,L", 1, 0), ("LD C,(HL)", 2, 0), ("LD C,A", 1, 0),
+  ("LD D,B", 1, 0), ("LD D,C", 1, 0), ("LD D,D", 1, 0), ("LD D,E", 1, 0), ("LD D,H", 1, 0), ("LD D,L", 1, 0), ("LD D,(HL)", 2, 0), ("LD D,A", 1, 0),
+  ("LD E,B", 1, 0), ("LD E,C", 1, 0), ("LD E,D", 1, 0), ("LD E,E", 1, 0), ("LD E,H", 1, 0), ("LD E,L", 1, 0), ("LD E,(HL)", 2, 0), ("LD E,A", 1, 0),
+  ("LD H,B", 1, 0), ("LD H,C", 1, 0), ("LD H,D", 1, 0), ("LD H,E", 1, 0), ("LD H,H", 1, 0), ("LD H,L", 1, 0), ("LD H,(HL)", 2, 0), ("LD H,A", 1, 0),
+  ("LD L,B", 1, 0), ("LD L,C", 1, 0), ("LD L,D", 1, 0), ("LD L,E", 1, 0), ("LD L,H", 1, 0), ("LD L,L", 1, 0), ("LD L,(HL)", 2, 0), ("LD L,A", 1, 0),
+  ("LD (HL),B", 2, 0), ("LD (HL),C", 2, 0), ("LD (HL),D", 2, 0), ("LD (HL),E", 2, 0), ("LD (HL),H", 2, 0), ("LD (HL),L", 2, 0), ("HALT", 1, 0), ("LD (HL),A", 2, 0),
+  ("LD A,B", 1, 0), ("LD A,C", 1, 0), ("LD A,D", 1, 0), ("LD A,E", 1, 0), ("LD A,H", 1, 0), ("LD A,L", 1, 0), ("LD A,(HL)", 2, 0), ("LD A,A", 1, 0),
+  ("ADD A,B", 1, 0), ("ADD A,C", 1, 0), ("ADD A,D", 1, 0), ("ADD A,E", 1, 0), ("ADD A,H", 1, 0), ("ADD A,L", 1, 0), ("ADD A,(HL)", 2, 0), ("ADD A,A", 1, 0),
+  ("ADC A,B", 1, 0), ("ADC A,C", 1, 0), ("ADC A,D", 1, 0), ("ADC A,E", 1, 0), ("ADC A,H", 1, 0), ("ADC A,L", 1, 0), ("ADC A,(HL)", 2, 0), ("ADC A,A", 1, 0),
+  ("SUB A,B", 1, 0), ("SUB A,C", 1, 0), ("SUB A,D", 1, 0), ("SUB A,E", 1, 0), ("SUB A,H", 1, 0), ("SUB A,L", 1, 0), ("SUB A,(HL)", 2, 0), ("SUB A,A", 1, 0),
+  ("SBC A,B", 1, 0), ("SBC A,C", 1, 0), ("SBC A,D", 1, 0), ("SBC A,E", 1, 0), ("SBC A,H", 1, 0), ("SBC A,L", 1, 0), ("SBC A,(HL)", 2, 0), ("SBC A,A", 1, 0),
+  ("AND A,B", 1, 0), ("AND A,C", 1, 0), ("AND A,D", 1, 0), ("AND A,E", 1, 0), ("AND A,H", 1, 0), ("AND A,L", 1, 0), ("AND A,(HL)", 2, 0), ("AND A,A", 1, 0),
+  ("XOR A,B", 1, 0), ("XOR A,C", 1, 0), ("XOR A,D", 1, 0), ("XOR A,E", 1, 0), ("XOR A,H", 1, 0), ("XOR A,L", 1, 0), ("XOR A,(HL)", 2, 0), ("XOR A,A", 1, 0),
+  ("OR A,B", 1, 0), ("OR A,C", 1, 0), ("OR A,D", 1, 0), ("OR A,E", 1, 0), ("OR A,H", 1, 0), ("OR A,L", 1, 0), ("OR A,(HL)", 2, 0), ("OR A,A", 1, 0),
+  ("CP A,B", 1, 0), ("CP A,C", 1, 0), ("CP A,D", 1, 0), ("CP A,E", 1, 0), ("CP A,H", 1, 0), ("CP A,L", 1, 0), ("CP A,(HL)", 2, 0), ("CP A,A", 1, 0),
+  ("RET NZ", 2, 5), ("POP BC", 3, 0), ("JP NZ,u16", 3, 4), ("JP u16", 4, 0), ("CALL NZ,u16", 3, 6), ("PUSH BC", 4, 0), ("ADD A,u8", 2, 0), ("RST 00h", 4, 0),
+  ("RET Z", 2, 5), ("RET", 4, 0), ("JP Z,u16", 3, 4), ("PREFIX CB", 1, 0), ("CALL Z,u16", 3, 6), ("CALL u16", 6, 0), ("ADC A,u8", 2, 0), ("RST 08h", 4, 0),
+  ("RET NC", 2, 5), ("POP DE", 3, 0), ("JP NC,u16", 3, 4), ("ILLEGAL", 1, 0), ("CALL NC,u16", 3, 6), ("PUSH DE", 4, 0), ("SUB A,u8", 2, 0), ("RST 10h", 4, 0),
+  ("RET C", 2, 5), ("RETI", 4, 0), ("JP C,u16", 3, 4), ("ILLEGAL", 1, 0), ("CALL C,u16", 3, 6), ("ILLEGAL", 1, 0), ("SBC A,u8", 2, 0), ("RST 18h", 4, 0),
+  ("LD (FF00+u8),A", 3, 0), ("POP HL", 3, 0), ("LD (FF00+C),A", 2, 0), ("ILLEGAL", 1, 0), ("ILLEGAL", 1, 0), ("PUSH HL", 4, 0), ("AND A,u8", 2, 0), ("RST 20h", 4, 0),
+  ("ADD SP,i8", 4, 0), ("JP HL", 1, 0), ("LD (u16),A", 4, 0), ("ILLEGAL", 1, 0), ("ILLEGAL", 1, 0), ("ILLEGAL", 1, 0), ("XOR A,u8", 2, 0), ("RST 28h", 4, 0),
+  ("LD A,(FF00+u8)", 3, 0), ("POP AF", 3, 0), ("LD A,(FF00+C)", 2, 0), ("DI", 1, 0), ("ILLEGAL", 1, 0), ("PUSH AF", 4, 0), ("OR A,u8", 2, 0), ("RST 30h", 4, 0),
+  ("LD HL,SP+i8", 3, 0), ("LD SP,HL", 2, 0), ("LD A,(u16)", 4, 0), ("EI", 1, 0), ("ILLEGAL", 1, 0), ("ILLEGAL", 1, 0), ("CP A,u8", 2, 0), ("RST 38h", 4, 0),
+];
+const CB_OPCODES: [&str; 256] = [
+  "RLC B", "RLC C", "RLC D", "RLC E", "RLC H", "RLC L", "RLC (HL)", "RLC A",
+  "RRC B", "RRC C", "RRC D", "RRC E", "RRC H", "RRC L", "RRC (HL)", "RRC A",
+  "RL B", "RL C", "RL D", "RL E", "RL H", "RL L", "RL (HL)", "RL A",
+  "RR B", "RR C", "RR D", "RR E", "RR H", "RR L", "RR (HL)", "RR A",
+  "SLA B", "SLA C", "SLA D", "SLA E", "SLA H", "SLA L", "SLA (HL)", "SLA A",
+  "SRA B", "SRA C", "SRA D", "SRA E", "SRA H", "SRA L", "SRA (HL)", "SRA A",
+  "SWAP B", "SWAP C", "SWAP D", "SWAP E", "SWAP H", "SWAP L", "SWAP (HL)", "SWAP A",
+  "SRL B", "SRL C", "SRL D", "SRL E", "SRL H", "SRL L", "SRL (HL)", "SRL A",
+  "BIT 0,B", "BIT 0,C", "BIT 0,D", "BIT 0,E", "BIT 0,H", "BIT 0,L", "BIT 0,(HL)", "BIT 0,A",
+  "BIT 1,B", "BIT 1,C", "BIT 1,D", "BIT 1,E", "BIT 1,H", "BIT 1,L", "BIT 1,(HL)", "BIT 1,A",
+  "BIT 2,B", "BIT 2,C", "BIT 2,D", "BIT 2,E", "BIT 2,H", "BIT 2,L", "BIT 2,(HL)", "BIT 2,A",
+  "BIT 3,B", "BIT 3,C", "BIT 3,D", "BIT 3,E", "BIT 3,H", "BIT 3,L", "BIT 3,(HL)", "BIT 3,A",
+  "BIT 4,B", "BIT 4,C", "BIT 4,D", "BIT 4,E", "BIT 4,H", "BIT 4,L", "BIT 4,(HL)", "BIT 4,A",
+  "BIT 5,B", "BIT 5,C", "BIT 5,D", "BIT 5,E", "BIT 5,H", "BIT 5,L", "BIT 5,(HL)", "BIT 5,A",
+  "BIT 6,B", "BIT 6,C", "BIT 6,D", "BIT 6,E", "BIT 6,H", "BIT 6,L", "BIT 6,(HL)", "BIT 6,A",
+  "BIT 7,B", "BIT 7,C", "BIT 7,D", "BIT 7,E", "BIT 7,H", "BIT 7,L", "BIT 7,(HL)", "BIT 7,A",
+  "RES 0,B", "RES 0,C", "RES 0,D", "RES 0,E", "RES 0,H", "RES 0,L", "RES 0,(HL)", "RES 0,A",
+  "RES 1,B", "RES 1,C", "RES 1,D", "RES 1,E", "RES 1,H", "RES 1,L", "RES 1,(HL)", "RES 1,A",
+  "RES 2,B", "RES 2,C", "RES 2,D", "RES 2,E", "RES 2,H", "RES 2,L", "RES 2,(HL)", "RES 2,A",
+  "RES 3,B", "RES 3,C", "RES 3,D", "RES 3,E", "RES 3,H", "RES 3,L", "RES 3,(HL)", "RES 3,A",
+  "RES 4,B", "RES 4,C", "RES 4,D", "RES 4,E", "RES 4,H", "RES 4,L", "RES 4,(HL)", "RES 4,A",
+  "RES 5,B", "RES 5,C", "RES 5,D", "RES 5,E", "RES 5,H", "RES 5,L", "RES 5,(HL)", "RES 5,A",
+  "RES 6,B", "RES 6,C", "RES 6,D", "RES 6,E", "RES 6,H", "RES 6,L", "RES 6,(HL)", "RES 6,A",
+  "RES 7,B", "RES 7,C", "RES 7,D", "RES 7,E", "RES 7,H", "RES 7,L", "RES 7,(HL)", "RES 7,A",
+  "SET 0,B", "SET 0,C", "SET 0,D", "SET 0,E", "SET 0,H", "SET 0,L", "SET 0,(HL)", "SET 0,A",
+  "SET 1,B", "SET 1,C", "SET 1,D", "SET 1,E", "SET 1,H", "SET 1,L", "SET 1,(HL)", "SET 1,A",
+  "SET 2,B", "SET 2,C", "SET 2,D", "SET 2,E", "SET 2,H", "SET 2,L", "SET 2,(HL)", "SET 2,A",
+  "SET 3,B", "SET 3,C", "SET 3,D", "SET 3,E", "SET 3,H", "SET 3,L", "SET 3,(HL)", "SET 3,A",
+  "SET 4,B", "SET 4,C", "SET 4,D", "SET 4,E", "SET 4,H", "SET 4,L", "SET 4,(HL)", "SET 4,A",
+  "SET 5,B", "SET 5,C", "SET 5,D", "SET 5,E", "SET 5,H", "SET 5,L", "SET 5,(HL)", "SET 5,A",
+  "SET 6,B", "SET 6,C", "SET 6,D", "SET 6,E", "SET 6,H", "SET 6,L", "SET 6,(HL)", "SET 6,A",
+  "SET 7,B", "SET 7,C", "SET 7,D", "SET 7,E", "SET 7,H", "SET 7,L", "SET 7,(HL)", "SET 7,A",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+  None,
+  U8(u8),
+  I8(i8),
+  U16(u16),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+  pub addr: u16,
+  ///Opcode (the second byte for 0xCB-prefixed instructions)
+  pub opcode: u8,
+  pub prefixed: bool,
+  ///Length in bytes
+  pub length: u8,
+  ///Duration in T-cycles
+  pub cycles: u8,
+  ///Duration in T-cycles if the branch is taken (conditional jumps, calls and returns only)
+  pub cycles_branch: Option<u8>,
+  pub operand: Operand,
+  ///Bank of the address referenced by the operand, used to look up symbols
+  pub target_bank: u16,
+  template: &'static str,
+}
+impl Instruction {
+  pub fn mnemonic(&self) -> &'static str {
+    self.template.split(' ').next().unwrap()
+  }
+
+  ///Address referenced by the operand (jump/call target or memory address)
+  pub fn target(&self) -> Option<u16> {
+    match self.operand {
+      Operand::U16(value) => Some(value),
+      Operand::U8(value) if self.template.contains("FF00+u8") => Some(0xFF00 | value as u16),
+      Operand::I8(offset) if self.mnemonic() == "JR" => {
+        Some(self.addr.wrapping_add(2).wrapping_add(offset as u16))
+      },
+      _ => None
+    }
+  }
+
+  ///Format the operands, replacing addresses with names from `symbols`
+  pub fn operands(&self, symbols: Option<&Symbols>) -> String {
+    let operands = match self.template.split_once(' ') {
+      Some((_, operands)) => operands,
+      None => return String::new()
+    };
+    let symbol = self.target().and_then(|addr| {
+      symbols.and_then(|symbols| symbols.get(self.target_bank, addr))
+    });
+    match self.operand {
+      Operand::None => operands.to_string(),
+      Operand::U8(value) => match symbol {
+        Some(name) => operands.replace("FF00+u8", name),
+        None => operands.replace("u8", &format!("${:02X}", value)),
+      },
+      Operand::U16(value) => {
+        operands.replace("u16", &symbol.map(String::from).unwrap_or_else(|| format!("${:04X}", value)))
+      },
+      Operand::I8(offset) => match (symbol, self.target()) {
+        (Some(name), _) => operands.replace("i8", name),
+        (None, Some(target)) => operands.replace("i8", &format!("${:04X}", target)),
+        (None, None) => {
+          let sign = if offset < 0 { '-' } else { '+' };
+          let value = format!("{}${:02X}", sign, offset.unsigned_abs());
+          match operands.contains("+i8") {
+            true => operands.replace("+i8", &value),
+            false => operands.replace("i8", value.trim_start_matches('+')),
+          }
+        }
+      },
+    }
+  }
+
+  ///Format the instruction, replacing addresses with names from `symbols`
+  pub fn format(&self, symbols: Option<&Symbols>) -> String {
+    let operands = self.operands(symbols);
+    match operands.is_empty() {
+      true => self.mnemonic().to_string(),
+      false => format!("{} {}", self.mnemonic(), operands),
+    }
+  }
+}
+impl fmt::Display for Instruction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.format(None))
+  }
+}
+
+///Decode the instruction at `addr`, reading memory with `read`
+pub fn disassemble(addr: u16, read: impl Fn(u16) -> u8) -> Instruction {
+  let opcode = read(addr);
+  if opcode == 0xCB {
+    let opcode = read(addr.wrapping_add(1));
+    let template = CB_OPCODES[opcode as usize];
+    let cycles = match (opcode & 7, opcode >> 6) {
+      (6, 1) => 3, //BIT b,(HL)
+      (6, _) => 4,
+      _ => 2,
+    };
+    return Instruction {
+      addr,
+      opcode,
+      prefixed: true,
+      length: 2,
+      cycles: cycles << 2,
+      cycles_branch: None,
+      operand: Operand::None,
+      target_bank: 0,
+      template,
+    }
+  }
+  let (template, cycles, cycles_branch) = OPCODES[opcode as usize];
+  let read_u8 = || read(addr.wrapping_add(1));
+  let (length, operand) = if template.contains("u16") {
+    (3, Operand::U16(read_u8() as u16 | ((read(addr.wrapping_add(2)) as u16) << 8)))
+  } else if template.contains("u8") {
+    (2, Operand::U8(read_u8()))
+  } else if template.contains("i8") {
+    (2, Operand::I8(read_u8() as i8))
+  } else if opcode == 0x10 {
+    //STOP is followed by an (ignored) byte
+    (2, Operand::None)
+  } else {
+    (1, Operand::None)
+  };
+  Instruction {
+    addr,
+    opcode,
+    prefixed: false,
+    length,
+    cycles: cycles << 2,
+    cycles_branch: (cycles_branch > 0).then_some(cycles_branch << 2),
+    operand,
+    target_bank: 0,
+    template,
+  }
+}
+
+///Symbol names keyed by (bank, address)
+#[derive(Clone, Default)]
+pub struct Symbols {
+  map: HashMap<(u16, u16), String>,
+}
+impl Symbols {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  ///Parse a symbol file in the RGBDS/BGB format (`BB:AAAA Name` lines, `;` comments)
+  pub fn parse(text: &str) -> Self {
+    let mut symbols = Self::new();
+    for line in text.lines() {
+      let line = line.split(';').next().unwrap().trim();
+      let (location, name) = match line.split_once(char::is_whitespace) {
+        Some(x) => x,
+        None => continue
+      };
+      let (bank, addr) = match location.split_once(':') {
+        Some(x) => x,
+        None => continue
+      };
+      if let (Ok(bank), Ok(addr)) = (u16::from_str_radix(bank, 16), u16::from_str_radix(addr, 16)) {
+        symbols.insert(bank, addr, name.trim());
+      }
+    }
+    symbols
+  }
+
+  pub fn insert(&mut self, bank: u16, addr: u16, name: impl Into<String>) {
+    self.map.insert((bank, addr), name.into());
+  }
+
+  ///Get the symbol at `addr` in `bank` (falls back to bank 0)
+  pub fn get(&self, bank: u16, addr: u16) -> Option<&str> {
+    self.map.get(&(bank, addr))
+      .or_else(|| self.map.get(&(0, addr)))
+      .map(|x| x.as_str())
+  }
+
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.map.is_empty()
+  }
+}
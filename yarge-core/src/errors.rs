@@ -14,16 +14,20 @@ pub enum YargeError {
   #[error("Invalid ROM size: {0} bytes")]
   InvalidRomSize(usize),
 
-  // #[error("MMU breakpoint hit: {} at {addr:#06X} with value {value:#04X}", if *is_write { "WRITE" } else { "READ" })]
-  // MmuBreakpoint {
-  //   is_write: bool,
-  //   addr: u16,
-  //   value: u8,
-  // },
-
-  #[error("PC breakpoint hit: instruction {instr:#04X} at {addr:#06X}")]
+  #[error("Invalid boot ROM size: {0} bytes")]
+  InvalidBootRomSize(usize),
+
+  #[error("MMU breakpoint hit: {} at {addr:#06X} with value {value:#04X}", if *is_write { "WRITE" } else { "READ" })]
+  MmuBreakpoint {
+    is_write: bool,
+    addr: u16,
+    value: u8,
+  },
+
+  #[error("PC breakpoint hit: instruction {instr:#04X} at {bank:02X}:{addr:04X}")]
   PcBreakpoint {
     addr: u16,
+    bank: u16,
     instr: u8,
   },
 
@@ -32,6 +36,15 @@ pub enum YargeError {
     addr: u16
   },
 
+  #[error("Invalid save state: {0}")]
+  InvalidSaveState(&'static str),
+
+  #[error("Invalid audio sample rate: {0} Hz")]
+  InvalidSampleRate(usize),
+
+  #[error("Invalid audio chunk size: {0} (must be even and non-zero)")]
+  InvalidChunkSize(usize),
+
   #[error("I/O error")]
   Io {
     #[from] source: std::io::Error
@@ -39,6 +52,6 @@ pub enum YargeError {
 }
 impl YargeError {
   pub fn is_recoverable(&self) -> bool {
-    matches!(*self, Self::PcBreakpoint{..} | Self::LdBreakpoint{..})
+    matches!(*self, Self::PcBreakpoint{..} | Self::MmuBreakpoint{..} | Self::LdBreakpoint{..})
   }
 }
@@ -1,22 +1,184 @@
-//WIP: Event logging system
-//TODO finish it and remove the "dbg-emit-ppu-events" feature
-
-// #[derive(Clone, Copy, Debug)]
-// pub enum Event {
-//   PpuLxInc {
-//     lx: u8,
-//     ly: u8,
-//     cycles: u16,
-//   }
-// }
-
-// pub struct EventRecorder {
-//   list: Vec<Event>
-// }
-
-// impl EventRecorder {
-//   #[inline]
-//   pub fn record(&mut self, event: Event) {
-//     self.list.push(event);
-//   }
-// }
+//! Event logging system
+//!
+//! Components report what they're doing as [`Event`]s, which get stored (along with a cycle timestamp)
+//! in a ring buffer while recording is enabled (see `Gameboy::enable_event_recording`).
+//! Recorded events can be exported as JSON lines, which can be loaded directly by `yarge-ppu-evt-viewer`
+
+use std::{collections::VecDeque, io::{self, Write}};
+use arrayvec::ArrayVec;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+  ///PPU mode changed (0 = HBlank, 1 = VBlank, 2 = OAM search, 3 = pixel transfer)
+  PpuMode { mode: u8, from: u8, ly: u8 },
+  ///Pixel pushed to the display
+  PpuLxInc { lx: u8, ly: u8, cycles: u16 },
+  ///Pixel transfer finished for the current line
+  PpuLineEnd { ly: u8, cycles: u16 },
+  ///Last line of VBlank finished
+  PpuFrameEnd,
+  SpriteFetchStart { lx: u8, ly: u8, cycles: u16 },
+  SpriteFetchEnd { lx: u8, ly: u8, cycles: u16 },
+  SpriteFetcherState { ly: u8, cycles: u16, state: u8, prev: u8 },
+  BgFetcherState { ly: u8, cycles: u16, state: u8, prev: u8 },
+  ///Interrupt flag set in IF (0 = VBlank, 1 = STAT, 2 = Timer, 3 = Serial, 4 = Joypad)
+  InterruptRequested { interrupt: u8 },
+  ///CPU jumped to the interrupt handler
+  InterruptServiced { interrupt: u8 },
+  OamDma { source: u16 },
+  ///CGB VRAM DMA started (`length` in bytes)
+  VramDma { source: u16, destination: u16, length: u16, hblank: bool },
+  TimerOverflow,
+  ///Cartridge ROM bank mapped at 0x4000-0x7FFF changed
+  RomBankSwitch { bank: u16 },
+  ///Cartridge RAM bank mapped at 0xA000-0xBFFF changed
+  RamBankSwitch { bank: u8 },
+}
+impl Event {
+  ///Event type name (as used by `yarge-ppu-evt-viewer`)
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::PpuMode { .. } => "CHANGE_MODE",
+      Self::PpuLxInc { .. } => "LX_INC",
+      Self::PpuLineEnd { .. } => "PX_FETCH_LINE_END",
+      Self::PpuFrameEnd => "FRAME_END",
+      Self::SpriteFetchStart { .. } => "SPR_FETCH_START",
+      Self::SpriteFetchEnd { .. } => "SPR_FETCH_END",
+      Self::SpriteFetcherState { .. } => "SPR_FETCHER_STATE_CHANGE",
+      Self::BgFetcherState { .. } => "BG_FETCHER_STATE_CHANGE",
+      Self::InterruptRequested { .. } => "INTERRUPT_REQUESTED",
+      Self::InterruptServiced { .. } => "INTERRUPT_SERVICED",
+      Self::OamDma { .. } => "OAM_DMA",
+      Self::VramDma { .. } => "VRAM_DMA",
+      Self::TimerOverflow => "TIMER_OVERFLOW",
+      Self::RomBankSwitch { .. } => "ROM_BANK_SWITCH",
+      Self::RamBankSwitch { .. } => "RAM_BANK_SWITCH",
+    }
+  }
+
+  ///Event arguments as name/value pairs
+  pub fn args(&self) -> ArrayVec<(&'static str, u32), 4> {
+    let mut args = ArrayVec::new();
+    match *self {
+      Self::PpuMode { mode, from, ly } => {
+        args.extend([("mode", mode as u32), ("from", from as u32), ("ly", ly as u32)]);
+      },
+      Self::PpuLxInc { lx, ly, cycles } |
+      Self::SpriteFetchStart { lx, ly, cycles } |
+      Self::SpriteFetchEnd { lx, ly, cycles } => {
+        args.extend([("lx", lx as u32), ("ly", ly as u32), ("cycles", cycles as u32)]);
+      },
+      Self::PpuLineEnd { ly, cycles } => {
+        args.extend([("ly", ly as u32), ("cycles", cycles as u32)]);
+      },
+      Self::SpriteFetcherState { ly, cycles, state, prev } |
+      Self::BgFetcherState { ly, cycles, state, prev } => {
+        args.extend([("cycles", cycles as u32), ("ly", ly as u32), ("next", state as u32), ("prev", prev as u32)]);
+      },
+      Self::InterruptRequested { interrupt } |
+      Self::InterruptServiced { interrupt } => {
+        args.push(("interrupt", interrupt as u32));
+      },
+      Self::OamDma { source } => {
+        args.push(("source", source as u32));
+      },
+      Self::VramDma { source, destination, length, hblank } => {
+        args.extend([
+          ("source", source as u32),
+          ("destination", destination as u32),
+          ("length", length as u32),
+          ("hblank", hblank as u32),
+        ]);
+      },
+      Self::RomBankSwitch { bank } => {
+        args.push(("bank", bank as u32));
+      },
+      Self::RamBankSwitch { bank } => {
+        args.push(("bank", bank as u32));
+      },
+      Self::PpuFrameEnd | Self::TimerOverflow => (),
+    }
+    args
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordedEvent {
+  ///T-cycles elapsed since recording started
+  pub cycle: u64,
+  pub event: Event,
+}
+
+///Ring buffer of recorded events, the oldest ones are dropped once it's full
+pub struct EventRecorder {
+  events: VecDeque<RecordedEvent>,
+  capacity: usize,
+}
+impl EventRecorder {
+  pub fn new(capacity: usize) -> Self {
+    assert!(capacity > 0, "Capacity must be non-zero");
+    Self {
+      events: VecDeque::with_capacity(capacity.min(0x10000)),
+      capacity,
+    }
+  }
+
+  #[inline]
+  pub fn record(&mut self, cycle: u64, event: Event) {
+    if self.events.len() >= self.capacity {
+      self.events.pop_front();
+    }
+    self.events.push_back(RecordedEvent { cycle, event });
+  }
+
+  ///Iterate over recorded events, oldest first
+  pub fn iter(&self) -> impl Iterator<Item = &RecordedEvent> {
+    self.events.iter()
+  }
+
+  pub fn len(&self) -> usize {
+    self.events.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.events.is_empty()
+  }
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  pub fn clear(&mut self) {
+    self.events.clear();
+  }
+
+  ///Write all recorded events as JSON lines, for example:
+  ///`{"cycle":1234,"type":"LX_INC","lx":1,"ly":0,"cycles":94}`
+  pub fn write_json_lines(&self, out: &mut impl Write) -> io::Result<()> {
+    for RecordedEvent { cycle, event } in &self.events {
+      write!(out, "{{\"cycle\":{},\"type\":\"{}\"", cycle, event.name())?;
+      for (name, value) in event.args() {
+        write!(out, ",\"{}\":{}", name, value)?;
+      }
+      writeln!(out, "}}")?;
+    }
+    Ok(())
+  }
+}
+
+///Events reported by a component during a tick, collected by the `MemBus`
+#[derive(Default)]
+pub(crate) struct EventQueue {
+  pub enabled: bool,
+  events: Vec<Event>,
+}
+impl EventQueue {
+  #[inline]
+  pub fn push(&mut self, event: Event) {
+    if self.enabled {
+      self.events.push(event);
+    }
+  }
+
+  pub fn drain(&mut self) -> impl Iterator<Item = Event> + '_ {
+    self.events.drain(..)
+  }
+}
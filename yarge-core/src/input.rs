@@ -1,5 +1,10 @@
 use enumflags2::{bitflags, BitFlags, make_bitflags};
-use crate::cpu::{Cpu, Interrupt};
+use crate::{
+  Res,
+  cpu::{Cpu, Interrupt},
+  io_registers::JoypadRegisters,
+  state::{SaveState, StateReader, check_state},
+};
 
 #[bitflags]
 #[repr(u8)]
@@ -11,8 +16,9 @@ pub enum JoypSelect {
 
 #[bitflags]
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Key {
+  #[default]
   Right  = 1 << 0,
   Left   = 1 << 1,
   Up     = 1 << 2,
@@ -22,9 +28,6 @@ pub enum Key {
   Select = 1 << 6,
   Start  = 1 << 7,
 }
-impl Default for Key {
-  fn default() -> Self { Self::Right } //0
-}
 
 fn filter(state: BitFlags<Key>) -> BitFlags<Key> {
   const UP_DOWN: BitFlags<Key>    = make_bitflags!(Key::{Up | Down });
@@ -39,10 +42,24 @@ fn filter(state: BitFlags<Key>) -> BitFlags<Key> {
   state
 }
 
+///Size of a single SGB command packet in bytes
+pub const SGB_PACKET_SIZE: usize = 16;
+
 pub struct Input {
   select: BitFlags<JoypSelect>,
   key_state: BitFlags<Key>,
   interrupt_flag: bool,
+  //SGB packet transfer
+  pub sgb_enabled: bool,
+  sgb_packet: [u8; SGB_PACKET_SIZE],
+  sgb_bit: u8,
+  sgb_receiving: bool,
+  sgb_pulse_ready: bool,
+  sgb_received: Option<[u8; SGB_PACKET_SIZE]>,
+  //SGB multiplayer (MLT_REQ)
+  sgb_players: u8,
+  sgb_player: u8,
+  sgb_player_lock: bool,
 }
 impl Input {
   pub fn new() -> Self {
@@ -50,6 +67,15 @@ impl Input {
       select: BitFlags::default(),
       key_state: BitFlags::default(),
       interrupt_flag: false,
+      sgb_enabled: false,
+      sgb_packet: [0; SGB_PACKET_SIZE],
+      sgb_bit: 0,
+      sgb_receiving: false,
+      sgb_pulse_ready: false,
+      sgb_received: None,
+      sgb_players: 1,
+      sgb_player: 0,
+      sgb_player_lock: true,
     }
   }
 
@@ -79,18 +105,91 @@ impl Input {
   }
 
   pub fn get_joyp(&self) -> u8 {
-    if self.select.bits() == 0 { return 0xFF; }
+    if self.select.bits() == 0 {
+      //In multiplayer mode, the lower bits contain the current controller id (0xF - player 1, 0xE - player 2, etc.)
+      return 0xF0 | (0xF - self.sgb_player)
+    }
+    //Only the first controller is connected
+    let key_state = match self.sgb_player {
+      0 => self.key_state,
+      _ => BitFlags::default(),
+    };
     let mut output = 0;
     if self.select.contains(JoypSelect::Direction) {
-      output |= filter(self.key_state).bits();
+      output |= filter(key_state).bits();
     }
     if self.select.contains(JoypSelect::Action) {
-      output |= self.key_state.bits() >> 4;
+      output |= key_state.bits() >> 4;
     }
     ((!self.select.bits() & 0b11) << 4) | ((!output) & 0xF) | 0xC0
   }
+  pub fn snapshot(&self) -> JoypadRegisters {
+    JoypadRegisters {
+      select_direction: self.select.contains(JoypSelect::Direction),
+      select_action: self.select.contains(JoypSelect::Action),
+      joyp: self.get_joyp(),
+    }
+  }
   pub fn set_joyp(&mut self, value: u8) {
     self.select = BitFlags::from_bits_truncate(!(value >> 4));
+    if self.sgb_enabled {
+      self.sgb_write(value);
+    }
+  }
+
+  ///Handle SGB packet transfer pulses
+  ///
+  ///A transfer starts with a reset pulse (P14 and P15 low), followed by 128 data bits
+  ///(P14 low - 0, P15 low - 1, LSB first) and a stop bit (0), with P14 and P15 released between pulses
+  fn sgb_write(&mut self, value: u8) {
+    match (value >> 4) & 3 {
+      //Reset pulse
+      0b00 => {
+        self.sgb_packet = [0; SGB_PACKET_SIZE];
+        self.sgb_bit = 0;
+        self.sgb_receiving = true;
+        self.sgb_pulse_ready = false;
+      },
+      //Data pulse
+      0b01 | 0b10 if self.sgb_receiving && self.sgb_pulse_ready => {
+        let bit = (value & 0x20) == 0;
+        self.sgb_pulse_ready = false;
+        if self.sgb_bit as usize == SGB_PACKET_SIZE * 8 {
+          //Stop bit, has to be 0 (otherwise the packet is discarded)
+          if !bit {
+            self.sgb_received = Some(self.sgb_packet);
+          }
+          self.sgb_receiving = false;
+        } else {
+          self.sgb_packet[self.sgb_bit as usize >> 3] |= (bit as u8) << (self.sgb_bit & 7);
+          self.sgb_bit += 1;
+        }
+      },
+      //Both lines released
+      0b11 => {
+        self.sgb_pulse_ready = true;
+        //P15 going high switches to the next controller
+        if (self.sgb_players > 1) && !self.sgb_player_lock {
+          self.sgb_player = (self.sgb_player + 1) & (self.sgb_players - 1);
+          self.sgb_player_lock = true;
+        }
+      },
+      _ => (),
+    }
+    if (value & 0x20) == 0 {
+      self.sgb_player_lock = false;
+    }
+  }
+
+  ///Take the most recently received SGB packet
+  pub fn take_sgb_packet(&mut self) -> Option<[u8; SGB_PACKET_SIZE]> {
+    self.sgb_received.take()
+  }
+
+  ///Set the amount of connected controllers (1, 2 or 4), used by MLT_REQ
+  pub fn set_sgb_players(&mut self, players: u8) {
+    self.sgb_players = players;
+    self.sgb_player = 0;
   }
 
   pub fn tick(&mut self, iif: &mut u8) {
@@ -100,3 +199,40 @@ impl Input {
     }
   }
 }
+
+impl SaveState for Input {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    self.select.bits().save_state(out);
+    self.key_state.bits().save_state(out);
+    self.interrupt_flag.save_state(out);
+    self.sgb_enabled.save_state(out);
+    self.sgb_packet.save_state(out);
+    self.sgb_bit.save_state(out);
+    self.sgb_receiving.save_state(out);
+    self.sgb_pulse_ready.save_state(out);
+    self.sgb_received.save_state(out);
+    self.sgb_players.save_state(out);
+    self.sgb_player.save_state(out);
+    self.sgb_player_lock.save_state(out);
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    let (mut select, mut key_state) = (0u8, 0u8);
+    select.load_state(input)?;
+    key_state.load_state(input)?;
+    self.select = BitFlags::from_bits_truncate(select);
+    self.key_state = BitFlags::from_bits_truncate(key_state);
+    self.interrupt_flag.load_state(input)?;
+    self.sgb_enabled.load_state(input)?;
+    self.sgb_packet.load_state(input)?;
+    self.sgb_bit.load_state(input)?;
+    self.sgb_receiving.load_state(input)?;
+    self.sgb_pulse_ready.load_state(input)?;
+    self.sgb_received.load_state(input)?;
+    self.sgb_players.load_state(input)?;
+    self.sgb_player.load_state(input)?;
+    self.sgb_player_lock.load_state(input)?;
+    check_state(self.sgb_bit as usize <= SGB_PACKET_SIZE * 8, "invalid sgb packet bit")?;
+    check_state(matches!(self.sgb_players, 1 | 2 | 4), "invalid sgb player count")?;
+    check_state(self.sgb_player < self.sgb_players, "invalid sgb player")
+  }
+}
@@ -0,0 +1,193 @@
+//! Typed snapshot of the IO registers (FF00-FF7F, FFFF) and the internal state behind them
+//!
+//! See `Gameboy::get_io_registers`
+
+use crate::cpu::CpuState;
+pub use crate::ppu::ppu_registers::{Lcdc, StatInterrupts, PpuMode};
+
+#[derive(Clone, Copy, Debug)]
+pub struct IoRegisters {
+  pub ppu: PpuRegisters,
+  pub timers: TimerRegisters,
+  pub interrupts: InterruptRegisters,
+  pub joypad: JoypadRegisters,
+  pub serial: SerialRegisters,
+  pub apu: ApuRegisters,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PpuRegisters {
+  pub lcdc: Lcdc,
+  ///STAT interrupt sources (bits 3-6)
+  pub stat: StatInterrupts,
+  pub mode: PpuMode,
+  ///STAT bit 2 (LY == LYC)
+  pub lyc_eq: bool,
+  ///LY as seen by the CPU
+  pub ly: u8,
+  pub lyc: u8,
+  pub scy: u8,
+  pub scx: u8,
+  pub wy: u8,
+  pub wx: u8,
+  ///Internal window line counter
+  pub wly: u8,
+  pub bgp: u8,
+  pub obp: (u8, u8),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TimerRegisters {
+  ///16-bit internal counter, DIV is the upper byte
+  pub div: u16,
+  pub tima: u8,
+  pub tma: u8,
+  ///TAC bit 2
+  pub enable: bool,
+  ///TAC bits 0-1 (input clock select)
+  pub rate: u8,
+}
+impl TimerRegisters {
+  ///TIMA increment frequency in Hz (in normal speed mode)
+  pub fn frequency(&self) -> u32 {
+    [4096, 262144, 65536, 16384][(self.rate & 3) as usize]
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct InterruptRegisters {
+  pub ie: u8,
+  pub iif: u8,
+  pub ime: bool,
+  ///EI was executed, IME gets set after the next instruction
+  pub ime_pending: bool,
+  pub state: CpuState,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct JoypadRegisters {
+  ///JOYP bit 4 cleared (d-pad selected)
+  pub select_direction: bool,
+  ///JOYP bit 5 cleared (buttons selected)
+  pub select_action: bool,
+  ///Value returned by JOYP reads
+  pub joyp: u8,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SerialRegisters {
+  pub sb: u8,
+  ///SC bit 7
+  pub transfer: bool,
+  ///SC bit 0
+  pub internal_clock: bool,
+  ///Bits shifted so far in the current transfer
+  pub bits: u8,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LengthState {
+  pub timer: u16,
+  pub enable: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EnvelopeState {
+  pub start_volume: u8,
+  pub increase: bool,
+  pub period: u8,
+  ///Current volume (0-15)
+  pub volume: u8,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SweepState {
+  pub period: u8,
+  pub negate: bool,
+  pub shift: u8,
+}
+
+///CH1 and CH2 (CH2 has no sweep)
+#[derive(Clone, Copy, Debug)]
+pub struct SquareChannelState {
+  pub enabled: bool,
+  pub dac_enabled: bool,
+  ///Wave duty (0: 12.5%, 1: 25%, 2: 50%, 3: 75%)
+  pub duty: u8,
+  pub duty_position: u8,
+  pub frequency: u16,
+  pub freq_timer: u16,
+  pub length: LengthState,
+  pub envelope: EnvelopeState,
+  pub sweep: Option<SweepState>,
+}
+impl SquareChannelState {
+  ///Tone frequency in Hz
+  pub fn frequency_hz(&self) -> f32 {
+    131072. / (2048 - self.frequency as u32) as f32
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WaveChannelState {
+  pub enabled: bool,
+  pub dac_enabled: bool,
+  ///NR32 output level (0: mute, 1: 100%, 2: 50%, 3: 25%)
+  pub output_level: u8,
+  ///Current sample index (0-31)
+  pub position: u8,
+  pub sample: u8,
+  pub frequency: u16,
+  pub freq_timer: u16,
+  pub length: LengthState,
+  pub wave_ram: [u8; 16],
+}
+impl WaveChannelState {
+  ///Frequency of the whole 32-sample waveform in Hz
+  pub fn frequency_hz(&self) -> f32 {
+    65536. / (2048 - self.frequency as u32) as f32
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct NoiseChannelState {
+  pub enabled: bool,
+  pub dac_enabled: bool,
+  pub shift: u8,
+  ///7-bit LFSR mode
+  pub width_short: bool,
+  pub divider: u8,
+  pub freq_timer: u16,
+  pub lfsr: u16,
+  pub length: LengthState,
+  pub envelope: EnvelopeState,
+}
+impl NoiseChannelState {
+  ///LFSR clock frequency in Hz
+  pub fn frequency_hz(&self) -> f32 {
+    let divisor = if self.divider == 0 { 0.5 } else { self.divider as f32 };
+    524288. / divisor / (2 << self.shift) as f32
+  }
+}
+
+///NR50/NR51 settings of a single output
+#[derive(Clone, Copy, Debug)]
+pub struct TerminalState {
+  pub volume: u8,
+  pub vin: bool,
+  pub channels: [bool; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ApuRegisters {
+  ///NR52 bit 7
+  pub enabled: bool,
+  ///Frame sequencer step (0-7)
+  pub sequencer: u8,
+  pub channel1: SquareChannelState,
+  pub channel2: SquareChannelState,
+  pub channel3: WaveChannelState,
+  pub channel4: NoiseChannelState,
+  pub left: TerminalState,
+  pub right: TerminalState,
+}
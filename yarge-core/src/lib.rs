@@ -8,7 +8,10 @@ pub(crate) mod apu;
 pub(crate) mod timers;
 pub(crate) mod input;
 pub(crate) mod serial;
+pub(crate) mod sgb;
 pub(crate) mod event;
+pub(crate) mod state;
+pub(crate) mod rewind;
 
 pub(crate) use bus::MemBus;
 pub(crate) use cpu::Cpu;
@@ -16,23 +19,45 @@ pub(crate) use ppu::Ppu;
 pub(crate) use apu::Apu;
 pub(crate) use timers::Timers;
 pub(crate) use input::Input;
+pub(crate) use sgb::Sgb;
 
 //Modules
 pub mod consts;
+pub mod renderer;
+pub mod disasm;
+pub mod io_registers;
 mod errors;
+mod model;
+mod builder;
 mod api;
+mod debugger;
 
 //Re-exports
-pub use api::*;
 pub use input::Key;
 pub use cpu::CpuState;
+pub use cpu::call_stack::CallFrame;
+pub use disasm::{Instruction, Symbols};
+pub use io_registers::IoRegisters;
 pub use errors::YargeError;
-pub use apu::AudioDevice;
+pub use model::Model;
+pub use builder::GameboyBuilder;
+pub use renderer::{Renderer, RgbaImage};
+pub use ppu::oam::{OamObject, OamFlags};
+pub use apu::{AudioDevice, AudioFilter};
+pub use serial::{SerialLink, LocalLink, TcpLink};
 pub use bus::cartridge::RomHeader;
+pub use event::{Event, EventRecorder, RecordedEvent};
+#[cfg(feature = "dbg-breakpoints")]
+pub use bus::watchpoints::Watchpoint;
+#[cfg(feature = "dbg-breakpoints")]
+pub use cpu::breakpoints::{Breakpoint, BreakpointCondition, Register, Compare};
 
 //Types
 pub(crate) type Res<T> = Result<T, YargeError>;
 
+use state::{SaveState, StateReader, STATE_MAGIC, STATE_VERSION};
+use rewind::RewindBuffer;
+
 //Tests
 #[cfg(test)]
 mod tests;
@@ -40,18 +65,24 @@ mod tests;
 ///Gameboy emulator
 pub struct Gameboy {
   cpu: Cpu,
+  rewind: Option<RewindBuffer>,
   #[cfg(feature = "dbg-logging-file")] 
   log_file: Option<std::fs::File>,
 }
 impl Gameboy {
   pub fn new() -> Self {
     Self {
-      cpu: Cpu::new(),
+      cpu: Cpu::new(Model::default()),
+      rewind: None,
       #[cfg(feature = "dbg-logging-file")]
       log_file: None,
     }
   }
 
+  pub fn builder() -> GameboyBuilder {
+    GameboyBuilder::new()
+  }
+
   pub fn init(&mut self) {
     #[cfg(feature = "dbg-logging-file")] {
       use std::{fs, io::Write};
@@ -60,7 +91,6 @@ impl Gameboy {
       file.write_all(b"").unwrap();
       drop(file);
       self.log_file = Some(fs::OpenOptions::new()
-        .write(true)
         .append(true)
         .open(LOG_PATH)
         .unwrap());
@@ -74,24 +104,141 @@ impl Gameboy {
   }
   
   pub fn skip_bootrom(&mut self) {
+    //Already skipped by `load_rom` if there's no boot ROM for the model
+    if self.cpu.bus.bios_disabled && !self.cpu.bus.has_boot_rom() {
+      return
+    }
     if self.cpu.bus.bios_disabled {
       panic!("Attempt to skip bios while not in bootrom");
     }
+    let bus = &mut self.cpu.bus;
+    let [af, bc, de, hl] = bus.model.post_boot_registers(bus.cgb_mode, bus.header().header_checksum);
     let reg = &mut self.cpu.reg;
     reg.pc = 0x0100;
     reg.sp = 0xFFFE;
-    reg.set_af(0x01B0);
-    reg.set_bc(0x0013);
-    reg.set_de(0x00D8);
-    reg.set_hl(0x014D);
-    self.cpu.bus.bios_disabled = true;
+    reg.set_af(af);
+    reg.set_bc(bc);
+    reg.set_de(de);
+    reg.set_hl(hl);
+    bus.skip_bootrom();
+  }
+
+  ///There's no built-in boot ROM for some models, start right after it instead
+  fn skip_missing_bootrom(&mut self) {
+    if !self.cpu.bus.bios_disabled && !self.cpu.bus.has_boot_rom() {
+      self.skip_bootrom();
+    }
   }
   
   pub fn reset(&mut self) {
     //MAYBE: option to keep rom?
     let device = self.cpu.bus.apu.device.take();
-    self.cpu = Cpu::new();
+    let audio_settings = self.cpu.bus.apu.settings();
+    let link = self.cpu.bus.serial.link.take();
+    let boot_rom = self.cpu.bus.boot_rom.take();
+    let events = self.cpu.bus.set_event_recorder(None);
+    #[cfg(feature = "dbg-breakpoints")]
+    let watchpoints = std::mem::take(&mut self.cpu.bus.watchpoints.list);
+    #[cfg(feature = "dbg-breakpoints")]
+    let breakpoints = std::mem::take(&mut self.cpu.breakpoints.list);
+    self.cpu = Cpu::new(self.cpu.bus.model);
+    #[cfg(feature = "dbg-breakpoints")] {
+      self.cpu.bus.watchpoints.list = watchpoints;
+      self.cpu.breakpoints.list = breakpoints;
+    }
     self.cpu.bus.apu.device = device;
+    self.cpu.bus.apu.apply_settings(audio_settings);
+    self.cpu.bus.serial.link = link;
+    self.cpu.bus.boot_rom = boot_rom;
+    self.cpu.bus.set_event_recorder(events);
+    if let Some(rewind) = self.rewind.as_mut() {
+      rewind.clear();
+    }
+  }
+
+  ///Serialize the state of the whole machine, excluding ROM data
+  ///(see the `state` module for format description)
+  pub fn save_state(&self) -> Vec<u8> {
+    let header = self.cpu.bus.header();
+    let mut out = Vec::new();
+    out.extend_from_slice(STATE_MAGIC);
+    STATE_VERSION.save_state(&mut out);
+    header.global_checksum.save_state(&mut out);
+    header.mbc_type.save_state(&mut out);
+    self.cpu.bus.model.save_state(&mut out);
+    self.cpu.save_state(&mut out);
+    out
+  }
+
+  ///Restore a state created with `save_state`, the same ROM must be loaded beforehand
+  pub fn load_state(&mut self, data: &[u8]) -> Res<()> {
+    let header = self.cpu.bus.header();
+    let mut input = StateReader::new(data);
+    if input.take(STATE_MAGIC.len())? != STATE_MAGIC {
+      return Err(YargeError::InvalidSaveState("not a save state"));
+    }
+    let (mut version, mut checksum, mut mbc_type, mut model) = (0u16, 0u16, 0u8, Model::Dmg);
+    version.load_state(&mut input)?;
+    if version != STATE_VERSION {
+      return Err(YargeError::InvalidSaveState("unsupported version"));
+    }
+    checksum.load_state(&mut input)?;
+    mbc_type.load_state(&mut input)?;
+    if (checksum != header.global_checksum) || (mbc_type != header.mbc_type) {
+      return Err(YargeError::InvalidSaveState("ROM checksum mismatch"));
+    }
+    model.load_state(&mut input)?;
+    if model != self.cpu.bus.model {
+      return Err(YargeError::InvalidSaveState("hardware model mismatch"));
+    }
+    //Keep a backup in case the data turns out to be corrupted midway
+    let mut backup = Vec::new();
+    self.cpu.save_state(&mut backup);
+    let result = self.cpu.load_state(&mut input).and_then(|_| {
+      match input.is_empty() {
+        true => Ok(()),
+        false => Err(YargeError::InvalidSaveState("unexpected trailing data")),
+      }
+    });
+    if result.is_err() {
+      self.cpu.load_state(&mut StateReader::new(&backup)).unwrap();
+    } else {
+      self.cpu.call_stack.clear();
+    }
+    result
+  }
+
+  ///Record a snapshot every `interval` frames (in `run_for_frame`),
+  ///dropping the oldest ones once `memory_limit` bytes are used
+  pub fn enable_rewind(&mut self, interval: usize, memory_limit: usize) {
+    self.rewind = Some(RewindBuffer::new(interval, memory_limit));
+  }
+
+  pub fn disable_rewind(&mut self) {
+    self.rewind = None;
+  }
+
+  ///Go back to the most recent snapshot in the rewind buffer,
+  ///returns `false` if the rewind buffer is empty (or disabled)
+  pub fn rewind_step(&mut self) -> Res<bool> {
+    match self.rewind.as_mut().and_then(|rewind| rewind.pop()) {
+      Some(state) => {
+        self.load_state(&state)?;
+        Ok(true)
+      },
+      None => Ok(false)
+    }
+  }
+
+  ///Record emulation events (PPU, interrupts, DMA, timers, bank switches),
+  ///keeping at most `capacity` most recent ones (see `get_event_recorder`)
+  pub fn enable_event_recording(&mut self, capacity: usize) {
+    self.cpu.bus.set_event_recorder(Some(EventRecorder::new(capacity)));
+  }
+
+  ///Stop recording events, returning the ones recorded so far
+  pub fn disable_event_recording(&mut self) -> Option<EventRecorder> {
+    self.cpu.bus.set_event_recorder(None)
   }
 
   #[cfg(feature = "dbg-logging")]
@@ -102,16 +249,18 @@ impl Gameboy {
       "A: {a:02X} F: {f:02X} B: {b:02X} C: {c:02X} \
       D: {d:02X} E: {e:02X} H: {h:02X} L: {l:02X} \
       SP: {sp:04X} PC: 00:{pc:04X} \
-      ({rb0:02X} {rb1:02X} {rb2:02X} {rb3:02X})", 
+      ({rb0:02X} {rb1:02X} {rb2:02X} {rb3:02X}) \
+      {instr}", 
       a = r.a(), f = r.f(), 
       b = r.b(), c = r.c(), 
       d = r.d(), e = r.e(), 
       h = r.h(), l = r.l(), 
       sp = r.sp, pc = r.pc,
-      rb0 = m.rb(r.pc + 0, true),
-      rb1 = m.rb(r.pc + 1, true), 
-      rb2 = m.rb(r.pc + 2, true),
-      rb3 = m.rb(r.pc + 3, true)
+      rb0 = m.rb(r.pc, false),
+      rb1 = m.rb(r.pc.wrapping_add(1), false),
+      rb2 = m.rb(r.pc.wrapping_add(2), false),
+      rb3 = m.rb(r.pc.wrapping_add(3), false),
+      instr = self.disassemble(r.pc),
     );
     #[cfg(feature = "dbg-logging-file")] {
       use std::io::Write;
@@ -132,9 +281,15 @@ impl Gameboy {
 
   pub fn run_for_frame(&mut self) -> Res<()> {
     use consts::CYCLES_PER_FRAME;
+    //Snapshot the start of the frame, so that rewinding always goes back at least one frame
+    if self.rewind.as_mut().map(|rewind| rewind.frame_start()).unwrap_or(false) {
+      let state = self.save_state();
+      self.rewind.as_mut().unwrap().push(state);
+    }
     self.reset_frame_ready();
     let mut cycles: usize = 0;
-    while !(self.get_frame_ready() || cycles >= CYCLES_PER_FRAME) {
+    let max_cycles = CYCLES_PER_FRAME << self.cpu.bus.double_speed as usize;
+    while !(self.get_frame_ready() || cycles >= max_cycles) {
       cycles += self.step()?;
     }
     Ok(())
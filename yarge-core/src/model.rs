@@ -0,0 +1,100 @@
+use std::{fmt, str::FromStr};
+use crate::state::impl_save_state_enum;
+
+///Emulated hardware model
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Model {
+  ///Original Game Boy (early boot ROM revision)
+  Dmg0,
+  ///Original Game Boy
+  #[default]
+  Dmg,
+  ///Game Boy Pocket
+  Mgb,
+  ///Super Game Boy
+  Sgb,
+  ///Super Game Boy 2
+  Sgb2,
+  ///Game Boy Color
+  ///
+  ///CGB mode is only enabled for ROMs with the CGB flag set in the header (byte 0x143),
+  ///other ROMs run in DMG compatibility mode
+  Cgb,
+}
+impl Model {
+  pub const ALL: [Model; 6] = [Model::Dmg0, Model::Dmg, Model::Mgb, Model::Sgb, Model::Sgb2, Model::Cgb];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Dmg0 => "dmg0",
+      Self::Dmg  => "dmg",
+      Self::Mgb  => "mgb",
+      Self::Sgb  => "sgb",
+      Self::Sgb2 => "sgb2",
+      Self::Cgb  => "cgb",
+    }
+  }
+
+  pub fn is_sgb(self) -> bool {
+    matches!(self, Self::Sgb | Self::Sgb2)
+  }
+
+  pub fn is_cgb(self) -> bool {
+    self == Self::Cgb
+  }
+
+  ///The built-in boot ROM is DMG-only,
+  ///other models need a boot ROM image (see [`crate::GameboyBuilder::boot_rom`]) or start with the boot ROM skipped
+  pub fn has_builtin_boot_rom(self) -> bool {
+    matches!(self, Self::Dmg0 | Self::Dmg | Self::Mgb)
+  }
+
+  ///Size of the boot ROM image in bytes
+  pub fn boot_rom_size(self) -> usize {
+    match self {
+      Self::Cgb => 0x900,
+      _ => 0x100,
+    }
+  }
+
+  ///CPU registers (AF, BC, DE, HL) after the boot ROM is done
+  pub(crate) fn post_boot_registers(self, cgb_mode: bool, header_checksum: u8) -> [u16; 4] {
+    //H and C flags depend on the header checksum
+    let flags = if header_checksum != 0 { 0xB0 } else { 0x80 };
+    match self {
+      Self::Dmg0 => [0x0100, 0xFF13, 0x00C1, 0x8403],
+      Self::Dmg  => [0x0100 | flags, 0x0013, 0x00D8, 0x014D],
+      Self::Mgb  => [0xFF00 | flags, 0x0013, 0x00D8, 0x014D],
+      Self::Sgb  => [0x0100, 0x0014, 0x0000, 0xC060],
+      Self::Sgb2 => [0xFF00, 0x0014, 0x0000, 0xC060],
+      Self::Cgb if cgb_mode => [0x1180, 0x0000, 0xFF56, 0x000D],
+      Self::Cgb  => [0x1180, 0x0000, 0x0008, 0x007C],
+    }
+  }
+
+  ///Internal DIV counter value after the boot ROM is done
+  pub(crate) fn post_boot_div(self) -> u16 {
+    match self {
+      Self::Dmg0 => 0x182C,
+      Self::Dmg | Self::Mgb => 0xABC8,
+      Self::Sgb | Self::Sgb2 => 0xD85C,
+      Self::Cgb  => 0x1EA0,
+    }
+  }
+}
+impl fmt::Display for Model {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+impl FromStr for Model {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Self::ALL.into_iter()
+      .find(|model| model.name().eq_ignore_ascii_case(s))
+      .ok_or_else(|| format!("unknown model: {s}"))
+  }
+}
+
+//Variant indices are stored in save states, new variants go at the end
+impl_save_state_enum!(Model { Dmg, Cgb, Dmg0, Mgb, Sgb, Sgb2 });
@@ -1,19 +1,27 @@
-mod oam;
-mod ppu_registers;
+pub mod oam;
+pub mod ppu_registers;
 mod fifo;
+mod palette;
 mod util;
 
 use fifo::{Fetcher, BackgroundFetcher, SpriteFetcher, FifoPixel};
-use oam::{OamMemory, OamBuffer};
+use oam::{OamMemory, OamBuffer, OamObject};
+use palette::CgbPalette;
 use ppu_registers::{Lcdc, PpuMode, StatInterrupts};
 use crate::{
-  consts::{VRAM_SIZE, WIDTH, FB_SIZE, OBJECTS_PER_LINE},
-  cpu::{Cpu, Interrupt}
+  consts::{VRAM_SIZE, WIDTH, HEIGHT, FB_SIZE, OBJECTS_PER_LINE},
+  cpu::{Cpu, Interrupt},
+  event::{Event, EventQueue},
+  io_registers::PpuRegisters,
+  state::{impl_save_state, check_state},
+  Res,
 };
 
 pub struct Ppu {
   pub display: Box<[u8; FB_SIZE]>,
+  pub display_cgb: Box<[u16; FB_SIZE]>,
   pub frame_ready: bool,
+  pub cgb_mode: bool,
   pub bgp: u8,
   pub obp: (u8, u8),
   pub lyc: u8,
@@ -29,7 +37,10 @@ pub struct Ppu {
   hblank_len: usize,
   cycles: usize,
   mode: PpuMode,
-  vram: Box<[u8; VRAM_SIZE]>,
+  vram: Box<[[u8; VRAM_SIZE]; 2]>,
+  vram_bank: u8,
+  bg_palette: CgbPalette,
+  obj_palette: CgbPalette,
   oam: OamMemory,
   lcdc: Lcdc,
   display_cleared: bool,
@@ -42,6 +53,9 @@ pub struct Ppu {
   suspend_bg_fetcher: bool,
   fetched_sprites: usize,
   pub mmu_oam_locked: bool,
+  hblank_start: bool,
+  vblank_start: bool,
+  pub events: EventQueue,
   /*HACK*/ stat_r_lyc_eq: bool,
 }
 impl Ppu {
@@ -55,7 +69,9 @@ impl Ppu {
         }
         display
       },
+      display_cgb: Box::new([0x7FFF; FB_SIZE]),
       frame_ready: false,
+      cgb_mode: false,
       bgp: 0b11_10_01_00,
       obp: (0b11_10_01_00, 0b11_10_01_00),
       lyc: 0,
@@ -71,7 +87,10 @@ impl Ppu {
       hblank_len: 204,
       cycles: 0,
       mode: PpuMode::default(),
-      vram: Box::new([0; VRAM_SIZE]),
+      vram: Box::new([[0; VRAM_SIZE]; 2]),
+      vram_bank: 0,
+      bg_palette: CgbPalette::new(),
+      obj_palette: CgbPalette::new(),
       oam: OamMemory::new(),
       lcdc: Lcdc::default(),
       display_cleared: false,
@@ -84,6 +103,9 @@ impl Ppu {
       suspend_bg_fetcher: false,
       fetched_sprites: 0,
       mmu_oam_locked: false,
+      hblank_start: false,
+      vblank_start: false,
+      events: EventQueue::default(),
       stat_r_lyc_eq: false
     }
   }
@@ -105,6 +127,11 @@ impl Ppu {
     self.stat_intr.set_from_u8(value >> 3);
   }
 
+  ///True in HBlank or while the LCD is off (when HBlank VRAM DMA transfers are allowed to copy a block)
+  pub fn in_hblank(&self) -> bool {
+    !self.lcdc.enable_display || self.mode == PpuMode::HBlank
+  }
+
   fn oam_blocked(&self) -> bool {
     #[cfg(feature = "dbg-ly-stub")] { return false }
     if !self.lcdc.enable_display { return false }
@@ -127,19 +154,70 @@ impl Ppu {
 
   pub fn read_vram(&self, addr: u16, blocking: bool) -> u8 {
     if blocking && self.vram_blocked() { return 0xFF }
-    self.vram[(addr - 0x8000) as usize]
+    self.vram[self.vram_bank as usize][(addr - 0x8000) as usize]
   }
   pub fn write_vram(&mut self, addr: u16, value: u8, blocking: bool) {
     if blocking && self.vram_blocked() { return }
-    self.vram[(addr - 0x8000) as usize] = value;
+    self.vram[self.vram_bank as usize][(addr - 0x8000) as usize] = value;
+  }
+
+  pub fn get_vbk(&self) -> u8 {
+    0xFE | self.vram_bank
+  }
+  pub fn set_vbk(&mut self, value: u8) {
+    self.vram_bank = value & 1;
+  }
+
+  pub fn get_bcps(&self) -> u8 {
+    self.bg_palette.get_spec()
+  }
+  pub fn set_bcps(&mut self, value: u8) {
+    self.bg_palette.set_spec(value);
+  }
+  pub fn read_bcpd(&self, blocking: bool) -> u8 {
+    if blocking && self.vram_blocked() { return 0xFF }
+    self.bg_palette.get_data()
+  }
+  pub fn write_bcpd(&mut self, value: u8, blocking: bool) {
+    if blocking && self.vram_blocked() { return }
+    self.bg_palette.set_data(value);
+  }
+
+  pub fn get_ocps(&self) -> u8 {
+    self.obj_palette.get_spec()
+  }
+  pub fn set_ocps(&mut self, value: u8) {
+    self.obj_palette.set_spec(value);
+  }
+  pub fn read_ocpd(&self, blocking: bool) -> u8 {
+    if blocking && self.vram_blocked() { return 0xFF }
+    self.obj_palette.get_data()
+  }
+  pub fn write_ocpd(&mut self, value: u8, blocking: bool) {
+    if blocking && self.vram_blocked() { return }
+    self.obj_palette.set_data(value);
   }
   
   fn mode(&mut self, mode: PpuMode) {
-    #[cfg(feature = "dbg-emit-ppu-events")] {
-      println!("PPU_EVENT CHANGE_MODE mode={} from={} ly={}", mode as u8, self.mode as u8, self.ly);
-    }
+    self.events.push(Event::PpuMode { mode: mode as u8, from: self.mode as u8, ly: self.ly });
     self.cycles = 0;
     self.mode = mode;
+    if mode == PpuMode::HBlank && self.lcdc.enable_display {
+      self.hblank_start = true;
+    }
+    if mode == PpuMode::VBlank {
+      self.vblank_start = true;
+    }
+  }
+
+  ///Returns true once after entering HBlank (used by VRAM DMA)
+  pub fn take_hblank_start(&mut self) -> bool {
+    std::mem::take(&mut self.hblank_start)
+  }
+
+  ///Returns true once after entering VBlank (used by SGB)
+  pub fn take_vblank_start(&mut self) -> bool {
+    std::mem::take(&mut self.vblank_start)
   }
 
   fn check_stat(&mut self, iif: &mut u8) {
@@ -171,22 +249,33 @@ impl Ppu {
           self.spr_fetcher.start(*sprite, self.ly);
           self.fetched_sprites += 1;
           debug_assert!(self.fetched_sprites <= OBJECTS_PER_LINE, "Fetched too much sprites");
-          #[cfg(feature = "dbg-emit-ppu-events")] {
-            println!("PPU_EVENT SPR_FETCH_START lx={} ly={} cycles={}", self.lx, self.ly, self.cycles);
-          }
+          self.events.push(Event::SpriteFetchStart { lx: self.lx, ly: self.ly, cycles: self.cycles as u16 });
         }
       }
     }
     //Tick spr_fetcher if it's not done fetching stuff
     if self.spr_fetcher.fetching {
-      #[cfg(feature = "dbg-emit-ppu-events")] let prev_state = self.spr_fetcher.state;
-      self.spr_fetcher.tick(&self.lcdc, &self.vram);
-      #[cfg(feature = "dbg-emit-ppu-events")] if prev_state != self.spr_fetcher.state {
-        println!("PPU_EVENT SPR_FETCHER_STATE_CHANGE cycles={} ly={} next={} prev={}", self.cycles, self.ly, self.spr_fetcher.state as u8, prev_state as u8);
+      let prev = self.spr_fetcher.state;
+      self.spr_fetcher.tick(&self.lcdc, &self.vram, self.cgb_mode);
+      if prev != self.spr_fetcher.state {
+        self.events.push(Event::SpriteFetcherState {
+          ly: self.ly,
+          cycles: self.cycles as u16,
+          state: self.spr_fetcher.state as u8,
+          prev: prev as u8,
+        });
       }
     }
   }
 
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.vram_bank <= 1, "invalid vram bank")?;
+    check_state(self.ly <= 153 && self.wly as usize <= HEIGHT, "invalid ppu line")?;
+    check_state((self.lx as usize) < WIDTH, "invalid ppu pixel")?;
+    check_state(self.mode != PpuMode::PxTransfer || (self.ly as usize) < HEIGHT, "pixel transfer outside of the display")?;
+    check_state(self.mode != PpuMode::PxTransfer || self.cycles < 376, "pixel transfer took too long")
+  }
+
   fn set_ly_and_update(&mut self, ly: u8) {
     self.ly = ly;
     self.mmio_ly = ly;
@@ -224,6 +313,7 @@ impl Ppu {
     if !self.lcdc.enable_display {
       if !self.display_cleared {
         *self.display = [0; FB_SIZE];
+        *self.display_cgb = [0x7FFF; FB_SIZE];
         //TODO finish fixing mrdo
         //reset ly start
         //println!("comparison ly {} => {}, then mmio/ly reset", self.compare_ly, self.ly);
@@ -263,9 +353,7 @@ impl Ppu {
           self.cycles = 0;
           self.set_ly_and_update(self.ly + 1);
           if self.ly >= 155 {
-            #[cfg(feature = "dbg-emit-ppu-events")] {
-              println!("PPU_EVENT FRAME_END");
-            }
+            self.events.push(Event::PpuFrameEnd);
             self.wly = 0;
             self.set_ly_and_update(0);
             self.mode(PpuMode::OamSearch);
@@ -294,7 +382,7 @@ impl Ppu {
         }
       },
       PpuMode::PxTransfer => { //This is probably extremely inaccurate!
-        let mut push_color: Option<u8> = None;
+        let mut bg_pixel: Option<FifoPixel> = None;
 
         //TODO implement sprite fetch abort
         if (self.oam_buffer.len() != 0) && self.lcdc.enable_obj {
@@ -303,9 +391,7 @@ impl Ppu {
 
           //Un-suspend bg fetcher if the sprite fetcher is done fetching the sprite
           if self.suspend_bg_fetcher && !self.spr_fetcher.fetching {
-            #[cfg(feature = "dbg-emit-ppu-events")] {
-              println!("PPU_EVENT SPR_FETCH_END lx={} ly={} cycles={}", self.lx, self.ly, self.cycles);
-            }
+            self.events.push(Event::SpriteFetchEnd { lx: self.lx, ly: self.ly, cycles: self.cycles as u16 });
             self.suspend_bg_fetcher = false;
             //Re-check In case two sprites overlap
             self.do_sprite_fetcher_stuff(); 
@@ -326,63 +412,81 @@ impl Ppu {
           if !self.bg_fetcher.is_window() && self.window_in_ly() && ((self.lx + 7) >= self.wx) {
             self.bg_fetcher.switch_to_window();
           }
-          self.bg_fetcher.tick(&self.lcdc, &self.vram);
+          let prev = self.bg_fetcher.state;
+          self.bg_fetcher.tick(&self.lcdc, &self.vram, self.cgb_mode);
+          if prev != self.bg_fetcher.state {
+            self.events.push(Event::BgFetcherState {
+              ly: self.ly,
+              cycles: self.cycles as u16,
+              state: self.bg_fetcher.state as u8,
+              prev: prev as u8,
+            });
+          }
           //If bg fetcher has something
           if self.bg_fetcher.len() > 0 {
             //Shift out background pixel
-            let FifoPixel { color, .. } = self.bg_fetcher.pop().unwrap();
+            let mut pixel = self.bg_fetcher.pop().unwrap();
             //Discard bg pixel if needed
             if !self.bg_fetcher.is_window() && self.to_discard > 0 {
               self.to_discard -= 1;
             } else {
               //Set color to 0 if bg is disabled
-              if !self.lcdc.enable_bg {
-                push_color = Some(0);
-              } else {
-                push_color = Some(color);
+              //(In CGB mode, this bit only disables bg priority)
+              if !(self.lcdc.enable_bg || self.cgb_mode) {
+                pixel.color = 0;
               }
+              bg_pixel = Some(pixel);
             }
           }
         }
 
         //Pixel mixing
-        let spr_pixel = if (self.spr_fetcher.len() > 0) && push_color.is_some() {
-          let spr_pixel = self.spr_fetcher.pop().unwrap();
-          if
-            (spr_pixel.color > 0) && 
-            (!spr_pixel.priority || (push_color.unwrap() == 0))
-          {
-            push_color = Some(spr_pixel.color);
-            Some(spr_pixel)
-          } else { None }
-        } else { None };
-
-        //Map to pal
-        push_color = if let Some(color) = push_color {
-          let pal = if let Some(pixel) = spr_pixel {
-            let pal = if !pixel.pal { self.obp.0 } else { self.obp.1 };
-            pal & 0b11111100 //Index 0 is always transparent
-          } else {
-            self.bgp
-          };
-          Some((pal >> (color << 1)) & 0b11)
-        } else { None };
+        let spr_pixel = match bg_pixel {
+          Some(bg_pixel) if self.spr_fetcher.len() > 0 => {
+            let spr_pixel = self.spr_fetcher.pop().unwrap();
+            let bg_priority = if self.cgb_mode {
+              self.lcdc.enable_bg && (spr_pixel.priority || bg_pixel.priority)
+            } else {
+              spr_pixel.priority
+            };
+            if
+              (spr_pixel.color > 0) && 
+              (!bg_priority || (bg_pixel.color == 0))
+            {
+              Some(spr_pixel)
+            } else { None }
+          },
+          _ => None
+        };
 
         //Push pixel to the display
-        if let Some(color) = push_color {
-          //Get display addr and set pixel color
+        if let Some(bg_pixel) = bg_pixel {
+          //Get display addr
           let addr = (self.ly as usize * WIDTH) + self.lx as usize;
-          self.display[addr] = color;
+          //Map to pal and set pixel color
+          if self.cgb_mode {
+            let color = match spr_pixel {
+              Some(pixel) => self.obj_palette.color(pixel.cgb_pal, pixel.color),
+              None => self.bg_palette.color(bg_pixel.cgb_pal, bg_pixel.color),
+            };
+            self.display_cgb[addr] = color;
+            //Keep the shade buffer usable for frontends that only support DMG output
+            self.display[addr] = util::rgb555_shade(color);
+          } else {
+            let (pal, color) = if let Some(pixel) = spr_pixel {
+              let pal = if !pixel.pal { self.obp.0 } else { self.obp.1 };
+              (pal & 0b11111100, pixel.color) //Index 0 is always transparent
+            } else {
+              (self.bgp, bg_pixel.color)
+            };
+            self.display[addr] = (pal >> (color << 1)) & 0b11;
+          }
           //Move to the next pixel
           self.lx += 1;
-          #[cfg(feature = "dbg-emit-ppu-events")] {
-            println!("PPU_EVENT LX_INC lx={} ly={} cycles={}", self.lx, self.ly, self.cycles);
-          }
+          self.events.push(Event::PpuLxInc { lx: self.lx, ly: self.ly, cycles: self.cycles as u16 });
           //End PxTransfer if lx > WIDTH
           if self.lx >= WIDTH as u8 { 
-            #[cfg(feature = "dbg-emit-ppu-events")] {
-              println!("PPU_EVENT PX_FETCH_LINE_END ly={} cycles={}", self.ly, self.cycles);
-            }
+            self.events.push(Event::PpuLineEnd { ly: self.ly, cycles: self.cycles as u16 });
             #[cfg(debug_assertions)]
             if self.fetched_sprites != self.oam_buffer.len() {
               eprintln!("Fetched {} sprites out of {}", self.fetched_sprites, self.oam_buffer.len());
@@ -424,8 +528,36 @@ impl Ppu {
     }
   }
 
-  pub fn render_tileset(&self) {
-    todo!() //TODO render_tileset
+  ///Raw contents of a VRAM bank (bank 1 is only used in CGB mode)
+  pub fn get_vram(&self, bank: usize) -> &[u8; VRAM_SIZE] {
+    &self.vram[bank & 1]
+  }
+  pub fn get_bg_palette(&self) -> &CgbPalette {
+    &self.bg_palette
+  }
+  pub fn get_obj_palette(&self) -> &CgbPalette {
+    &self.obj_palette
+  }
+  pub fn get_oam(&self) -> &[OamObject; 40] {
+    &self.oam.objects
+  }
+
+  pub fn snapshot(&self) -> PpuRegisters {
+    PpuRegisters {
+      lcdc: self.lcdc,
+      stat: self.stat_intr,
+      mode: self.mode,
+      lyc_eq: self.stat_r_lyc_eq,
+      ly: self.mmio_ly,
+      lyc: self.lyc,
+      scy: self.scy,
+      scx: self.scx,
+      wy: self.wy,
+      wx: self.wx,
+      wly: self.wly,
+      bgp: self.bgp,
+      obp: self.obp,
+    }
   }
 }
 impl Default for Ppu {
@@ -433,3 +565,23 @@ impl Default for Ppu {
     Self::new()
   }
 }
+
+impl_save_state!(Ppu {
+  display, display_cgb,
+  frame_ready, cgb_mode,
+  bgp, obp, lyc, scy, scx, wy, wx,
+  wly, ly, mmio_ly, compare_ly, lx,
+  hblank_len, cycles, mode,
+  vram, vram_bank,
+  bg_palette, obj_palette,
+  oam, lcdc,
+  display_cleared,
+  bg_fetcher, spr_fetcher,
+  to_discard,
+  stat_intr, stat_prev,
+  oam_buffer,
+  suspend_bg_fetcher, fetched_sprites,
+  mmu_oam_locked,
+  hblank_start, vblank_start,
+  stat_r_lyc_eq,
+} validate validate_state);
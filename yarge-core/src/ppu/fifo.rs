@@ -2,13 +2,15 @@ mod background;
 mod sprite;
 pub use background::BackgroundFetcher;
 pub use sprite::SpriteFetcher;
+use crate::{Res, state::{impl_save_state, impl_save_state_enum, check_state}};
 
 #[derive(Default, Clone, Copy)]
 pub struct FifoPixel {
   pub color: u8,
   pub priority: bool,
   pub pal: bool,
-  //pal: u8, (CGB ONLY)
+  pub cgb_pal: u8, //CGB ONLY
+  pub oam_id: u8,  //CGB ONLY
 }
 impl FifoPixel {
   pub fn from_color(color: u8) -> Self {
@@ -16,25 +18,29 @@ impl FifoPixel {
     Self {
       color, 
       priority: false,
-      pal: false
-      //..Default::default()
+      pal: false,
+      cgb_pal: 0,
+      oam_id: 0,
     }
   }
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.color < 4, "invalid pixel color")
+  }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
 #[repr(u8)]
 pub enum FetcherState {
+  #[default]
   ReadTileId = 0,
   ReadTileDataLow = 1,
   ReadTileDataHigh = 2,
   PushToFifo = 3,
 }
-impl Default for FetcherState {
-  fn default() -> Self { Self::ReadTileId }
-}
-
 pub trait Fetcher {
   fn pop(&mut self) -> Option<FifoPixel>;
   fn len(&self) -> usize;
 }
+
+impl_save_state!(FifoPixel { color, priority, pal, cgb_pal, oam_id } validate validate_state);
+impl_save_state_enum!(FetcherState { ReadTileId, ReadTileDataLow, ReadTileDataHigh, PushToFifo });
@@ -1,11 +1,14 @@
 use arraydeque::ArrayDeque;
+use bit_reverse::LookupReverse;
 use seq_macro::seq;
-use crate::consts::VRAM_SIZE;
+use crate::Res;
+use crate::consts::{VRAM_SIZE, HEIGHT};
 use crate::ppu::ppu_registers::Lcdc;
 use crate::ppu::util;
+use crate::state::{impl_save_state, impl_save_state_enum, check_state};
 use super::{Fetcher, FetcherState, FifoPixel};
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum FetcherLayer {
   Background, Window
@@ -13,7 +16,7 @@ pub enum FetcherLayer {
 
 pub struct BackgroundFetcher {
   fifo: ArrayDeque<FifoPixel, 8>,
-  state: FetcherState,
+  pub state: FetcherState,
   cycle: bool,
   scx: u8, 
   scy: u8,
@@ -21,6 +24,7 @@ pub struct BackgroundFetcher {
   ly: u8,
   offset: u16,
   tile_idx: u16,
+  tile_attr: u8, //CGB ONLY
   tile_data: (u8, u8),
   layer: FetcherLayer,
   penalty: u8, //sleep
@@ -37,6 +41,7 @@ impl BackgroundFetcher {
       ly: 0,
       offset: 0,
       tile_idx: 0,
+      tile_attr: 0,
       tile_data: (0, 0),
       layer: FetcherLayer::Background,
       penalty: 6,
@@ -81,17 +86,27 @@ impl BackgroundFetcher {
     self.cycle = true; 
     self.state = FetcherState::default();
   }
-  pub fn tick(&mut self, lcdc: &Lcdc, vram: &[u8; VRAM_SIZE]) {
+  fn validate_state(&self) -> Res<()> {
+    check_state((self.tile_idx as usize) < VRAM_SIZE / 16, "invalid bg tile index")?;
+    check_state(self.offset <= 32 && self.wly as usize <= HEIGHT, "invalid bg fetcher position")
+  }
+  pub fn tick(&mut self, lcdc: &Lcdc, vram: &[[u8; VRAM_SIZE]; 2], cgb_mode: bool) {
     if self.penalty > 0 {
       self.penalty -= 1;
       return;
     }
+    let bank = ((self.tile_attr >> 3) & 1) as usize;
     let fetch_addr = || {
       let tile = self.tile_idx as usize * 16;
-      match self.layer { 
-        FetcherLayer::Background => tile + (2 * (self.ly.wrapping_add(self.scy) & 7)) as usize,
-        FetcherLayer::Window     => tile + (2 * ((self.wly as usize) & 7)),
+      let mut row = match self.layer { 
+        FetcherLayer::Background => (self.ly.wrapping_add(self.scy) & 7) as usize,
+        FetcherLayer::Window     => (self.wly as usize) & 7,
+      };
+      //Flip Y
+      if (self.tile_attr & 0x40) != 0 {
+        row = 7 - row;
       }
+      tile + (2 * row)
     };
     match self.state {
       FetcherState::ReadTileId if self.cycle => {
@@ -113,25 +128,38 @@ impl BackgroundFetcher {
             FetcherLayer::Window => lcdc.win_tilemap_addr() - 0x8000,
           }
         };
-        self.tile_idx = lcdc.transform_tile_index(vram[addr as usize]);
+        self.tile_idx = lcdc.transform_tile_index(vram[0][addr as usize]);
+        //Tile attributes are stored in the same location in VRAM bank 1
+        self.tile_attr = if cgb_mode { vram[1][addr as usize] } else { 0 };
         self.cycle = false;
         self.state = FetcherState::ReadTileDataLow;
       },
       FetcherState::ReadTileDataLow if self.cycle => {
-        self.tile_data.0 = vram[fetch_addr()];
+        self.tile_data.0 = vram[bank][fetch_addr()];
         self.cycle = false;
         self.state = FetcherState::ReadTileDataHigh;
       },
       FetcherState::ReadTileDataHigh if self.cycle => {
-        self.tile_data.1 = vram[fetch_addr() + 1];
+        self.tile_data.1 = vram[bank][fetch_addr() + 1];
         self.cycle = false;
         self.state = FetcherState::PushToFifo;
       },
       FetcherState::PushToFifo => {
         if self.fifo.is_empty() {
+          //Flip X
+          if (self.tile_attr & 0x20) != 0 {
+            self.tile_data.0 = LookupReverse::swap_bits(self.tile_data.0);
+            self.tile_data.1 = LookupReverse::swap_bits(self.tile_data.1);
+          }
           let colors = util::spr_line(self.tile_data);
           seq!(N in 0..8 {
-            self.fifo.push_back(FifoPixel::from_color(colors[N])).unwrap();
+            self.fifo.push_back(FifoPixel {
+              color: colors[N],
+              priority: (self.tile_attr & 0x80) != 0,
+              pal: false,
+              cgb_pal: self.tile_attr & 7,
+              oam_id: 0,
+            }).unwrap();
           });
           self.offset += 1;
           self.state = FetcherState::ReadTileId;
@@ -149,3 +177,11 @@ impl Fetcher for BackgroundFetcher {
     self.fifo.len()
   }
 }
+
+impl_save_state_enum!(FetcherLayer { Background, Window });
+impl_save_state!(BackgroundFetcher {
+  fifo, state, cycle,
+  scx, scy, wly, ly,
+  offset, tile_idx, tile_attr, tile_data,
+  layer, penalty,
+} validate validate_state);
@@ -2,6 +2,8 @@ use arraydeque::ArrayDeque;
 use bit_reverse::LookupReverse;
 use crate::consts::VRAM_SIZE;
 use super::{Fetcher, FifoPixel, FetcherState};
+use crate::Res;
+use crate::state::{impl_save_state, check_state};
 use crate::ppu::{
   oam::OamObject,
   ppu_registers::Lcdc,
@@ -44,7 +46,14 @@ impl SpriteFetcher {
     self.state = FetcherState::ReadTileId;
     self.fetching = true;
   }
-  pub fn tick(&mut self, lcdc: &Lcdc, vram: &[u8; VRAM_SIZE]) {
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.tile_idx <= 0xFF, "invalid sprite tile index")?;
+    //The fetched object has to overlap the current line
+    let y_offset = (self.ly as usize + 16).checked_sub(self.object.y as usize);
+    check_state(!self.fetching || matches!(y_offset, Some(0..=15)), "invalid sprite fetcher line")
+  }
+  pub fn tick(&mut self, lcdc: &Lcdc, vram: &[[u8; VRAM_SIZE]; 2], cgb_mode: bool) {
+    let bank = if cgb_mode { self.object.flags.cgb_bank() } else { 0 };
     let fetch_addr = || {
       let mut y_offset = (self.ly as usize + 16) - self.object.y as usize;
       let mut tile_idx = self.tile_idx;
@@ -67,12 +76,12 @@ impl SpriteFetcher {
         self.state = FetcherState::ReadTileDataLow;
       },
       FetcherState::ReadTileDataLow if self.cycle => {
-        self.tile_data.0 = vram[fetch_addr()];
+        self.tile_data.0 = vram[bank][fetch_addr()];
         self.cycle = false;
         self.state = FetcherState::ReadTileDataHigh;
       },
       FetcherState::ReadTileDataHigh if self.cycle => {
-        self.tile_data.1 = vram[fetch_addr() + 1];
+        self.tile_data.1 = vram[bank][fetch_addr() + 1];
         self.cycle = false;
         self.state = FetcherState::PushToFifo;
       },
@@ -98,12 +107,18 @@ impl SpriteFetcher {
         }
         let colors = util::spr_line(self.tile_data);
         for (i, color) in colors.iter().enumerate().take(take) {
-          //Only paint on top if the bg is transparent
-          if self.fifo[i].color == 0 {
+          //Only paint on top if the pixel below is transparent
+          //(or, in CGB mode, if it belongs to an object with higher OAM index)
+          let on_top = (self.fifo[i].color == 0) || (
+            cgb_mode && (*color != 0) && (self.object.id < self.fifo[i].oam_id)
+          );
+          if on_top {
             self.fifo[i] = FifoPixel {
               color: *color,
               priority: self.object.flags.priority,
-              pal: self.object.flags.palette
+              pal: self.object.flags.palette,
+              cgb_pal: self.object.flags.cgb_palette(),
+              oam_id: self.object.id,
             };
           }
         };
@@ -122,3 +137,5 @@ impl Fetcher for SpriteFetcher {
     self.fifo.len()
   }
 }
+
+impl_save_state!(SpriteFetcher { fifo, state, cycle, object, tile_idx, tile_data, ly, fetching } validate validate_state);
@@ -1,13 +1,22 @@
 use crate::consts::{WIDTH, OBJECTS_PER_LINE};
 use super::ppu_registers::Lcdc;
+use crate::{Res, state::{impl_save_state, check_state}};
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
 pub struct OamFlags {
   pub priority: bool, //BG/Sprite order
   pub flip_y: bool,
   pub flip_x: bool,
   pub palette: bool, //DMG ONLY
-  pub hi_bits: u8,
+  pub hi_bits: u8,    //CGB ONLY (VRAM bank and palette)
+}
+impl OamFlags {
+  pub fn cgb_bank(&self) -> usize {
+    ((self.hi_bits >> 3) & 1) as usize
+  }
+  pub fn cgb_palette(&self) -> u8 {
+    self.hi_bits & 7
+  }
 }
 impl From<OamFlags> for u8 {
   fn from(flags: OamFlags) -> u8 { 
@@ -30,7 +39,7 @@ impl From<u8> for OamFlags {
   }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
 pub struct OamObject {
   pub y: u8,
   pub x: u8,
@@ -124,7 +133,15 @@ impl OamBuffer {
   pub fn get(&self, index: usize) -> Option<&OamObject> {
     (index < self.length).then(|| &self.buffer[index])
   }
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.length <= OBJECTS_PER_LINE, "invalid oam buffer length")
+  }
 }
 impl Default for OamBuffer {
   fn default() -> Self { Self::new() }
 }
+
+impl_save_state!(OamFlags { priority, flip_y, flip_x, palette, hi_bits });
+impl_save_state!(OamObject { y, x, tile, flags, id });
+impl_save_state!(OamMemory { objects });
+impl_save_state!(OamBuffer { buffer, length } validate validate_state);
@@ -0,0 +1,52 @@
+use crate::{Res, state::{impl_save_state, check_state}};
+
+///CGB color palette memory, accessed through BCPS/BCPD or OCPS/OCPD
+///
+///Contains 8 palettes with 4 colors each, colors are stored as 15-bit RGB (little endian)
+pub struct CgbPalette {
+  data: [u8; 64],
+  index: u8,
+  auto_increment: bool,
+}
+impl CgbPalette {
+  pub fn new() -> Self {
+    Self {
+      data: [0xFF; 64],
+      index: 0,
+      auto_increment: false,
+    }
+  }
+
+  pub fn get_spec(&self) -> u8 {
+    ((self.auto_increment as u8) << 7) | 0x40 | self.index
+  }
+  pub fn set_spec(&mut self, value: u8) {
+    self.auto_increment = (value & 0x80) != 0;
+    self.index = value & 0x3F;
+  }
+
+  pub fn get_data(&self) -> u8 {
+    self.data[self.index as usize]
+  }
+  pub fn set_data(&mut self, value: u8) {
+    self.data[self.index as usize] = value;
+    if self.auto_increment {
+      self.index = (self.index + 1) & 0x3F;
+    }
+  }
+
+  ///Get 15-bit RGB color
+  pub fn color(&self, palette: u8, color: u8) -> u16 {
+    let addr = ((palette as usize & 7) << 3) | ((color as usize & 3) << 1);
+    u16::from_le_bytes([self.data[addr], self.data[addr + 1]]) & 0x7FFF
+  }
+
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.index <= 0x3F, "invalid palette index")
+  }
+}
+impl Default for CgbPalette {
+  fn default() -> Self { Self::new() }
+}
+
+impl_save_state!(CgbPalette { data, index, auto_increment } validate validate_state);
@@ -1,17 +1,16 @@
+use crate::state::{impl_save_state, impl_save_state_enum};
+
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
 pub enum PpuMode {
   HBlank     = 0,
   VBlank     = 1,
+  #[default]
   OamSearch  = 2,
   PxTransfer = 3,
 }
 
-impl Default for PpuMode {
-  fn default() -> Self { Self::OamSearch }
-}
-
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, Debug)]
 pub struct Lcdc {
   pub enable_bg: bool,
   pub enable_obj: bool,      
@@ -80,7 +79,7 @@ impl From<u8> for Lcdc {
   }
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, Debug)]
 pub struct StatInterrupts {
   pub lyc_eq: bool,
   pub mode_2: bool,
@@ -116,3 +115,12 @@ impl From<u8> for StatInterrupts {
     Self::from_u8(value)
   }
 }
+
+impl_save_state_enum!(PpuMode { HBlank, VBlank, OamSearch, PxTransfer });
+impl_save_state!(Lcdc {
+  enable_bg, enable_obj, obj_size,
+  bg_tilemap_addr, tiledata_addr,
+  enable_win, win_tilemap_addr,
+  enable_display,
+});
+impl_save_state!(StatInterrupts { lyc_eq, mode_2, mode_1, mode_0 });
@@ -14,3 +14,11 @@ pub fn spr_line(tile_data: (u8, u8)) -> [u8; 8] {
   }});
   colors
 }
+
+///Closest DMG shade (0: white - 3: black) of a 15-bit RGB color
+pub fn rgb555_shade(color: u16) -> u8 {
+  let (r, g, b) = (color & 0x1F, (color >> 5) & 0x1F, (color >> 10) & 0x1F);
+  //Weighted luma (0-31)
+  let luma = (r * 2 + g * 4 + b) / 7;
+  3 - ((luma + 5) / 10) as u8
+}
@@ -0,0 +1,326 @@
+//! Converts display data to RGB framebuffers
+//!
+//! Picks the right source depending on the emulated hardware:
+//! - SGB: 256x224 frame including the border
+//! - CGB mode: 15-bit RGB colors, with optional LCD color correction
+//! - Otherwise: 2-bit shades mapped to the DMG palette
+//!
+//! Also renders VRAM and OAM contents (tiles, tilemaps and objects) for debugging
+
+use crate::{
+  Gameboy,
+  ppu::oam::OamObject,
+  consts::{WIDTH, HEIGHT, SGB_WIDTH, SGB_HEIGHT},
+};
+
+const TILEMAP_SIZE: usize = 256;
+const VIEWPORT_COLOR: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+const WINDOW_COLOR: [u8; 4] = [0x00, 0x80, 0xFF, 0xFF];
+
+///Grayscale palette, used by default
+pub const DEFAULT_PALETTE: [[u8; 3]; 4] = [
+  [0xFF, 0xFF, 0xFF],
+  [0xAA, 0xAA, 0xAA],
+  [0x55, 0x55, 0x55],
+  [0x00, 0x00, 0x00],
+];
+
+fn rgb555_to_rgb888(color: u16) -> [u8; 3] {
+  let channel = |shift: u16| {
+    let value = ((color >> shift) & 0x1F) as u8;
+    (value << 3) | (value >> 2)
+  };
+  [channel(0), channel(5), channel(10)]
+}
+
+///Approximate the colors of the CGB LCD, which are less saturated and slightly shifted
+fn correct_rgb555(color: u16) -> [u8; 3] {
+  let (r, g, b) = (
+    (color & 0x1F) as u32,
+    ((color >> 5) & 0x1F) as u32,
+    ((color >> 10) & 0x1F) as u32,
+  );
+  [
+    ((r * 26 + g * 4 + b * 2).min(960) >> 2) as u8,
+    ((g * 24 + b * 8).min(960) >> 2) as u8,
+    ((r * 6 + g * 4 + b * 22).min(960) >> 2) as u8,
+  ]
+}
+
+///RGBA8888 image (4 bytes per pixel)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RgbaImage {
+  pub width: usize,
+  pub height: usize,
+  pub data: Vec<u8>,
+}
+impl RgbaImage {
+  fn new(width: usize, height: usize) -> Self {
+    Self { width, height, data: vec![0; width * height * 4] }
+  }
+
+  pub fn get_pixel(&self, x: usize, y: usize) -> [u8; 4] {
+    let i = (y * self.width + x) << 2;
+    self.data[i..(i + 4)].try_into().unwrap()
+  }
+  fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 4]) {
+    let i = (y * self.width + x) << 2;
+    self.data[i..(i + 4)].copy_from_slice(&color);
+  }
+
+  ///Draw a rectangle outline, wrapping around the edges of the image
+  fn draw_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 4]) {
+    if width == 0 || height == 0 {
+      return
+    }
+    let (w, h) = (self.width, self.height);
+    for i in 0..width {
+      self.set_pixel((x + i) % w, y % h, color);
+      self.set_pixel((x + i) % w, (y + height - 1) % h, color);
+    }
+    for i in 0..height {
+      self.set_pixel(x % w, (y + i) % h, color);
+      self.set_pixel((x + width - 1) % w, (y + i) % h, color);
+    }
+  }
+}
+
+///Color index (0-3) of a pixel in the tile at `addr` (relative to 0x8000)
+fn tile_pixel(vram: &[u8], addr: usize, x: usize, y: usize) -> u8 {
+  let lo = vram[addr + (y << 1)];
+  let hi = vram[addr + (y << 1) + 1];
+  let bit = 7 - x;
+  (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1)
+}
+
+pub struct Renderer {
+  palette: [[u8; 3]; 4],
+  color_correction: bool,
+  gamma: f32,
+  gamma_lut: [u8; 256],
+}
+impl Renderer {
+  pub fn new() -> Self {
+    let mut renderer = Self {
+      palette: DEFAULT_PALETTE,
+      color_correction: false,
+      gamma: 1.,
+      gamma_lut: [0; 256],
+    };
+    renderer.set_gamma(1.);
+    renderer
+  }
+
+  ///Set RGB colors used for DMG shades (lightest first)
+  pub fn set_palette(&mut self, palette: [[u8; 3]; 4]) {
+    self.palette = palette;
+  }
+  pub fn get_palette(&self) -> [[u8; 3]; 4] {
+    self.palette
+  }
+
+  ///Enable CGB LCD color correction (only applies in CGB mode)
+  pub fn set_color_correction(&mut self, enable: bool) {
+    self.color_correction = enable;
+  }
+  pub fn get_color_correction(&self) -> bool {
+    self.color_correction
+  }
+
+  ///Set the gamma curve exponent (`output = input ^ gamma`, 1.0 leaves colors unchanged)
+  pub fn set_gamma(&mut self, gamma: f32) {
+    self.gamma = gamma;
+    for (i, value) in self.gamma_lut.iter_mut().enumerate() {
+      *value = ((i as f32 / 255.).powf(gamma) * 255.).round() as u8;
+    }
+  }
+  pub fn get_gamma(&self) -> f32 {
+    self.gamma
+  }
+
+  ///Size of the framebuffer produced for the current state of `gb`
+  pub fn screen_size(gb: &Gameboy) -> (usize, usize) {
+    match gb.is_sgb_mode() {
+      true => (SGB_WIDTH, SGB_HEIGHT),
+      false => (WIDTH, HEIGHT),
+    }
+  }
+
+  fn gamma(&self, [r, g, b]: [u8; 3]) -> [u8; 3] {
+    [self.gamma_lut[r as usize], self.gamma_lut[g as usize], self.gamma_lut[b as usize]]
+  }
+
+  fn dmg_color(&self, shade: u8) -> [u8; 3] {
+    self.gamma(self.palette[(shade & 3) as usize])
+  }
+
+  fn cgb_color(&self, color: u16) -> [u8; 3] {
+    self.gamma(match self.color_correction {
+      true => correct_rgb555(color),
+      false => rgb555_to_rgb888(color),
+    })
+  }
+
+  fn for_each_pixel(&self, gb: &Gameboy, mut f: impl FnMut(usize, [u8; 3])) {
+    if gb.is_sgb_mode() {
+      for (i, &color) in gb.get_display_data_sgb().iter().enumerate() {
+        f(i, self.gamma(rgb555_to_rgb888(color)));
+      }
+    } else if gb.is_cgb_mode() {
+      for (i, &color) in gb.get_display_data_cgb().iter().enumerate() {
+        f(i, self.cgb_color(color));
+      }
+    } else {
+      let palette = [0, 1, 2, 3].map(|shade| self.dmg_color(shade));
+      for (i, &shade) in gb.get_display_data().iter().enumerate() {
+        f(i, palette[(shade & 3) as usize]);
+      }
+    }
+  }
+
+  ///RGBA colors of all 8 BG or OBJ palettes (indexed by palette, then color index),
+  ///taken from the DMG palette registers (BGP/OBP0/OBP1) or the CGB palettes
+  fn palette_colors(&self, gb: &Gameboy, obj: bool) -> [[[u8; 4]; 4]; 8] {
+    let rgba = |[r, g, b]: [u8; 3]| [r, g, b, 0xFF];
+    if gb.is_cgb_mode() {
+      let ppu = &gb.cpu.bus.ppu;
+      let palettes = if obj { ppu.get_obj_palette() } else { ppu.get_bg_palette() };
+      [0, 1, 2, 3, 4, 5, 6, 7].map(|palette| {
+        [0, 1, 2, 3].map(|color| rgba(self.cgb_color(palettes.color(palette, color))))
+      })
+    } else {
+      let regs = match obj {
+        false => [gb.read_mem(0xFF47); 2],
+        true => [gb.read_mem(0xFF48), gb.read_mem(0xFF49)],
+      };
+      [0, 1, 2, 3, 4, 5, 6, 7].map(|palette| {
+        let reg = regs[(palette != 0) as usize];
+        [0, 1, 2, 3].map(|color| rgba(self.dmg_color(reg >> (color << 1))))
+      })
+    }
+  }
+
+  ///Render all 384 tiles (16 tiles per row) using BGP (or BG palette 0 in CGB mode)
+  ///
+  ///Returns a 128x192 image, or 256x192 in CGB mode (VRAM bank 1 on the right side)
+  pub fn render_tileset(&self, gb: &Gameboy) -> RgbaImage {
+    let banks = if gb.is_cgb_mode() { 2 } else { 1 };
+    let colors = self.palette_colors(gb, false)[0];
+    let mut image = RgbaImage::new(128 * banks, 192);
+    for bank in 0..banks {
+      let vram = gb.get_vram(bank);
+      for tile in 0..384 {
+        let (tile_x, tile_y) = ((bank << 4) + (tile & 0xF), tile >> 4);
+        for y in 0..8 {
+          for x in 0..8 {
+            let color = tile_pixel(vram, tile << 4, x, y);
+            image.set_pixel((tile_x << 3) + x, (tile_y << 3) + y, colors[color as usize]);
+          }
+        }
+      }
+    }
+    image
+  }
+
+  ///Render a 32x32 tilemap (`map` 0: 0x9800-0x9BFF, 1: 0x9C00-0x9FFF) as a 256x256 image
+  ///
+  ///Tile data addressing mode is taken from LCDC, CGB tile attributes are applied in CGB mode.\
+  ///If `overlay` is set, the SCX/SCY viewport is outlined in red (if `map` is the BG map)
+  ///and the window area in blue (if `map` is the window map and the window is enabled)
+  pub fn render_tilemap(&self, gb: &Gameboy, map: u8, overlay: bool) -> RgbaImage {
+    let lcdc = gb.read_mem(0xFF40);
+    let cgb = gb.is_cgb_mode();
+    let palettes = self.palette_colors(gb, false);
+    let (vram, attrs) = (gb.get_vram(0), gb.get_vram(1));
+    let base = if map & 1 != 0 { 0x1C00 } else { 0x1800 };
+    let mut image = RgbaImage::new(TILEMAP_SIZE, TILEMAP_SIZE);
+    for tile_y in 0..32 {
+      for tile_x in 0..32 {
+        let offset = base + (tile_y << 5) + tile_x;
+        let index = vram[offset];
+        let attr = if cgb { attrs[offset] } else { 0 };
+        let addr = match lcdc & 0x10 != 0 {
+          true => (index as usize) << 4,
+          false => (0x1000 + ((index as i8 as isize) << 4)) as usize,
+        };
+        let tile_data = gb.get_vram(((attr >> 3) & 1) as usize);
+        for y in 0..8 {
+          for x in 0..8 {
+            let tx = if attr & 0x20 != 0 { 7 - x } else { x };
+            let ty = if attr & 0x40 != 0 { 7 - y } else { y };
+            let color = tile_pixel(tile_data, addr, tx, ty);
+            image.set_pixel((tile_x << 3) + x, (tile_y << 3) + y, palettes[(attr & 7) as usize][color as usize]);
+          }
+        }
+      }
+    }
+    if overlay {
+      let map_bit = (map & 1) != 0;
+      if ((lcdc & 0x08) != 0) == map_bit {
+        let (scx, scy) = (gb.read_mem(0xFF43) as usize, gb.read_mem(0xFF42) as usize);
+        image.draw_rect(scx, scy, WIDTH, HEIGHT, VIEWPORT_COLOR);
+      }
+      let (wx, wy) = (gb.read_mem(0xFF4B) as usize, gb.read_mem(0xFF4A) as usize);
+      if ((lcdc & 0x20) != 0) && (((lcdc & 0x40) != 0) == map_bit) && (wx < WIDTH + 7) && (wy < HEIGHT) {
+        let width = WIDTH - wx.saturating_sub(7);
+        image.draw_rect(0, 0, width, HEIGHT - wy, WINDOW_COLOR);
+      }
+    }
+    image
+  }
+
+  ///Render all 40 objects in OAM order, each one as an 8x8 or 8x16 image (depending on LCDC)
+  ///
+  ///Flips and palettes are applied, color 0 is transparent
+  pub fn render_oam(&self, gb: &Gameboy) -> Vec<RgbaImage> {
+    let tall = (gb.read_mem(0xFF40) & 0x04) != 0;
+    let palettes = self.palette_colors(gb, true);
+    gb.get_oam().iter().map(|object| self.render_object(gb, object, tall, &palettes)).collect()
+  }
+
+  fn render_object(&self, gb: &Gameboy, object: &OamObject, tall: bool, palettes: &[[[u8; 4]; 4]; 8]) -> RgbaImage {
+    let height = if tall { 16 } else { 8 };
+    let tile = if tall { object.tile & 0xFE } else { object.tile } as usize;
+    let (palette, bank) = match gb.is_cgb_mode() {
+      true => (object.flags.cgb_palette(), object.flags.cgb_bank()),
+      false => (object.flags.palette as u8, 0),
+    };
+    let vram = gb.get_vram(bank);
+    let mut image = RgbaImage::new(8, height);
+    for y in 0..height {
+      for x in 0..8 {
+        let tx = if object.flags.flip_x { 7 - x } else { x };
+        let ty = if object.flags.flip_y { height - 1 - y } else { y };
+        let color = tile_pixel(vram, (tile << 4) + ((ty >> 3) << 4), tx, ty & 7);
+        if color != 0 {
+          image.set_pixel(x, y, palettes[palette as usize][color as usize]);
+        }
+      }
+    }
+    image
+  }
+
+  ///Render to a RGBA8888 buffer (4 bytes per pixel, see `screen_size` for dimensions)
+  pub fn render_rgba8888(&self, gb: &Gameboy, buffer: &mut [u8]) {
+    let (width, height) = Self::screen_size(gb);
+    assert!(buffer.len() >= width * height * 4, "Buffer too small");
+    self.for_each_pixel(gb, |i, [r, g, b]| {
+      buffer[(i << 2)..((i << 2) + 4)].copy_from_slice(&[r, g, b, 0xFF]);
+    });
+  }
+
+  ///Render to a RGB565 buffer (see `screen_size` for dimensions)
+  pub fn render_rgb565(&self, gb: &Gameboy, buffer: &mut [u16]) {
+    let (width, height) = Self::screen_size(gb);
+    assert!(buffer.len() >= width * height, "Buffer too small");
+    self.for_each_pixel(gb, |i, [r, g, b]| {
+      buffer[i] = (((r >> 3) as u16) << 11) | (((g >> 2) as u16) << 5) | (b >> 3) as u16;
+    });
+  }
+}
+
+impl Default for Renderer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
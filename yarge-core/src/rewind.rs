@@ -0,0 +1,160 @@
+//! Rewind buffer
+//!
+//! Only the most recent snapshot is stored as-is, older ones are stored as patches
+//! that turn the next (newer) snapshot into the older one.
+//! Since consecutive snapshots are mostly identical, this keeps memory usage low.
+//!
+//! Patch format: target length (`u32`), followed by any number of
+//! `skip` (LEB128), `len` (LEB128), `data[len]` entries, where `skip` is the amount
+//! of bytes copied from the base state and `data` replaces the next `len` bytes.
+
+use std::collections::VecDeque;
+
+///Amount of identical bytes required to end a changed run
+const MIN_GAP: usize = 8;
+
+pub(crate) struct RewindBuffer {
+  interval: usize,
+  memory_limit: usize,
+  frame_counter: usize,
+  current: Option<Vec<u8>>,
+  patches: VecDeque<Vec<u8>>,
+  patches_size: usize,
+}
+impl RewindBuffer {
+  pub fn new(interval: usize, memory_limit: usize) -> Self {
+    Self {
+      interval: interval.max(1),
+      memory_limit,
+      frame_counter: 0,
+      current: None,
+      patches: VecDeque::new(),
+      patches_size: 0,
+    }
+  }
+
+  ///Returns true if a snapshot should be taken before this frame
+  pub fn frame_start(&mut self) -> bool {
+    self.frame_counter += 1;
+    if self.frame_counter >= self.interval {
+      self.frame_counter = 0;
+      true
+    } else {
+      false
+    }
+  }
+
+  pub fn push(&mut self, state: Vec<u8>) {
+    if let Some(previous) = self.current.take() {
+      let patch = make_patch(&state, &previous);
+      self.patches_size += patch.len();
+      self.patches.push_back(patch);
+    }
+    self.current = Some(state);
+    //Drop the oldest snapshots if over the memory limit
+    while self.memory_usage() > self.memory_limit {
+      match self.patches.pop_front() {
+        Some(patch) => { self.patches_size -= patch.len() },
+        None => break,
+      }
+    }
+  }
+
+  ///Remove and return the most recent snapshot
+  pub fn pop(&mut self) -> Option<Vec<u8>> {
+    let state = self.current.take()?;
+    if let Some(patch) = self.patches.pop_back() {
+      self.patches_size -= patch.len();
+      self.current = Some(apply_patch(&state, &patch));
+    }
+    self.frame_counter = 0;
+    Some(state)
+  }
+
+  pub fn clear(&mut self) {
+    self.frame_counter = 0;
+    self.current = None;
+    self.patches.clear();
+    self.patches_size = 0;
+  }
+
+  ///Amount of snapshots currently stored
+  pub fn len(&self) -> usize {
+    self.current.is_some() as usize + self.patches.len()
+  }
+
+  ///Approximate memory usage in bytes
+  pub fn memory_usage(&self) -> usize {
+    self.current.as_ref().map(|state| state.len()).unwrap_or(0) + self.patches_size
+  }
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: usize) {
+  loop {
+    let byte = (value & 0x7f) as u8;
+    value >>= 7;
+    if value == 0 {
+      out.push(byte);
+      break
+    }
+    out.push(byte | 0x80);
+  }
+}
+
+fn read_leb128(data: &[u8], pos: &mut usize) -> usize {
+  let mut value = 0;
+  let mut shift = 0;
+  loop {
+    let byte = data[*pos];
+    *pos += 1;
+    value |= ((byte & 0x7f) as usize) << shift;
+    shift += 7;
+    if byte & 0x80 == 0 {
+      break value
+    }
+  }
+}
+
+///Create a patch that turns `base` into `target`
+fn make_patch(base: &[u8], target: &[u8]) -> Vec<u8> {
+  let mut patch = Vec::new();
+  patch.extend_from_slice(&(target.len() as u32).to_le_bytes());
+  let is_same = |i: usize| i < base.len() && base[i] == target[i];
+  let mut last = 0;
+  let mut i = 0;
+  while i < target.len() {
+    if is_same(i) {
+      i += 1;
+      continue
+    }
+    let start = i;
+    let mut gap = 0;
+    while i < target.len() && gap < MIN_GAP {
+      gap = if is_same(i) { gap + 1 } else { 0 };
+      i += 1;
+    }
+    let end = i - gap;
+    write_leb128(&mut patch, start - last);
+    write_leb128(&mut patch, end - start);
+    patch.extend_from_slice(&target[start..end]);
+    last = end;
+  }
+  patch
+}
+
+fn apply_patch(base: &[u8], patch: &[u8]) -> Vec<u8> {
+  let len = u32::from_le_bytes(patch[0..4].try_into().unwrap()) as usize;
+  let mut target = Vec::with_capacity(len);
+  let mut pos = 4;
+  while pos < patch.len() {
+    let skip = read_leb128(patch, &mut pos);
+    let data_len = read_leb128(patch, &mut pos);
+    let offset = target.len();
+    target.extend_from_slice(&base[offset..(offset + skip)]);
+    target.extend_from_slice(&patch[pos..(pos + data_len)]);
+    pos += data_len;
+  }
+  let offset = target.len();
+  target.extend_from_slice(&base[offset..len]);
+  target
+}
@@ -1,37 +1,76 @@
-//NOTE: this is just a stub, only disconnected cable is implemented at the moment
+mod link;
+mod tcp;
+pub use link::{SerialLink, LocalLink};
+pub use tcp::TcpLink;
 
-use crate::cpu::{Cpu, Interrupt};
+use crate::{
+  consts::SERIAL_CLOCK_CYCLES,
+  cpu::{Cpu, Interrupt},
+  io_registers::SerialRegisters,
+  state::impl_save_state,
+};
 
 pub struct Serial {
   transfer: bool,
-  ext_clock: bool,
+  internal_clock: bool,
   data: u8,
+  bits: u8,
+  clock_cycles: u16,
+  pub link: Option<Box<dyn SerialLink>>,
 }
 
 impl Serial {
   pub fn new() -> Self {
     Self {
       transfer: false,
-      ext_clock: false,
+      internal_clock: false,
       data: 0xff,
+      bits: 0,
+      clock_cycles: 0,
+      link: None,
     }
   }
 
-  // pub fn shift(&mut self) {
-  //   self.data <<= 1;
-  //   self.data |= 1;
-  // }
-
-  pub fn tick(&mut self, iif: &mut u8) {
-    //XXX: Fucking ext clock?
-    if self.transfer & self.ext_clock {
+  fn shift(&mut self, bit: bool, iif: &mut u8) {
+    self.data = (self.data << 1) | bit as u8;
+    self.bits += 1;
+    if self.bits >= 8 {
+      self.bits = 0;
       self.transfer = false;
-      self.data = 0xff;
-      //FIXME: freezes in pokemon red while entering a pokemart
       Cpu::set_interrupt(iif, Interrupt::Serial);
     }
   }
 
+  pub fn tick(&mut self, iif: &mut u8) {
+    let out_bit = (self.data & 0x80) != 0;
+    //External clock: shift on pulses coming from the other side
+    //With no cable connected, the transfer never finishes
+    if let Some(link) = self.link.as_mut() {
+      if let Some(in_bit) = link.poll_clock(out_bit) {
+        if self.transfer && !self.internal_clock {
+          self.shift(in_bit, iif);
+        }
+      }
+    }
+    //Internal clock: shift one bit every 512 cycles (8192 Hz)
+    if self.transfer && self.internal_clock {
+      self.clock_cycles = (self.clock_cycles + 4).min(SERIAL_CLOCK_CYCLES);
+      if self.clock_cycles >= SERIAL_CLOCK_CYCLES {
+        //Disconnected cable always reads as 1
+        let in_bit = match self.link.as_mut() {
+          Some(link) => {
+            //The link may hold the pulse back while waiting for the other side
+            if !link.clock_ready(self.data) { return }
+            link.send_clock(out_bit)
+          },
+          None => true,
+        };
+        self.clock_cycles = 0;
+        self.shift(in_bit, iif);
+      }
+    }
+  }
+
   pub fn read_sb(&self) -> u8 {
     self.data
   }
@@ -42,16 +81,31 @@ impl Serial {
 
   pub fn read_sc(&self) -> u8 {
     (self.transfer as u8) << 7 |
-    self.ext_clock as u8 |
+    self.internal_clock as u8 |
     0b0111_1110
   }
 
   pub fn write_sc(&mut self, value: u8) {
     self.transfer = value & 0x80 != 0;
-    self.ext_clock = value & 0x01 != 0;
+    self.internal_clock = value & 0x01 != 0;
+    if self.transfer {
+      self.bits = 0;
+      self.clock_cycles = 0;
+    }
+  }
+
+  pub fn snapshot(&self) -> SerialRegisters {
+    SerialRegisters {
+      sb: self.data,
+      transfer: self.transfer,
+      internal_clock: self.internal_clock,
+      bits: self.bits,
+    }
   }
 }
 
 impl Default for Serial {
   fn default() -> Self { Self::new() }
 }
+
+impl_save_state!(Serial { transfer, internal_clock, data, bits, clock_cycles });
@@ -0,0 +1,56 @@
+use std::{rc::Rc, cell::RefCell, collections::VecDeque};
+
+pub trait SerialLink {
+  ///Called on every clock pulse generated by this side (internal clock),
+  ///`bit` is the bit being shifted out, returns the bit shifted in from the other side
+  fn send_clock(&mut self, bit: bool) -> bool;
+
+  ///Called every M-cycle, `bit` is the bit that will be shifted out next,
+  ///returns the bit to shift in if the other side generated a clock pulse
+  fn poll_clock(&mut self, bit: bool) -> Option<bool>;
+
+  ///Called every M-cycle while a clock pulse generated by this side is due, `data` is the current value of SB.
+  ///The pulse (and `send_clock`) is postponed until this returns true,
+  ///which lets links with latency exchange whole bytes without blocking the emulation
+  fn clock_ready(&mut self, _data: u8) -> bool {
+    true
+  }
+}
+
+#[derive(Default)]
+struct Wire {
+  pulses: [VecDeque<bool>; 2],
+  out_bits: [bool; 2],
+}
+
+///Link cable connecting two `Gameboy`s in the same process
+///(both sides must be stepped in small (less than 512 cycles) slices for the transfers to be reliable)
+pub struct LocalLink {
+  wire: Rc<RefCell<Wire>>,
+  side: usize,
+}
+impl LocalLink {
+  pub fn new_pair() -> (Self, Self) {
+    let wire = Rc::new(RefCell::new(Wire {
+      out_bits: [true, true],
+      ..Default::default()
+    }));
+    (
+      Self { wire: Rc::clone(&wire), side: 0 },
+      Self { wire, side: 1 },
+    )
+  }
+}
+impl SerialLink for LocalLink {
+  fn send_clock(&mut self, bit: bool) -> bool {
+    let mut wire = self.wire.borrow_mut();
+    wire.pulses[self.side ^ 1].push_back(bit);
+    wire.out_bits[self.side ^ 1]
+  }
+
+  fn poll_clock(&mut self, bit: bool) -> Option<bool> {
+    let mut wire = self.wire.borrow_mut();
+    wire.out_bits[self.side] = bit;
+    wire.pulses[self.side].pop_front()
+  }
+}
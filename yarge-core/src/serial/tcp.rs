@@ -0,0 +1,181 @@
+//! Link cable over a TCP socket
+//!
+//! Protocol: after connecting, both sides send [`HANDSHAKE`] and verify the one they receive.
+//! After that, each message is a 2 byte frame: frame type followed by the SB value.
+//! The side driving the clock sends a [`FRAME_TRANSFER`] at the start of each byte and holds
+//! its clock until the other side responds with [`FRAME_REPLY`] (or [`REPLY_TIMEOUT`] expires).
+//! The other side shifts the received byte in at 8192 Hz and replies with the bits it shifted out.
+//! Nothing blocks the emulation, both sides keep running while waiting for the network.
+//! Dropping the link closes the connection, the other side then receives 0xFF without waiting for the timeout.
+
+use std::{
+  io::{Read, Write},
+  net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+  sync::mpsc::{self, Receiver, TryRecvError},
+  thread,
+};
+use crate::{Res, consts::{CPU_CLOCK_SPEED, SERIAL_CLOCK_CYCLES}};
+use super::SerialLink;
+
+const HANDSHAKE: &[u8; 5] = b"YRGL\x01";
+const FRAME_TRANSFER: u8 = 0x01;
+const FRAME_REPLY: u8 = 0x02;
+///Assume that the cable got disconnected if the other side doesn't respond in time (1 second, in M-cycles)
+const REPLY_TIMEOUT: u32 = (CPU_CLOCK_SPEED / 4) as u32;
+
+pub struct TcpLink {
+  stream: TcpStream,
+  frames: Receiver<[u8; 2]>,
+  ///Cleared once the reader thread stops (the other side closed the connection)
+  connected: bool,
+  //Internal clock (this side drives the transfer)
+  ///Remaining M-cycles before the reply times out
+  waiting: Option<u32>,
+  received: Option<u8>,
+  bit: u8,
+  //External clock (the other side drives the transfer)
+  incoming: Option<u8>,
+  incoming_bit: u8,
+  reply: u8,
+  pulse_cycles: u16,
+}
+impl TcpLink {
+  ///Wait for the other side to connect
+  pub fn listen(addr: impl ToSocketAddrs) -> Res<Self> {
+    let listener = TcpListener::bind(addr)?;
+    let (stream, _) = listener.accept()?;
+    Self::from_stream(stream)
+  }
+
+  pub fn connect(addr: impl ToSocketAddrs) -> Res<Self> {
+    Self::from_stream(TcpStream::connect(addr)?)
+  }
+
+  pub fn from_stream(mut stream: TcpStream) -> Res<Self> {
+    stream.set_nodelay(true)?;
+    stream.write_all(HANDSHAKE)?;
+    let mut handshake = [0; HANDSHAKE.len()];
+    stream.read_exact(&mut handshake)?;
+    if &handshake != HANDSHAKE {
+      return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid link cable handshake").into());
+    }
+    //Read frames on a separate thread to avoid polling the socket every cycle
+    let (sender, frames) = mpsc::channel();
+    let mut reader = stream.try_clone()?;
+    thread::spawn(move || {
+      let mut frame = [0; 2];
+      while reader.read_exact(&mut frame).is_ok() {
+        if sender.send(frame).is_err() { break }
+      }
+    });
+    Ok(Self {
+      stream,
+      frames,
+      connected: true,
+      waiting: None,
+      received: None,
+      bit: 0,
+      incoming: None,
+      incoming_bit: 0,
+      reply: 0,
+      pulse_cycles: 0,
+    })
+  }
+
+  ///Returns false once the other side closes the connection
+  pub fn is_connected(&mut self) -> bool {
+    self.receive();
+    self.connected
+  }
+
+  fn send_frame(&mut self, frame_type: u8, data: u8) {
+    //Write errors are handled the same way as a disconnected cable
+    self.stream.write_all(&[frame_type, data]).ok();
+  }
+
+  fn receive(&mut self) {
+    loop {
+      let [frame_type, value] = match self.frames.try_recv() {
+        Ok(frame) => frame,
+        Err(TryRecvError::Empty) => break,
+        Err(TryRecvError::Disconnected) => {
+          self.connected = false;
+          break
+        },
+      };
+      match frame_type {
+        //If both sides are driving the clock, they just exchange their values
+        FRAME_REPLY | FRAME_TRANSFER if self.waiting.is_some() => {
+          self.waiting = None;
+          self.received = Some(value);
+        },
+        FRAME_TRANSFER if self.incoming.is_none() => {
+          self.incoming = Some(value);
+          self.incoming_bit = 0;
+          self.pulse_cycles = 0;
+        },
+        _ => (),
+      }
+    }
+  }
+}
+impl SerialLink for TcpLink {
+  fn clock_ready(&mut self, data: u8) -> bool {
+    if self.bit != 0 { return true }
+    self.receive();
+    if self.received.is_some() { return true }
+    if !self.connected {
+      //Same as an unplugged cable
+      self.waiting = None;
+      self.received = Some(0xff);
+      return true
+    }
+    match self.waiting.as_mut() {
+      None => {
+        self.send_frame(FRAME_TRANSFER, data);
+        self.waiting = Some(REPLY_TIMEOUT);
+      },
+      Some(0) => {
+        self.waiting = None;
+        self.received = Some(0xff);
+        return true
+      },
+      Some(cycles) => *cycles -= 1,
+    }
+    false
+  }
+
+  fn send_clock(&mut self, _bit: bool) -> bool {
+    let received = self.received.unwrap_or(0xff);
+    let bit = (received << self.bit) & 0x80 != 0;
+    self.bit = (self.bit + 1) & 7;
+    if self.bit == 0 {
+      self.received = None;
+    }
+    bit
+  }
+
+  fn poll_clock(&mut self, bit: bool) -> Option<bool> {
+    self.receive();
+    let value = self.incoming?;
+    //Pulses from the other side arrive at 8192 Hz
+    self.pulse_cycles += 4;
+    if self.pulse_cycles < SERIAL_CLOCK_CYCLES { return None }
+    self.pulse_cycles = 0;
+    self.reply = (self.reply << 1) | bit as u8;
+    let in_bit = (value << self.incoming_bit) & 0x80 != 0;
+    self.incoming_bit += 1;
+    if self.incoming_bit >= 8 {
+      self.incoming = None;
+      let reply = self.reply;
+      self.send_frame(FRAME_REPLY, reply);
+    }
+    Some(in_bit)
+  }
+}
+impl Drop for TcpLink {
+  fn drop(&mut self) {
+    //Also stops the reader thread, which holds a clone of the stream
+    self.stream.shutdown(Shutdown::Both).ok();
+  }
+}
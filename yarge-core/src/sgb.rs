@@ -0,0 +1,396 @@
+//! Super Game Boy
+//!
+//! Commands are sent by the game as 16-byte packets through P1 (see `Input`),
+//! the first byte of a command contains the command code (bits 3-7) and the amount of packets (bits 0-2).
+//! VRAM transfers (`*_TRN` commands) copy 4 KB of data from the next frame displayed on the LCD,
+//! with the screen interpreted as a list of 2bpp tiles (left to right, top to bottom).
+
+use crate::{
+  Input,
+  consts::{WIDTH, HEIGHT, FB_SIZE, SGB_WIDTH, SGB_HEIGHT, SGB_FB_SIZE},
+  input::SGB_PACKET_SIZE,
+  state::{impl_save_state, impl_save_state_enum, check_state},
+  Res,
+};
+
+const TILES_X: usize = WIDTH / 8;
+const TILES_Y: usize = HEIGHT / 8;
+const TRANSFER_SIZE: usize = 0x1000;
+const SYSTEM_PALETTES: usize = 512;
+const ATTR_FILE_SIZE: usize = (TILES_X * TILES_Y) / 4;
+const ATTR_FILES: usize = 45;
+const BORDER_TILES_SIZE: usize = 2 * TRANSFER_SIZE;
+const BORDER_MAP_SIZE: usize = 32 * 32 * 2;
+///Position of the Game Boy screen inside of the SGB frame
+const SCREEN_POS: (usize, usize) = ((SGB_WIDTH - WIDTH) / 2, (SGB_HEIGHT - HEIGHT) / 2);
+///Palette used before the game sets its own one
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum Transfer {
+  #[default]
+  Palettes,
+  TilesLow,
+  TilesHigh,
+  Border,
+  Attributes,
+}
+impl_save_state_enum!(Transfer { Palettes, TilesLow, TilesHigh, Border, Attributes });
+
+///Screen mask set by MASK_EN
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum Mask {
+  #[default]
+  Cancel,
+  Freeze,
+  Black,
+  Color0,
+}
+impl_save_state_enum!(Mask { Cancel, Freeze, Black, Color0 });
+
+fn read_color(data: &[u8], addr: usize) -> u16 {
+  u16::from_le_bytes([data[addr], data[addr + 1]]) & 0x7FFF
+}
+
+///Get 2-bit value `index` from a list of values packed 4 per byte (MSB first)
+fn unpack_2bpp(data: &[u8], index: usize) -> u8 {
+  (data[index >> 2] >> (6 - ((index & 3) << 1))) & 3
+}
+
+pub struct Sgb {
+  pub display: Box<[u16; SGB_FB_SIZE]>,
+  palettes: [[u16; 4]; 4],
+  system_palettes: Box<[[u16; 4]; SYSTEM_PALETTES]>,
+  attr_map: [u8; TILES_X * TILES_Y],
+  attr_files: Box<[u8; ATTR_FILE_SIZE * ATTR_FILES]>,
+  border_tiles: Box<[u8; BORDER_TILES_SIZE]>,
+  border_map: Box<[u8; BORDER_MAP_SIZE]>,
+  border_palettes: [u16; 64],
+  mask: Mask,
+  transfer: Option<Transfer>,
+  command: Vec<u8>,
+}
+impl Sgb {
+  pub fn new() -> Self {
+    Self {
+      display: Box::new([0; SGB_FB_SIZE]),
+      palettes: [DEFAULT_PALETTE; 4],
+      system_palettes: Box::new([[0; 4]; SYSTEM_PALETTES]),
+      attr_map: [0; TILES_X * TILES_Y],
+      attr_files: Box::new([0; ATTR_FILE_SIZE * ATTR_FILES]),
+      border_tiles: Box::new([0; BORDER_TILES_SIZE]),
+      border_map: Box::new([0; BORDER_MAP_SIZE]),
+      border_palettes: [0; 64],
+      mask: Mask::default(),
+      transfer: None,
+      command: Vec::new(),
+    }
+  }
+
+  ///Handle a packet received through P1, executing the command once all of its packets arrive
+  pub fn handle_packet(&mut self, packet: [u8; SGB_PACKET_SIZE], input: &mut Input) {
+    if self.command.is_empty() && (packet[0] & 7) == 0 {
+      return
+    }
+    self.command.extend_from_slice(&packet);
+    if self.command.len() >= (self.command[0] & 7) as usize * SGB_PACKET_SIZE {
+      let command = std::mem::take(&mut self.command);
+      self.execute(&command, input);
+    }
+  }
+
+  fn validate_state(&self) -> Res<()> {
+    check_state(self.attr_map.iter().all(|&palette| palette <= 3), "invalid sgb attribute map")?;
+    //Commands are executed as soon as the last packet arrives
+    let incomplete = self.command.is_empty() || self.command.len() < (self.command[0] & 7) as usize * SGB_PACKET_SIZE;
+    check_state(self.command.len() % SGB_PACKET_SIZE == 0 && incomplete, "invalid sgb command")
+  }
+
+  fn execute(&mut self, cmd: &[u8], input: &mut Input) {
+    match cmd[0] >> 3 {
+      0x00 => self.pal_pair(0, 1, cmd), //PAL01
+      0x01 => self.pal_pair(2, 3, cmd), //PAL23
+      0x02 => self.pal_pair(0, 3, cmd), //PAL03
+      0x03 => self.pal_pair(1, 2, cmd), //PAL12
+      0x04 => self.attr_blk(cmd),
+      0x05 => self.attr_lin(cmd),
+      0x06 => self.attr_div(cmd),
+      0x07 => self.attr_chr(cmd),
+      0x0A => self.pal_set(cmd),
+      0x0B => { self.transfer = Some(Transfer::Palettes) }, //PAL_TRN
+      0x11 => { //MLT_REQ
+        input.set_sgb_players(match cmd[1] & 3 {
+          1 => 2,
+          3 => 4,
+          _ => 1,
+        });
+      },
+      0x13 => { //CHR_TRN
+        self.transfer = Some(match cmd[1] & 1 {
+          0 => Transfer::TilesLow,
+          _ => Transfer::TilesHigh,
+        });
+      },
+      0x14 => { self.transfer = Some(Transfer::Border) }, //PCT_TRN
+      0x15 => { self.transfer = Some(Transfer::Attributes) }, //ATTR_TRN
+      0x16 => { //ATTR_SET
+        self.apply_attr_file(cmd[1] & 0x3F);
+        if (cmd[1] & 0x40) != 0 {
+          self.mask = Mask::Cancel;
+        }
+      },
+      0x17 => { //MASK_EN
+        self.mask = match cmd[1] & 3 {
+          0 => Mask::Cancel,
+          1 => Mask::Freeze,
+          2 => Mask::Black,
+          _ => Mask::Color0,
+        };
+      },
+      //Sound, SNES code execution and other stuff is not supported
+      _ => ()
+    }
+  }
+
+  ///Color 0 is shared between all palettes
+  fn share_color0(&mut self, color: u16) {
+    for palette in &mut self.palettes {
+      palette[0] = color;
+    }
+  }
+
+  fn pal_pair(&mut self, a: usize, b: usize, cmd: &[u8]) {
+    self.share_color0(read_color(cmd, 1));
+    for i in 0..3 {
+      self.palettes[a][i + 1] = read_color(cmd, 3 + (i << 1));
+      self.palettes[b][i + 1] = read_color(cmd, 9 + (i << 1));
+    }
+  }
+
+  fn set_attr(&mut self, x: usize, y: usize, palette: u8) {
+    if (x < TILES_X) && (y < TILES_Y) {
+      self.attr_map[(y * TILES_X) + x] = palette & 3;
+    }
+  }
+
+  fn attr_blk(&mut self, cmd: &[u8]) {
+    let count = cmd[1] as usize;
+    for set in cmd[2..].chunks_exact(6).take(count) {
+      let (control, palettes) = (set[0] & 7, set[1]);
+      let (x1, y1, x2, y2) = (
+        (set[2] & 0x1F) as usize, (set[3] & 0x1F) as usize,
+        (set[4] & 0x1F) as usize, (set[5] & 0x1F) as usize,
+      );
+      let pal_inside = palettes & 3;
+      let pal_border = (palettes >> 2) & 3;
+      let pal_outside = (palettes >> 4) & 3;
+      //If only the inside or outside is changed, the border is changed too
+      let border = match control {
+        0b001 => Some(pal_inside),
+        0b100 => Some(pal_outside),
+        _ if (control & 0b010) != 0 => Some(pal_border),
+        _ => None,
+      };
+      for y in 0..TILES_Y {
+        for x in 0..TILES_X {
+          let in_box = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+          let inside = (x1 < x) && (x < x2) && (y1 < y) && (y < y2);
+          let palette = if inside {
+            ((control & 0b001) != 0).then_some(pal_inside)
+          } else if in_box {
+            border
+          } else {
+            ((control & 0b100) != 0).then_some(pal_outside)
+          };
+          if let Some(palette) = palette {
+            self.set_attr(x, y, palette);
+          }
+        }
+      }
+    }
+  }
+
+  fn attr_lin(&mut self, cmd: &[u8]) {
+    let count = cmd[1] as usize;
+    for &line in cmd[2..].iter().take(count) {
+      let (index, palette) = ((line & 0x1F) as usize, (line >> 5) & 3);
+      if (line & 0x80) != 0 {
+        for x in 0..TILES_X {
+          self.set_attr(x, index, palette);
+        }
+      } else {
+        for y in 0..TILES_Y {
+          self.set_attr(index, y, palette);
+        }
+      }
+    }
+  }
+
+  fn attr_div(&mut self, cmd: &[u8]) {
+    let pal_after = cmd[1] & 3;
+    let pal_before = (cmd[1] >> 2) & 3;
+    let pal_line = (cmd[1] >> 4) & 3;
+    let horizontal = (cmd[1] & 0x40) != 0;
+    let coord = (cmd[2] & 0x1F) as usize;
+    for y in 0..TILES_Y {
+      for x in 0..TILES_X {
+        let palette = match (if horizontal { y } else { x }).cmp(&coord) {
+          std::cmp::Ordering::Less => pal_before,
+          std::cmp::Ordering::Equal => pal_line,
+          std::cmp::Ordering::Greater => pal_after,
+        };
+        self.set_attr(x, y, palette);
+      }
+    }
+  }
+
+  fn attr_chr(&mut self, cmd: &[u8]) {
+    let (mut x, mut y) = ((cmd[1] & 0x1F) as usize, (cmd[2] & 0x1F) as usize);
+    let count = (u16::from_le_bytes([cmd[3], cmd[4]]) as usize)
+      .min(TILES_X * TILES_Y)
+      .min((cmd.len() - 6) * 4);
+    let vertical = (cmd[5] & 1) != 0;
+    for i in 0..count {
+      self.set_attr(x, y, unpack_2bpp(&cmd[6..], i));
+      if vertical {
+        y += 1;
+        if y >= TILES_Y {
+          y = 0;
+          x += 1;
+        }
+      } else {
+        x += 1;
+        if x >= TILES_X {
+          x = 0;
+          y += 1;
+        }
+      }
+    }
+  }
+
+  fn pal_set(&mut self, cmd: &[u8]) {
+    for i in 0..4 {
+      let index = u16::from_le_bytes([cmd[1 + (i << 1)], cmd[2 + (i << 1)]]) as usize;
+      self.palettes[i] = self.system_palettes[index % SYSTEM_PALETTES];
+    }
+    self.share_color0(self.palettes[0][0]);
+    if (cmd[9] & 0x80) != 0 {
+      self.apply_attr_file(cmd[9] & 0x3F);
+    }
+    if (cmd[9] & 0x40) != 0 {
+      self.mask = Mask::Cancel;
+    }
+  }
+
+  fn apply_attr_file(&mut self, file: u8) {
+    let file = file as usize;
+    if file >= ATTR_FILES { return }
+    let offset = file * ATTR_FILE_SIZE;
+    let data = &self.attr_files[offset..(offset + ATTR_FILE_SIZE)];
+    for (i, attr) in self.attr_map.iter_mut().enumerate() {
+      *attr = unpack_2bpp(data, i);
+    }
+  }
+
+  ///Read the VRAM transfer data from the screen
+  fn transfer_data(display: &[u8; FB_SIZE]) -> Box<[u8; TRANSFER_SIZE]> {
+    let mut data = Box::new([0; TRANSFER_SIZE]);
+    for (tile, tile_data) in data.chunks_exact_mut(16).enumerate() {
+      let (tile_x, tile_y) = (tile % TILES_X, tile / TILES_X);
+      for row in 0..8 {
+        let addr = (((tile_y << 3) + row) * WIDTH) + (tile_x << 3);
+        for (px, &shade) in display[addr..(addr + 8)].iter().enumerate() {
+          tile_data[row << 1] |= (shade & 1) << (7 - px);
+          tile_data[(row << 1) + 1] |= ((shade >> 1) & 1) << (7 - px);
+        }
+      }
+    }
+    data
+  }
+
+  ///Called at the start of VBlank, performs pending VRAM transfers and updates the SGB display
+  pub fn frame_done(&mut self, display: &[u8; FB_SIZE]) {
+    if let Some(transfer) = self.transfer.take() {
+      let data = Self::transfer_data(display);
+      match transfer {
+        Transfer::Palettes => {
+          for (i, palette) in self.system_palettes.iter_mut().enumerate() {
+            for (j, color) in palette.iter_mut().enumerate() {
+              *color = read_color(&data[..], (i << 3) | (j << 1));
+            }
+          }
+        },
+        Transfer::TilesLow => {
+          self.border_tiles[..TRANSFER_SIZE].copy_from_slice(&data[..]);
+        },
+        Transfer::TilesHigh => {
+          self.border_tiles[TRANSFER_SIZE..].copy_from_slice(&data[..]);
+        },
+        Transfer::Border => {
+          self.border_map.copy_from_slice(&data[..BORDER_MAP_SIZE]);
+          for (i, color) in self.border_palettes.iter_mut().enumerate() {
+            *color = read_color(&data[..], BORDER_MAP_SIZE + (i << 1));
+          }
+        },
+        Transfer::Attributes => {
+          let len = self.attr_files.len();
+          self.attr_files.copy_from_slice(&data[..len]);
+        },
+      }
+    }
+    self.render(display);
+  }
+
+  ///Get the color index of the border pixel (0 is transparent)
+  fn border_pixel(&self, x: usize, y: usize) -> (u8, usize) {
+    let addr = (((y >> 3) << 5) | (x >> 3)) << 1;
+    let entry = u16::from_le_bytes([self.border_map[addr], self.border_map[addr + 1]]);
+    let tile = (entry & 0xFF) as usize;
+    let palette = ((entry >> 10) & 3) as usize;
+    let px = if (entry & 0x4000) != 0 { 7 - (x & 7) } else { x & 7 };
+    let py = if (entry & 0x8000) != 0 { 7 - (y & 7) } else { y & 7 };
+    //4bpp tile, bitplanes 0 and 1 are stored in the first 16 bytes, 2 and 3 in the last 16
+    let addr = (tile << 5) | (py << 1);
+    let bit = |offset: usize| (self.border_tiles[addr + offset] >> (7 - px)) & 1;
+    let color = bit(0) | (bit(1) << 1) | (bit(16) << 2) | (bit(17) << 3);
+    (color, palette)
+  }
+
+  fn render(&mut self, display: &[u8; FB_SIZE]) {
+    let backdrop = self.palettes[0][0];
+    for y in 0..SGB_HEIGHT {
+      for x in 0..SGB_WIDTH {
+        let (color, palette) = self.border_pixel(x, y);
+        let (gb_x, gb_y) = (x.wrapping_sub(SCREEN_POS.0), y.wrapping_sub(SCREEN_POS.1));
+        let value = if color != 0 {
+          self.border_palettes[(palette << 4) | color as usize]
+        } else if (gb_x < WIDTH) && (gb_y < HEIGHT) {
+          match self.mask {
+            Mask::Freeze => continue,
+            Mask::Black => 0,
+            Mask::Color0 => backdrop,
+            Mask::Cancel => {
+              let attr = self.attr_map[((gb_y >> 3) * TILES_X) + (gb_x >> 3)];
+              self.palettes[attr as usize][(display[(gb_y * WIDTH) + gb_x] & 3) as usize]
+            },
+          }
+        } else {
+          backdrop
+        };
+        self.display[(y * SGB_WIDTH) + x] = value;
+      }
+    }
+  }
+}
+impl Default for Sgb {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl_save_state!(Sgb {
+  display, palettes, system_palettes,
+  attr_map, attr_files,
+  border_tiles, border_map, border_palettes,
+  mask, transfer, command,
+} validate validate_state);
@@ -0,0 +1,265 @@
+//! Save state serialization
+//!
+//! # Format
+//! All values are stored in little-endian byte order.
+//!
+//! | Offset | Size | Description                                   |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | Magic bytes, `YRGS`                           |
+//! | 4      | 2    | Format version ([`STATE_VERSION`])            |
+//! | 6      | 2    | Global ROM checksum (header bytes 0x14E-0x14F) |
+//! | 8      | 1    | MBC type (header byte 0x147)                  |
+//! | 9      | 1    | Hardware model                                |
+//! | 10     | ...  | Component state                               |
+//!
+//! Component state is a plain concatenation of the fields of each component,
+//! in the order they're listed in the `impl_save_state!` invocations,
+//! starting from `Cpu` (which includes the `MemBus` and all components connected to it).
+//! Primitives are stored as-is (`usize` as `u64`, `bool` as a single byte),
+//! `Vec`s and queues are prefixed with their length (as `u32`),
+//! `Option`s are prefixed with a single byte (0 - `None`, 1 - `Some`)
+//! and enums are stored as a single byte variant index.
+//!
+//! Values that are used as indices or buffer sizes are checked after loading,
+//! out of range values fail with `YargeError::InvalidSaveState`.
+//!
+//! ROM data is never stored in the save state, it has to be loaded before calling `load_state`.
+//! The version has to be bumped every time the layout changes.
+
+use arraydeque::ArrayDeque;
+use crate::{Res, YargeError};
+
+pub const STATE_MAGIC: &[u8; 4] = b"YRGS";
+pub const STATE_VERSION: u16 = 6;
+
+pub(crate) struct StateReader<'a> {
+  data: &'a [u8],
+}
+impl<'a> StateReader<'a> {
+  pub fn new(data: &'a [u8]) -> Self {
+    Self { data }
+  }
+
+  pub fn take(&mut self, len: usize) -> Res<&'a [u8]> {
+    if len > self.data.len() {
+      return Err(YargeError::InvalidSaveState("unexpected end of data"));
+    }
+    let (taken, rest) = self.data.split_at(len);
+    self.data = rest;
+    Ok(taken)
+  }
+
+  pub fn take_array<const N: usize>(&mut self) -> Res<[u8; N]> {
+    Ok(self.take(N)?.try_into().unwrap())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.data.is_empty()
+  }
+}
+
+///Fails with `InvalidSaveState` unless `valid`,
+///used to range-check loaded values that are later used as indices
+pub(crate) fn check_state(valid: bool, message: &'static str) -> Res<()> {
+  match valid {
+    true => Ok(()),
+    false => Err(YargeError::InvalidSaveState(message)),
+  }
+}
+
+pub(crate) trait SaveState {
+  fn save_state(&self, out: &mut Vec<u8>);
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()>;
+}
+
+macro_rules! impl_save_state_int {
+  ($($t: ty),*) => {
+    $(
+      impl SaveState for $t {
+        fn save_state(&self, out: &mut Vec<u8>) {
+          out.extend_from_slice(&self.to_le_bytes());
+        }
+        fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+          *self = <$t>::from_le_bytes(input.take_array()?);
+          Ok(())
+        }
+      }
+    )*
+  };
+}
+impl_save_state_int!(u8, u16, u32, u64, i8, f32);
+
+impl SaveState for usize {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    (*self as u64).save_state(out);
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    let mut value = 0u64;
+    value.load_state(input)?;
+    *self = value.try_into().map_err(|_| YargeError::InvalidSaveState("value out of range"))?;
+    Ok(())
+  }
+}
+
+impl SaveState for bool {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    out.push(*self as u8);
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    *self = match input.take_array::<1>()?[0] {
+      0 => false,
+      1 => true,
+      _ => return Err(YargeError::InvalidSaveState("invalid boolean value")),
+    };
+    Ok(())
+  }
+}
+
+impl<T: SaveState, const N: usize> SaveState for [T; N] {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    for item in self {
+      item.save_state(out);
+    }
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    for item in self {
+      item.load_state(input)?;
+    }
+    Ok(())
+  }
+}
+
+impl<T: SaveState + ?Sized> SaveState for Box<T> {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    self.as_ref().save_state(out);
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    self.as_mut().load_state(input)
+  }
+}
+
+impl SaveState for Vec<u8> {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    (self.len() as u32).save_state(out);
+    out.extend_from_slice(self);
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    let mut len = 0u32;
+    len.load_state(input)?;
+    self.clear();
+    self.extend_from_slice(input.take(len as usize)?);
+    Ok(())
+  }
+}
+
+impl<T: SaveState + Default> SaveState for Option<T> {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    self.is_some().save_state(out);
+    if let Some(value) = self {
+      value.save_state(out);
+    }
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    let mut is_some = false;
+    is_some.load_state(input)?;
+    *self = match is_some {
+      true => {
+        let mut value = self.take().unwrap_or_default();
+        value.load_state(input)?;
+        Some(value)
+      },
+      false => None,
+    };
+    Ok(())
+  }
+}
+
+impl<T: SaveState + Default, const N: usize> SaveState for ArrayDeque<T, N> {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    (self.len() as u32).save_state(out);
+    for item in self {
+      item.save_state(out);
+    }
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    let mut len = 0u32;
+    len.load_state(input)?;
+    self.clear();
+    for _ in 0..len {
+      let mut item = T::default();
+      item.load_state(input)?;
+      self.push_back(item).map_err(|_| YargeError::InvalidSaveState("queue overflow"))?;
+    }
+    Ok(())
+  }
+}
+
+impl<A: SaveState, B: SaveState> SaveState for (A, B) {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    self.0.save_state(out);
+    self.1.save_state(out);
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    self.0.load_state(input)?;
+    self.1.load_state(input)
+  }
+}
+
+impl<A: SaveState, B: SaveState, C: SaveState, D: SaveState> SaveState for (A, B, C, D) {
+  fn save_state(&self, out: &mut Vec<u8>) {
+    self.0.save_state(out);
+    self.1.save_state(out);
+    self.2.save_state(out);
+    self.3.save_state(out);
+  }
+  fn load_state(&mut self, input: &mut StateReader) -> Res<()> {
+    self.0.load_state(input)?;
+    self.1.load_state(input)?;
+    self.2.load_state(input)?;
+    self.3.load_state(input)
+  }
+}
+
+///Implements `SaveState` for a struct by serializing the listed fields in order
+///(generic parameters can be passed in square brackets: `impl_save_state!([const N: u8] Type<N> { ... })`)
+///
+///`validate fn_name` can be added after the field list to call `self.fn_name() -> Res<()>` once all fields are loaded
+macro_rules! impl_save_state {
+  ([$($generics: tt)*] $type: ty { $($field: ident),* $(,)? } $(validate $validate: ident)?) => {
+    impl<$($generics)*> $crate::state::SaveState for $type {
+      #[allow(unused_variables)]
+      fn save_state(&self, out: &mut Vec<u8>) {
+        $( $crate::state::SaveState::save_state(&self.$field, out); )*
+      }
+      #[allow(unused_variables)]
+      fn load_state(&mut self, input: &mut $crate::state::StateReader) -> $crate::Res<()> {
+        $( $crate::state::SaveState::load_state(&mut self.$field, input)?; )*
+        $( self.$validate()?; )?
+        Ok(())
+      }
+    }
+  };
+  ($type: ty { $($field: ident),* $(,)? } $(validate $validate: ident)?) => {
+    impl_save_state!([] $type { $($field),* } $(validate $validate)?);
+  };
+}
+pub(crate) use impl_save_state;
+
+///Implements `SaveState` for a fieldless enum, storing the variant index as a single byte
+macro_rules! impl_save_state_enum {
+  ($type: ty { $($variant: ident),* $(,)? }) => {
+    impl $crate::state::SaveState for $type {
+      fn save_state(&self, out: &mut Vec<u8>) {
+        let variants = [$(<$type>::$variant),*];
+        out.push(variants.iter().position(|v| v == self).unwrap() as u8);
+      }
+      fn load_state(&mut self, input: &mut $crate::state::StateReader) -> $crate::Res<()> {
+        let variants = [$(<$type>::$variant),*];
+        let index = input.take_array::<1>()?[0] as usize;
+        *self = *variants.get(index).ok_or($crate::YargeError::InvalidSaveState("invalid enum variant"))?;
+        Ok(())
+      }
+    }
+  };
+}
+pub(crate) use impl_save_state_enum;
@@ -1,6 +1,8 @@
 use crate::{
   cpu::{Cpu, Interrupt},
   consts::TIMER_CLOCK_MASKS,
+  io_registers::TimerRegisters,
+  state::impl_save_state,
 };
 
 pub struct Timers {
@@ -11,6 +13,7 @@ pub struct Timers {
   enable: bool,
   rate: u8,
   tima_inc: bool,
+  overflow: bool,
 }
 impl Timers {
   pub fn new() -> Self {
@@ -22,6 +25,7 @@ impl Timers {
       enable: false,
       rate: 0,
       tima_inc: false,
+      overflow: false,
     }
   }
 
@@ -31,6 +35,9 @@ impl Timers {
   pub fn read_div(&self) -> u8 {
     (self.div >> 8) as u8
   }
+  pub fn set_div_raw(&mut self, value: u16) {
+    self.div = value;
+  }
   pub fn reset_div(&mut self) {
     self.div = 0;
   }
@@ -44,13 +51,28 @@ impl Timers {
   }
 
   pub fn read_tac(&self) -> u8 {
-    ((self.enable as u8) << 2) | self.rate
+    0xF8 | ((self.enable as u8) << 2) | self.rate
   }
   pub fn write_tac(&mut self, value: u8) {
     self.enable = value & 0b100 != 0;
     self.rate = value & 0b11;
   }
 
+  pub fn snapshot(&self) -> TimerRegisters {
+    TimerRegisters {
+      div: self.div,
+      tima: self.tima,
+      tma: self.tma,
+      enable: self.enable,
+      rate: self.rate,
+    }
+  }
+
+  ///Returns true once after TIMA overflows
+  pub fn take_overflow(&mut self) -> bool {
+    std::mem::take(&mut self.overflow)
+  }
+
   pub fn tick(&mut self, iif: &mut u8) {
     if self.tima_reset_pending {
       self.tima_reset_pending = false;
@@ -66,8 +88,11 @@ impl Timers {
       self.tima = tima;
       if carry {
         self.tima_reset_pending = true;
+        self.overflow = true;
       }
     }
     self.tima_inc = cur_tima_inc;
   }
 }
+
+impl_save_state!(Timers { tma, div, tima, tima_reset_pending, enable, rate, tima_inc });
@@ -1,9 +1,9 @@
-use super::gb::{AudioDevice, consts::AUDIO_BUFFER_SIZE};
+use super::gb::AudioDevice;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 
 struct TestAudioDevice;
 impl AudioDevice for TestAudioDevice {
-  fn queue_samples(&mut self, _: &[f32; AUDIO_BUFFER_SIZE]) {}
+  fn queue_samples(&mut self, _: &[f32]) {}
 }
 
 //WIP
@@ -8,17 +8,19 @@ use framework::{
   Gui, Dimensions as Dim,
   Icon
 };
-use egui::{Context, RichText, TextStyle, Color32};
+use egui::{Context, RichText, TextStyle, Color32, TextureHandle, TextureId, TextureFilter, ColorImage};
 use std::{
   fs,
   error::Error,
   hash::Hasher as _,
   time::Instant,
+  collections::VecDeque,
 };
 pub(crate) use crate::gb;
 use crate::{
-  gb::consts::{MBC_TYPE_LIST},
-  gb::{CpuState, Gameboy},
+  gb::consts::{MBC_TYPE_LIST, CYCLES_PER_FRAME},
+  gb::{CpuState, Gameboy, Renderer, RgbaImage, Symbols, AudioFilter},
+  gb::io_registers::{PpuMode, EnvelopeState, LengthState},
   NAME,
   VERSION,
   GITHUB_REPO,
@@ -34,23 +36,57 @@ use error_words::WORDS as ERROR_WORDS;
 
 #[cfg(feature = "audio")] mod audio;
 
-pub(crate) use gb::consts::{WIDTH as GB_WIDTH, HEIGHT as GB_HEIGHT};
-const WIDTH: u32 = GB_WIDTH as u32;
-const HEIGHT: u32 = GB_HEIGHT as u32;
 const SCALE: u32 = 4;
-const GB_PALETTE: [[u8; 4]; 4] = [
-  [0xe0, 0xf8, 0xd0, 0xff],
-  [0x88, 0xc0, 0x70, 0xff],
-  [0x34, 0x68, 0x56, 0xff],
-  [0x08, 0x18, 0x20, 0xff],
+///Give up on step over/out and "Run to" after ~10 seconds of emulated time
+const DEBUG_MAX_CYCLES: usize = CYCLES_PER_FRAME * 600;
+const DISASM_LINES: usize = 256;
+const VRAM_VIEW_SCALE: f32 = 2.;
+const SCOPE_SAMPLES: usize = 512;
+const PIANO_ROLL_FRAMES: usize = 256;
+const CHANNEL_COLORS: [Color32; 4] = [
+  Color32::from_rgb(0xFF, 0x60, 0x60),
+  Color32::from_rgb(0xFF, 0xC0, 0x40),
+  Color32::from_rgb(0x60, 0xC0, 0xFF),
+  Color32::from_rgb(0xA0, 0xFF, 0x80),
 ];
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+const GB_PALETTE: [[u8; 3]; 4] = [
+  [0xe0, 0xf8, 0xd0],
+  [0x88, 0xc0, 0x70],
+  [0x34, 0x68, 0x56],
+  [0x08, 0x18, 0x20],
+];
+
+///Step over/out and "Run to" target, approached one frame's worth of cycles at a time
+#[derive(Clone, Copy)]
+enum RunTarget {
+  CallDepth(usize),
+  Pc(u16),
+}
 
 pub struct GuiState {
   gb: Gameboy,
   gb_running: bool,
+  renderer: Renderer,
 
   gb_result: Result<(), gb::YargeError>,
   show_mem_view: bool,
+  show_disasm: bool,
+  disasm_follow_pc: bool,
+  disasm_addr: u16,
+  symbols: Option<Symbols>,
+  show_tileset: bool,
+  show_tilemap: bool,
+  tilemap_index: u8,
+  tilemap_overlay: bool,
+  show_oam: bool,
+  show_io_regs: bool,
+  show_audio_channels: bool,
+  ///MIDI note of each channel, sampled once per GUI frame
+  note_history: VecDeque<[Option<f32>; 4]>,
+  tileset_texture: Option<TextureHandle>,
+  tilemap_texture: Option<TextureHandle>,
+  oam_textures: Vec<Option<TextureHandle>>,
   load_force_mbc: bool,
   load_force_mbc_type: u8,
   load_no_reset: bool,
@@ -61,20 +97,50 @@ pub struct GuiState {
   enable_gui: bool,
   speed: u8,
   corrupt_amount: u16,
+  run_to_addr: u16,
+  run_cycles: usize,
+  run_target: Option<RunTarget>,
+  run_target_cycles: usize,
 
-  #[cfg(feature = "dbg-breakpoints")]
-  mmu_breakpoint_addr: u16,
   #[cfg(feature = "dbg-breakpoints")]
   pc_breakpoint_addr: u16,
+  #[cfg(feature = "dbg-breakpoints")]
+  pc_breakpoint_bank: Option<u16>,
+  #[cfg(feature = "dbg-breakpoints")]
+  pc_breakpoint_ignore: u32,
+  #[cfg(feature = "dbg-breakpoints")]
+  mmu_breakpoint_range: (u16, u16),
+  #[cfg(feature = "dbg-breakpoints")]
+  mmu_breakpoint_value: Option<u8>,
 }
 impl GuiState {
   pub fn new(gb: Gameboy) -> Self {
     Self {
       gb,
       gb_running: false,
+      renderer: {
+        let mut renderer = Renderer::new();
+        renderer.set_palette(GB_PALETTE);
+        renderer
+      },
 
       gb_result: Ok(()),
       show_mem_view: false,
+      show_disasm: false,
+      disasm_follow_pc: true,
+      disasm_addr: 0,
+      symbols: None,
+      show_tileset: false,
+      show_tilemap: false,
+      tilemap_index: 0,
+      tilemap_overlay: true,
+      show_oam: false,
+      show_io_regs: false,
+      show_audio_channels: false,
+      note_history: VecDeque::with_capacity(PIANO_ROLL_FRAMES),
+      tileset_texture: None,
+      tilemap_texture: None,
+      oam_textures: vec![None; 40],
       load_force_mbc: false,
       load_force_mbc_type: 0,
       load_no_reset: false,
@@ -85,21 +151,40 @@ impl GuiState {
       enable_gui: true,
       speed: 1,
       corrupt_amount: 100,
+      run_to_addr: 0x0100,
+      run_cycles: CYCLES_PER_FRAME,
+      run_target: None,
+      run_target_cycles: 0,
 
-      #[cfg(feature = "dbg-breakpoints")]
-      mmu_breakpoint_addr: 0,
       #[cfg(feature = "dbg-breakpoints")]
       pc_breakpoint_addr: 0,
+      #[cfg(feature = "dbg-breakpoints")]
+      pc_breakpoint_bank: None,
+      #[cfg(feature = "dbg-breakpoints")]
+      pc_breakpoint_ignore: 0,
+      #[cfg(feature = "dbg-breakpoints")]
+      mmu_breakpoint_range: (0xC000, 0xC000),
+      #[cfg(feature = "dbg-breakpoints")]
+      mmu_breakpoint_value: None,
     }
   }
+  fn set_run_target(&mut self, target: RunTarget) {
+    self.run_target = Some(target);
+    self.run_target_cycles = 0;
+  }
+
   ///Warning: consumes self!
   pub fn init(self) {
     #[cfg(feature = "audio")] audio::init();
+    let resolution = {
+      let (width, height) = Renderer::screen_size(&self.gb);
+      (width as u32, height as u32)
+    };
     framework::init(self, InitProperties {
       title: NAME.unwrap_or("open source gameboy emulator"),
-      pixels_resoltion: (WIDTH, HEIGHT),
-      min_size: (WIDTH, HEIGHT),
-      size: (WIDTH * SCALE, HEIGHT * SCALE),
+      pixels_resoltion: resolution,
+      min_size: resolution,
+      size: (resolution.0 * SCALE, resolution.1 * SCALE),
       window_icon: Some(Icon::from_rgba(Vec::from(&icons::ICON_WINDOW[..]), 64, 64).unwrap()),
       #[cfg(target_os = "windows")]
       taskbar_icon: Some(Icon::from_rgba(Vec::from(&icons::ICON_TASKBAR[..]), 256, 256).unwrap()),
@@ -111,6 +196,18 @@ impl GuiState {
 
 impl Gui for GuiState {
   fn prepare(&mut self) {
+    if let Some(target) = self.run_target {
+      let result = match target {
+        RunTarget::CallDepth(depth) => self.gb.run_to_call_depth(depth, CYCLES_PER_FRAME),
+        RunTarget::Pc(pc) => self.gb.run_to_pc(pc, CYCLES_PER_FRAME),
+      };
+      self.run_target_cycles += CYCLES_PER_FRAME;
+      match result {
+        Ok(false) if self.run_target_cycles < DEBUG_MAX_CYCLES => (),
+        _ => self.run_target = None,
+      }
+      self.gb_result = result.map(|_| ());
+    }
     if self.gb_running {
       let instant = Instant::now();
       if self.gb_result.is_ok() {
@@ -128,10 +225,7 @@ impl Gui for GuiState {
   fn render(&mut self, frame: &mut [u8]) {
     self.frame_time = self.last_render.elapsed().as_secs_f64();
     self.last_render = Instant::now();
-    let data = self.gb.get_display_data();
-    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-      pixel.copy_from_slice(&GB_PALETTE[(data[i] & 3) as usize]);
-    }
+    self.renderer.render_rgba8888(&self.gb, frame);
   }
   fn handle_input(&mut self, input: &WinitInputHelper) {
     
@@ -283,11 +377,8 @@ impl Gui for GuiState {
     }
 
     // HANDLE ERROR
-    if self.gb_result.is_err() {
-      let (str, recoverable) = {
-        let error = self.gb_result.as_ref().unwrap_err();
-        (error.to_string(), error.is_recoverable())
-      };
+    if let Err(error) = self.gb_result.as_ref() {
+      let (str, recoverable) = (error.to_string(), error.is_recoverable());
       error_window(format!(
         "{} error", 
         NAME.unwrap_or("emulator")).as_str(), 
@@ -351,6 +442,19 @@ impl Gui for GuiState {
                 .map_err(|err| { println!("Load error: {err}"); });
             }
           });
+          if ui.button("Load symbols...").clicked() {
+            ui.close_menu();
+            let file = FileDialog::new()
+              .add_filter("Symbol file", &["sym"])
+              .set_directory("/")
+              .pick_file();
+            if let Some(file) = file {
+              match fs::read_to_string(file) {
+                Ok(text) => self.symbols = Some(Symbols::parse(&text)),
+                Err(err) => println!("Load error: {err}"),
+              }
+            }
+          }
           if ui.button("Exit").clicked() {
             exit = true;
           }
@@ -374,6 +478,42 @@ impl Gui for GuiState {
               self.show_mem_view = true;
             }
           });
+          ui.add_enabled_ui(!self.show_disasm, |ui| {
+            if ui.button("Disassembly").clicked() {
+              ui.close_menu();
+              self.show_disasm = true;
+            }
+          });
+          ui.add_enabled_ui(!self.show_io_regs, |ui| {
+            if ui.button("IO registers").clicked() {
+              ui.close_menu();
+              self.show_io_regs = true;
+            }
+          });
+          ui.add_enabled_ui(!self.show_audio_channels, |ui| {
+            if ui.button("Audio channels").clicked() {
+              ui.close_menu();
+              self.show_audio_channels = true;
+            }
+          });
+          ui.add_enabled_ui(!self.show_tileset, |ui| {
+            if ui.button("Tileset").clicked() {
+              ui.close_menu();
+              self.show_tileset = true;
+            }
+          });
+          ui.add_enabled_ui(!self.show_tilemap, |ui| {
+            if ui.button("Tilemaps").clicked() {
+              ui.close_menu();
+              self.show_tilemap = true;
+            }
+          });
+          ui.add_enabled_ui(!self.show_oam, |ui| {
+            if ui.button("OAM").clicked() {
+              ui.close_menu();
+              self.show_oam = true;
+            }
+          });
         });
         ui.menu_button("Fun", |ui| {
           if ui.button("Corrupt some memory").clicked() {
@@ -450,6 +590,49 @@ impl Gui for GuiState {
           }
         });
       });
+      if self.run_target.is_some() {
+        ui.horizontal(|ui| {
+          ui.label("Running...");
+          if ui.button("Cancel").clicked() {
+            self.run_target = None;
+          }
+        });
+      }
+      ui.add_enabled_ui(!(self.gb_running || self.gb_result.is_err() || self.run_target.is_some()), |ui| {
+        ui.horizontal(|ui| {
+          if ui.button("Step over").clicked() {
+            let depth = self.gb.get_call_stack().len();
+            self.gb_result = self.gb.step().map(|_| ());
+            if self.gb_result.is_ok() {
+              self.set_run_target(RunTarget::CallDepth(depth));
+            }
+          }
+          if ui.button("Step out").clicked() {
+            let depth = self.gb.get_call_stack().len();
+            if depth > 0 {
+              self.set_run_target(RunTarget::CallDepth(depth - 1));
+            }
+          }
+          ui.separator();
+          if ui.button(RichText::new("Run to").monospace()).clicked() {
+            self.set_run_target(RunTarget::Pc(self.run_to_addr));
+          }
+          if let Some(v) = u16_edit(ui, "", self.run_to_addr, true, 1) {
+            self.run_to_addr = v;
+          }
+        });
+        ui.horizontal(|ui| {
+          if ui.button(RichText::new("Run for").monospace()).clicked() {
+            self.gb_result = self.gb.run_for_cycles(self.run_cycles).map(|_| ());
+          }
+          ui.add(
+            egui::DragValue::new(&mut self.run_cycles)
+              .suffix(" cycles")
+              .max_decimals(0)
+              .clamp_range(1..=usize::MAX)
+          );
+        });
+      });
       ui.add(egui::Slider::new(&mut self.speed, 1..=10).text("Speed"));
 
       //REGISTERS
@@ -519,6 +702,21 @@ impl Gui for GuiState {
         });
       });
 
+      egui::CollapsingHeader::new("Call stack").show(ui, |ui| {
+        let call_stack = self.gb.get_call_stack();
+        if call_stack.is_empty() {
+          ui.label("Empty");
+        }
+        for frame in call_stack.iter().rev() {
+          ui.monospace(format!(
+            "{:04X} -> {:04X}{}",
+            frame.return_addr,
+            frame.target,
+            if frame.interrupt { " (interrupt)" } else { "" }
+          ));
+        }
+      });
+
       //BREAKPOINTS
       {
         const ENABLED: bool = {
@@ -530,60 +728,112 @@ impl Gui for GuiState {
             "Breakpoints"
           ).show(ui, |ui| {
             #[cfg(feature = "dbg-breakpoints")] {
-              ui.label("MMU breakpoints have been removed!");
-              // ui.horizontal(|ui| {
-              //   if let Some(v) = u16_edit(ui, "MMU", self.mmu_breakpoint_addr, true, 1) {
-              //     self.mmu_breakpoint_addr = v;
-              //   }
-              //   if ui.button("R/W").clicked() {
-              //     self.gb.set_mmu_breakpoint(
-              //       self.mmu_breakpoint_addr,
-              //       0b11
-              //     )
-              //   }
-              //   if ui.button("R").clicked() {
-              //     self.gb.set_mmu_breakpoint(
-              //       self.mmu_breakpoint_addr,
-              //       0b01
-              //     )
-              //   }
-              //   if ui.button("W").clicked() {
-              //     self.gb.set_mmu_breakpoint(
-              //       self.mmu_breakpoint_addr,
-              //       0b10
-              //     )
-              //   }
-              //   if ui.button("Disable").clicked() {
-              //     self.gb.set_mmu_breakpoint(
-              //       self.mmu_breakpoint_addr,
-              //       0b00
-              //     )
-              //   }
-              // });
+              ui.horizontal(|ui| {
+                if let Some(v) = u16_edit(ui, "MMU", self.mmu_breakpoint_range.0, true, 1) {
+                  self.mmu_breakpoint_range = (v, v.max(self.mmu_breakpoint_range.1));
+                }
+                if let Some(v) = u16_edit(ui, "to", self.mmu_breakpoint_range.1, true, 1) {
+                  self.mmu_breakpoint_range = (v.min(self.mmu_breakpoint_range.0), v);
+                }
+              });
+              ui.horizontal(|ui| {
+                let mut match_value = self.mmu_breakpoint_value.is_some();
+                ui.checkbox(&mut match_value, "Value");
+                self.mmu_breakpoint_value = match_value.then(|| self.mmu_breakpoint_value.unwrap_or(0));
+                if let Some(value) = self.mmu_breakpoint_value {
+                  if let Some(v) = u16_edit(ui, "", value as u16, true, 1) {
+                    self.mmu_breakpoint_value = Some(v as u8);
+                  }
+                }
+              });
+              ui.horizontal(|ui| {
+                for (name, read, write) in [("R/W", true, true), ("R", true, false), ("W", false, true)] {
+                  if ui.button(name).clicked() {
+                    let (start, end) = self.mmu_breakpoint_range;
+                    let mut watchpoint = gb::Watchpoint::new(start..=end, read, write);
+                    watchpoint.value = self.mmu_breakpoint_value;
+                    self.gb.add_watchpoint(watchpoint);
+                  }
+                }
+                if ui.button("Clear all").clicked() {
+                  self.gb.clear_watchpoints();
+                }
+              });
+              let mut remove = None;
+              for (i, watchpoint) in self.gb.get_watchpoints().iter().enumerate() {
+                ui.horizontal(|ui| {
+                  ui.monospace(format!(
+                    "{:04X}-{:04X} {}{}{}",
+                    watchpoint.range.start(),
+                    watchpoint.range.end(),
+                    if watchpoint.read { "R" } else { "" },
+                    if watchpoint.write { "W" } else { "" },
+                    watchpoint.value.map(|x| format!(" ={:02X}", x)).unwrap_or_default(),
+                  ));
+                  if ui.small_button("x").clicked() {
+                    remove = Some(i);
+                  }
+                });
+              }
+              if let Some(i) = remove {
+                self.gb.remove_watchpoint(i);
+              }
+              ui.separator();
               ui.horizontal(|ui| {
                 if let Some(v) = u16_edit(ui, "PC ", self.pc_breakpoint_addr, true, 1) {
                   self.pc_breakpoint_addr = v;
                 }
                 if ui.button("Enable").clicked() {
-                  self.gb.set_pc_breakpoint(
-                    self.pc_breakpoint_addr,
-                    true
-                  );
+                  let mut breakpoint = gb::Breakpoint::new(self.pc_breakpoint_addr);
+                  breakpoint.bank = self.pc_breakpoint_bank;
+                  breakpoint.ignore_count = self.pc_breakpoint_ignore;
+                  self.gb.add_breakpoint(breakpoint);
                 }
                 if ui.button("Disable").clicked() {
-                  self.gb.set_pc_breakpoint(
-                    self.pc_breakpoint_addr,
-                    false
-                  );
+                  let addr = self.pc_breakpoint_addr;
+                  self.gb.get_breakpoints_mut().retain(|x| x.addr != addr);
                 }
               });
+              ui.horizontal(|ui| {
+                let mut match_bank = self.pc_breakpoint_bank.is_some();
+                ui.checkbox(&mut match_bank, "Bank");
+                self.pc_breakpoint_bank = match_bank.then(|| self.pc_breakpoint_bank.unwrap_or(1));
+                if let Some(bank) = self.pc_breakpoint_bank {
+                  if let Some(v) = u16_edit(ui, "", bank, true, 1) {
+                    self.pc_breakpoint_bank = Some(v);
+                  }
+                }
+                ui.label("Ignore:");
+                ui.add(egui::DragValue::new(&mut self.pc_breakpoint_ignore));
+              });
+              let mut remove = None;
+              for (i, breakpoint) in self.gb.get_breakpoints().iter().enumerate() {
+                ui.horizontal(|ui| {
+                  ui.monospace(format!(
+                    "{}:{:04X} hits: {}/{}{}",
+                    breakpoint.bank.map(|x| format!("{:02X}", x)).unwrap_or_else(|| "**".into()),
+                    breakpoint.addr,
+                    breakpoint.hits,
+                    breakpoint.ignore_count + 1,
+                    match breakpoint.conditions.len() {
+                      0 => String::new(),
+                      n => format!(" ({} conditions)", n),
+                    }
+                  ));
+                  if ui.small_button("x").clicked() {
+                    remove = Some(i);
+                  }
+                });
+              }
+              if let Some(i) = remove {
+                self.gb.remove_breakpoint(i);
+              }
               egui::CollapsingHeader::new(
                 RichText::new("Warning").color(Color32::LIGHT_YELLOW)
               ).show(ui, |ui| {
                 ui.label("Breakpoints are experimental");
-                ui.label("1) PC breakpoints get triggered AFTER the instruction is executed");
-                ui.label("2) PC breakpoints can cause minor timing issues");
-                ui.label("3) MMU breakpoints are NOT recoverable");
+                ui.label("1) PC breakpoints can cause minor timing issues");
+                ui.label("2) MMU breakpoints stop execution after the instruction that triggered them");
               });
             }
           });
@@ -642,6 +892,70 @@ impl Gui for GuiState {
       });
     });
 
+    //DISASSEMBLY WINDOW
+    if self.show_disasm {
+      egui::Window::new("Disassembly").open(&mut self.show_disasm).show(ui, |ui| {
+        ui.horizontal(|ui| {
+          ui.checkbox(&mut self.disasm_follow_pc, "Follow PC");
+          ui.add_enabled_ui(!self.disasm_follow_pc, |ui| {
+            if let Some(v) = u16_edit(ui, "", self.disasm_addr, true, 1) {
+              self.disasm_addr = v;
+            }
+          });
+        });
+        if self.disasm_follow_pc {
+          self.disasm_addr = self.gb.get_reg_pc();
+        }
+        ui.separator();
+        let pc = self.gb.get_reg_pc();
+        let symbols = self.symbols.as_ref();
+        egui::ScrollArea::vertical().always_show_scroll(true).show(ui, |ui| {
+          let mut addr = self.disasm_addr;
+          for _ in 0..DISASM_LINES {
+            let instr = self.gb.disassemble(addr);
+            let bank = self.gb.get_bank_at(addr);
+            if let Some(label) = symbols.and_then(|x| x.get(bank, addr)) {
+              ui.monospace(RichText::new(format!("{}:", label)).color(Color32::LIGHT_BLUE));
+            }
+            let bytes = (0..instr.length as u16)
+              .map(|i| format!("{:02X}", self.gb.read_mem(addr.wrapping_add(i))))
+              .collect::<Vec<_>>()
+              .join(" ");
+            let color = if addr == pc {
+              Color32::LIGHT_RED
+            } else {
+              const DEFAULT_COLOR: Color32 = Color32::WHITE;
+              #[cfg(feature = "dbg-breakpoints")]
+              if self.gb.get_pc_breakpoint(addr) {
+                Color32::DARK_GREEN
+              } else {
+                DEFAULT_COLOR
+              }
+              #[cfg(not(feature = "dbg-breakpoints"))] {
+                DEFAULT_COLOR
+              }
+            };
+            let response = ui.add(egui::Label::new(
+              RichText::new(format!("{:02X}:{:04X}  {:<8}  {}", bank, addr, bytes, instr.format(symbols)))
+                .monospace()
+                .color(color)
+            ).sense(egui::Sense::click())).on_hover_text(match instr.cycles_branch {
+              Some(cycles) => format!("Cycles: {} ({} if taken)", instr.cycles, cycles),
+              None => format!("Cycles: {}", instr.cycles),
+            });
+            #[cfg(feature = "dbg-breakpoints")]
+            if response.clicked() {
+              let enable = !self.gb.get_pc_breakpoint(addr);
+              self.gb.set_pc_breakpoint(addr, enable);
+            }
+            #[cfg(not(feature = "dbg-breakpoints"))]
+            let _ = response;
+            addr = addr.wrapping_add(instr.length as u16);
+          }
+        });
+      });
+    }
+
     //MEMORY VIEW WINDOW
     if self.show_mem_view {
       egui::Window::new("Memory view").open(&mut self.show_mem_view).show(ui, |ui| {
@@ -693,6 +1007,286 @@ impl Gui for GuiState {
       });
     }
 
+    //IO REGISTERS WINDOW
+    if self.show_io_regs {
+      egui::Window::new("IO registers").open(&mut self.show_io_regs).show(ui, |ui| {
+        let io = self.gb.get_io_registers();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+          egui::CollapsingHeader::new("LCD").default_open(true).show(ui, |ui| {
+            let ppu = io.ppu;
+            egui::Grid::new("io_lcd").show(ui, |ui| {
+              io_value(ui, "LCDC", format!("{:02X}", ppu.lcdc.into_u8()));
+              io_flag(ui, "7 LCD enable", ppu.lcdc.enable_display);
+              io_value(ui, "6 Window map", if ppu.lcdc.win_tilemap_addr { "9C00" } else { "9800" });
+              io_flag(ui, "5 Window enable", ppu.lcdc.enable_win);
+              io_value(ui, "4 Tile data", if ppu.lcdc.tiledata_addr { "8000" } else { "8800" });
+              io_value(ui, "3 BG map", if ppu.lcdc.bg_tilemap_addr { "9C00" } else { "9800" });
+              io_value(ui, "2 OBJ size", if ppu.lcdc.obj_size { "8x16" } else { "8x8" });
+              io_flag(ui, "1 OBJ enable", ppu.lcdc.enable_obj);
+              io_flag(ui, "0 BG/Window enable", ppu.lcdc.enable_bg);
+              io_value(ui, "STAT", format!("{:02X}", self.gb.read_mem(0xFF41)));
+              io_flag(ui, "6 LYC=LY interrupt", ppu.stat.lyc_eq);
+              io_flag(ui, "5 Mode 2 interrupt", ppu.stat.mode_2);
+              io_flag(ui, "4 Mode 1 interrupt", ppu.stat.mode_1);
+              io_flag(ui, "3 Mode 0 interrupt", ppu.stat.mode_0);
+              io_flag(ui, "2 LYC=LY", ppu.lyc_eq);
+              io_value(ui, "0-1 Mode", match ppu.mode {
+                PpuMode::HBlank => "0 (HBlank)",
+                PpuMode::VBlank => "1 (VBlank)",
+                PpuMode::OamSearch => "2 (OAM search)",
+                PpuMode::PxTransfer => "3 (Pixel transfer)",
+              });
+              io_value(ui, "LY/LYC", format!("{:02X}/{:02X}", ppu.ly, ppu.lyc));
+              io_value(ui, "SCX/SCY", format!("{:02X}/{:02X}", ppu.scx, ppu.scy));
+              io_value(ui, "WX/WY", format!("{:02X}/{:02X}", ppu.wx, ppu.wy));
+              io_value(ui, "Window line", format!("{:02X}", ppu.wly));
+              io_value(ui, "BGP", format!("{:02X}", ppu.bgp));
+              io_value(ui, "OBP0/OBP1", format!("{:02X}/{:02X}", ppu.obp.0, ppu.obp.1));
+            });
+          });
+          egui::CollapsingHeader::new("Interrupts").default_open(true).show(ui, |ui| {
+            let int = io.interrupts;
+            egui::Grid::new("io_interrupts").show(ui, |ui| {
+              ui.label("");
+              ui.label(RichText::new("IE").strong());
+              ui.label(RichText::new("IF").strong());
+              ui.end_row();
+              for (i, name) in ["VBlank", "STAT", "Timer", "Serial", "Joypad"].into_iter().enumerate() {
+                ui.label(format!("{} {}", i, name));
+                io_bit(ui, (int.ie & (1 << i)) != 0);
+                io_bit(ui, (int.iif & (1 << i)) != 0);
+                ui.end_row();
+              }
+              io_flag(ui, "IME", int.ime);
+              io_flag(ui, "IME pending (EI)", int.ime_pending);
+              io_value(ui, "CPU state", format!("{:?}", int.state));
+            });
+          });
+          egui::CollapsingHeader::new("Timers").show(ui, |ui| {
+            let timers = io.timers;
+            egui::Grid::new("io_timers").show(ui, |ui| {
+              io_value(ui, "DIV (internal)", format!("{:04X}", timers.div));
+              io_value(ui, "TIMA", format!("{:02X}", timers.tima));
+              io_value(ui, "TMA", format!("{:02X}", timers.tma));
+              io_flag(ui, "TAC 2 Enable", timers.enable);
+              io_value(ui, "TAC 0-1 Clock", format!("{} ({} Hz)", timers.rate, timers.frequency()));
+            });
+          });
+          egui::CollapsingHeader::new("Joypad/Serial").show(ui, |ui| {
+            egui::Grid::new("io_joyp_serial").show(ui, |ui| {
+              io_value(ui, "JOYP", format!("{:02X}", io.joypad.joyp));
+              io_flag(ui, "5 Select buttons", io.joypad.select_action);
+              io_flag(ui, "4 Select d-pad", io.joypad.select_direction);
+              io_value(ui, "SB", format!("{:02X}", io.serial.sb));
+              io_flag(ui, "SC 7 Transfer", io.serial.transfer);
+              io_flag(ui, "SC 0 Internal clock", io.serial.internal_clock);
+              io_value(ui, "Bits shifted", io.serial.bits.to_string());
+            });
+          });
+          egui::CollapsingHeader::new("Audio").show(ui, |ui| {
+            let apu = io.apu;
+            egui::Grid::new("io_apu").show(ui, |ui| {
+              io_flag(ui, "NR52 7 APU enable", apu.enabled);
+              io_value(ui, "Frame sequencer", apu.sequencer.to_string());
+              for (name, terminal) in [("Left", apu.left), ("Right", apu.right)] {
+                io_value(ui, &format!("{} volume", name), terminal.volume.to_string());
+                io_flag(ui, &format!("{} VIN", name), terminal.vin);
+                io_value(ui, &format!("{} channels", name), terminal.channels.iter().enumerate().map(|(i, &x)| {
+                  if x { char::from(b'1' + i as u8) } else { '-' }
+                }).collect::<String>());
+              }
+            });
+            for (i, ch) in [apu.channel1, apu.channel2].into_iter().enumerate() {
+              egui::CollapsingHeader::new(format!("CH{} (Square)", i + 1)).show(ui, |ui| {
+                egui::Grid::new(("io_square", i)).show(ui, |ui| {
+                  io_flag(ui, "Enabled", ch.enabled);
+                  io_flag(ui, "DAC", ch.dac_enabled);
+                  io_value(ui, "Duty", format!("{} ({})", ch.duty, ["12.5%", "25%", "50%", "75%"][(ch.duty & 3) as usize]));
+                  io_value(ui, "Duty position", ch.duty_position.to_string());
+                  io_value(ui, "Frequency", format!("{:03X}", ch.frequency));
+                  io_value(ui, "Frequency timer", ch.freq_timer.to_string());
+                  io_length(ui, ch.length);
+                  io_envelope(ui, ch.envelope);
+                  if let Some(sweep) = ch.sweep {
+                    io_value(ui, "Sweep period", sweep.period.to_string());
+                    io_flag(ui, "Sweep negate", sweep.negate);
+                    io_value(ui, "Sweep shift", sweep.shift.to_string());
+                  }
+                });
+              });
+            }
+            egui::CollapsingHeader::new("CH3 (Wave)").show(ui, |ui| {
+              let ch = apu.channel3;
+              egui::Grid::new("io_wave").show(ui, |ui| {
+                io_flag(ui, "Enabled", ch.enabled);
+                io_flag(ui, "DAC", ch.dac_enabled);
+                io_value(ui, "Output level", format!("{} ({})", ch.output_level, ["0%", "100%", "50%", "25%"][(ch.output_level & 3) as usize]));
+                io_value(ui, "Position", ch.position.to_string());
+                io_value(ui, "Sample", format!("{:X}", ch.sample));
+                io_value(ui, "Frequency", format!("{:03X}", ch.frequency));
+                io_value(ui, "Frequency timer", ch.freq_timer.to_string());
+                io_length(ui, ch.length);
+                io_value(ui, "Wave RAM", ch.wave_ram.iter().map(|x| format!("{:02X}", x)).collect::<String>());
+              });
+            });
+            egui::CollapsingHeader::new("CH4 (Noise)").show(ui, |ui| {
+              let ch = apu.channel4;
+              egui::Grid::new("io_noise").show(ui, |ui| {
+                io_flag(ui, "Enabled", ch.enabled);
+                io_flag(ui, "DAC", ch.dac_enabled);
+                io_value(ui, "Shift", ch.shift.to_string());
+                io_value(ui, "Width", if ch.width_short { "7 bit" } else { "15 bit" });
+                io_value(ui, "Divider", ch.divider.to_string());
+                io_value(ui, "Frequency timer", ch.freq_timer.to_string());
+                io_value(ui, "LFSR", format!("{:04X}", ch.lfsr));
+                io_length(ui, ch.length);
+                io_envelope(ui, ch.envelope);
+              });
+            });
+          });
+        });
+      });
+    }
+
+    //AUDIO CHANNELS WINDOW
+    if self.show_audio_channels {
+      egui::Window::new("Audio channels").open(&mut self.show_audio_channels).resizable(false).show(ui, |ui| {
+        let apu = self.gb.get_io_registers().apu;
+        let (ch1, ch2, ch3, ch4) = (apu.channel1, apu.channel2, apu.channel3, apu.channel4);
+        //(enabled, frequency, volume, length timer)
+        let info = [
+          (ch1.enabled, ch1.frequency_hz(), format!("{:X}", ch1.envelope.volume), ch1.length.timer),
+          (ch2.enabled, ch2.frequency_hz(), format!("{:X}", ch2.envelope.volume), ch2.length.timer),
+          (ch3.enabled, ch3.frequency_hz(), ["0%", "100%", "50%", "25%"][(ch3.output_level & 3) as usize].to_string(), ch3.length.timer),
+          (ch4.enabled, ch4.frequency_hz(), format!("{:X}", ch4.envelope.volume), ch4.length.timer),
+        ];
+        //Noise has no pitch, so it's not shown on the piano roll
+        let notes = [0, 1, 2, 3].map(|i| (i < 3 && info[i].0).then(|| hz_to_note(info[i].1)));
+        if self.gb_running || self.note_history.is_empty() {
+          if self.note_history.len() >= PIANO_ROLL_FRAMES {
+            self.note_history.pop_front();
+          }
+          self.note_history.push_back(notes);
+        }
+        ui.horizontal(|ui| {
+          ui.label("High-pass filter:");
+          let mut filter = self.gb.get_audio_filter();
+          ui.radio_value(&mut filter, AudioFilter::Dmg, "DMG");
+          ui.radio_value(&mut filter, AudioFilter::Cgb, "CGB");
+          ui.radio_value(&mut filter, AudioFilter::Raw, "Raw");
+          if filter != self.gb.get_audio_filter() {
+            self.gb.set_audio_filter(filter);
+          }
+        });
+        let mut samples = [0.; SCOPE_SAMPLES];
+        for (i, name) in ["CH1 Square", "CH2 Square", "CH3 Wave", "CH4 Noise"].into_iter().enumerate() {
+          let (enabled, frequency, volume, length) = &info[i];
+          ui.horizontal(|ui| {
+            ui.label(RichText::new(name).strong().color(CHANNEL_COLORS[i]));
+            let mut muted = self.gb.get_channel_muted(i);
+            if ui.checkbox(&mut muted, "Mute").changed() {
+              self.gb.set_channel_muted(i, muted);
+            }
+            let mut solo = self.gb.get_channel_solo(i);
+            if ui.checkbox(&mut solo, "Solo").changed() {
+              self.gb.set_channel_solo(i, solo);
+            }
+          });
+          ui.horizontal(|ui| {
+            let len = self.gb.get_channel_samples(i, &mut samples);
+            draw_scope(ui, &samples[..len], CHANNEL_COLORS[i], *enabled && self.gb.is_channel_audible(i));
+            ui.vertical(|ui| {
+              ui.monospace(match (enabled, i) {
+                (false, _) => "Off".to_string(),
+                (true, 3) => format!("{:.0} Hz", frequency),
+                (true, _) => format!("{:.1} Hz {}", frequency, note_name(hz_to_note(*frequency))),
+              });
+              ui.monospace(format!("Volume: {}", volume));
+              ui.monospace(format!("Length: {}", length));
+            });
+          });
+        }
+        ui.separator();
+        ui.label("Piano roll");
+        draw_piano_roll(ui, &self.note_history);
+      });
+    }
+
+    //TILESET WINDOW
+    if self.show_tileset {
+      egui::Window::new("Tileset").open(&mut self.show_tileset).resizable(false).show(ui, |ui| {
+        let image = self.renderer.render_tileset(&self.gb);
+        let size = egui::vec2(image.width as f32, image.height as f32) * VRAM_VIEW_SCALE;
+        let texture = update_texture(ui.ctx(), &mut self.tileset_texture, "tileset", &image);
+        let response = ui.image(texture, size);
+        if let Some(pos) = response.hover_pos() {
+          let pos = (pos - response.rect.min) / (8. * VRAM_VIEW_SCALE);
+          let (bank, tile) = ((pos.x as usize) >> 4, ((pos.y as usize) << 4) | (pos.x as usize & 0xF));
+          response.on_hover_text(format!("Tile: {:#05X} (bank {})\nAddr: {:#06X}", tile, bank, 0x8000 + (tile << 4)));
+        }
+      });
+    }
+
+    //TILEMAP WINDOW
+    if self.show_tilemap {
+      egui::Window::new("Tilemaps").open(&mut self.show_tilemap).resizable(false).show(ui, |ui| {
+        ui.horizontal(|ui| {
+          ui.radio_value(&mut self.tilemap_index, 0, "9800");
+          ui.radio_value(&mut self.tilemap_index, 1, "9C00");
+          ui.checkbox(&mut self.tilemap_overlay, "Show viewport/window");
+        });
+        let image = self.renderer.render_tilemap(&self.gb, self.tilemap_index, self.tilemap_overlay);
+        let size = egui::vec2(image.width as f32, image.height as f32) * VRAM_VIEW_SCALE;
+        let texture = update_texture(ui.ctx(), &mut self.tilemap_texture, "tilemap", &image);
+        let response = ui.image(texture, size);
+        if let Some(pos) = response.hover_pos() {
+          let pos = (pos - response.rect.min) / (8. * VRAM_VIEW_SCALE);
+          let addr = 0x9800 + ((self.tilemap_index as u16) << 10) + ((pos.y as u16) << 5) + pos.x as u16;
+          let tile = self.gb.get_vram(0)[(addr - 0x8000) as usize];
+          response.on_hover_text(format!("X: {}, Y: {}\nAddr: {:#06X}\nTile: {:#04X}", pos.x as u8, pos.y as u8, addr, tile));
+        }
+      });
+    }
+
+    //OAM WINDOW
+    if self.show_oam {
+      egui::Window::new("OAM").open(&mut self.show_oam).show(ui, |ui| {
+        let images = self.renderer.render_oam(&self.gb);
+        let cgb = self.gb.is_cgb_mode();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+          egui::Grid::new("oam_grid").striped(true).show(ui, |ui| {
+            for header in ["#", "", "X", "Y", "Tile", "Palette", "Flip", "Priority"] {
+              ui.label(RichText::new(header).strong());
+            }
+            ui.end_row();
+            for ((object, image), texture) in self.gb.get_oam().iter().zip(&images).zip(self.oam_textures.iter_mut()) {
+              let flags = object.flags;
+              ui.monospace(format!("{:02}", object.id));
+              let size = egui::vec2(image.width as f32, image.height as f32) * VRAM_VIEW_SCALE;
+              ui.image(update_texture(ui.ctx(), texture, "oam", image), size);
+              ui.monospace(format!("{:02X}", object.x));
+              ui.monospace(format!("{:02X}", object.y));
+              ui.monospace(match cgb {
+                true => format!("{:02X}:{}", object.tile, flags.cgb_bank()),
+                false => format!("{:02X}", object.tile),
+              });
+              ui.monospace(match cgb {
+                true => format!("CGB {}", flags.cgb_palette()),
+                false => format!("OBP{}", flags.palette as u8),
+              });
+              ui.monospace(format!(
+                "{}{}",
+                if flags.flip_x { "X" } else { "-" },
+                if flags.flip_y { "Y" } else { "-" },
+              ));
+              ui.monospace(if flags.priority { "BG" } else { "OBJ" });
+              ui.end_row();
+            }
+          });
+        });
+      });
+    }
+
     //TODO ...and this
     if reset_error_window || error_continue {
       self.gb_result = Ok(());
@@ -704,3 +1298,93 @@ impl Gui for GuiState {
     exit
   }
 }
+
+fn io_bit(ui: &mut egui::Ui, value: bool) {
+  ui.label(RichText::new(if value { "1" } else { "0" }).monospace().color(
+    if value { Color32::LIGHT_GREEN } else { Color32::GRAY }
+  ));
+}
+
+fn io_flag(ui: &mut egui::Ui, name: &str, value: bool) {
+  ui.label(name);
+  io_bit(ui, value);
+  ui.end_row();
+}
+
+fn io_value(ui: &mut egui::Ui, name: &str, value: impl Into<String>) {
+  ui.label(name);
+  ui.monospace(value.into());
+  ui.end_row();
+}
+
+fn io_length(ui: &mut egui::Ui, length: LengthState) {
+  io_flag(ui, "Length enable", length.enable);
+  io_value(ui, "Length timer", length.timer.to_string());
+}
+
+fn io_envelope(ui: &mut egui::Ui, envelope: EnvelopeState) {
+  io_value(ui, "Volume", envelope.volume.to_string());
+  io_value(ui, "Envelope start", envelope.start_volume.to_string());
+  io_value(ui, "Envelope direction", if envelope.increase { "Up" } else { "Down" });
+  io_value(ui, "Envelope period", envelope.period.to_string());
+}
+
+fn hz_to_note(hz: f32) -> f32 {
+  69. + 12. * (hz / 440.).log2()
+}
+
+fn note_name(note: f32) -> String {
+  let rounded = note.round();
+  let index = (rounded as i32).rem_euclid(12) as usize;
+  let octave = (rounded as i32).div_euclid(12) - 1;
+  format!("{}{} {:+.0}c", NOTE_NAMES[index], octave, (note - rounded) * 100.)
+}
+
+fn draw_scope(ui: &mut egui::Ui, samples: &[f32], color: Color32, active: bool) {
+  let (rect, _) = ui.allocate_exact_size(egui::vec2(256., 48.), egui::Sense::hover());
+  let painter = ui.painter_at(rect);
+  painter.rect_filled(rect, 0., Color32::from_gray(16));
+  painter.hline(rect.x_range(), rect.center().y, (1., Color32::from_gray(48)));
+  let color = if active { color } else { color.linear_multiply(0.3) };
+  let points: Vec<egui::Pos2> = samples.iter().enumerate().map(|(i, &sample)| {
+    egui::pos2(
+      rect.left() + rect.width() * i as f32 / samples.len().max(1) as f32,
+      rect.center().y - sample.clamp(-1., 1.) * rect.height() * 0.45,
+    )
+  }).collect();
+  painter.add(egui::Shape::line(points, (1., color)));
+}
+
+fn draw_piano_roll(ui: &mut egui::Ui, history: &VecDeque<[Option<f32>; 4]>) {
+  //C2-C8
+  const LOW: f32 = 36.;
+  const HIGH: f32 = 108.;
+  let (rect, _) = ui.allocate_exact_size(egui::vec2(PIANO_ROLL_FRAMES as f32 * 2., 144.), egui::Sense::hover());
+  let painter = ui.painter_at(rect);
+  painter.rect_filled(rect, 0., Color32::from_gray(16));
+  let note_y = |note: f32| rect.bottom() - (note - LOW) / (HIGH - LOW) * rect.height();
+  for octave in (LOW as i32..=HIGH as i32).step_by(12) {
+    painter.hline(rect.x_range(), note_y(octave as f32), (1., Color32::from_gray(40)));
+  }
+  let width = rect.width() / PIANO_ROLL_FRAMES as f32;
+  let offset = PIANO_ROLL_FRAMES - history.len();
+  for (frame, notes) in history.iter().enumerate() {
+    let x = rect.left() + (offset + frame) as f32 * width;
+    for (channel, note) in notes.iter().enumerate() {
+      if let Some(note) = note.filter(|x| (LOW..=HIGH).contains(x)) {
+        let y = note_y(note);
+        painter.rect_filled(egui::Rect::from_min_max(egui::pos2(x, y - 1.5), egui::pos2(x + width, y + 1.5)), 0., CHANNEL_COLORS[channel]);
+      }
+    }
+  }
+}
+
+///Upload `image` to `texture` (creating it if needed)
+fn update_texture(ctx: &Context, texture: &mut Option<TextureHandle>, name: &str, image: &RgbaImage) -> TextureId {
+  let image = ColorImage::from_rgba_unmultiplied([image.width, image.height], &image.data);
+  match texture {
+    Some(texture) => texture.set(image, TextureFilter::Nearest),
+    None => *texture = Some(ctx.load_texture(name, image, TextureFilter::Nearest)),
+  }
+  texture.as_ref().unwrap().id()
+}
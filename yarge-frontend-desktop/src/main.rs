@@ -1,5 +1,5 @@
 pub(crate) use yarge_core as gb;
-use gb::Gameboy;
+use gb::{Gameboy, Model};
 use clap::Parser;
 use build_time::build_time_local;
 
@@ -7,6 +7,7 @@ pub(crate) const NAME: Option<&str> = option_env!("CARGO_PKG_NAME");
 pub(crate) const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 pub(crate) const BUILD_TIME: &str = build_time_local!("%Y-%m-%dT%H:%M:%S%.f%:z");
 pub(crate) const GITHUB_REPO: &str = "https://github.com/griffi-gh/yarge";
+const EVENT_CAPACITY: usize = 1 << 22;
 
 #[cfg(feature = "gui")] mod gui;
 
@@ -17,6 +18,12 @@ struct Args {
   skip_bootrom: bool,
   #[clap(short, long)]
   nogui: bool,
+  ///Hardware model to emulate (dmg0, dmg, mgb, sgb, sgb2 or cgb)
+  #[clap(long, default_value = "dmg")]
+  model: Model,
+  ///Record emulation events and save them as JSON lines once emulation stops (nogui only)
+  #[clap(long)]
+  events: Option<String>,
   path: Option<String>
 }
 
@@ -26,6 +33,8 @@ fn main() {
     path: rom_path, 
     nogui, 
     skip_bootrom,
+    model,
+    events,
   } = args;
 
   println!(
@@ -46,22 +55,28 @@ fn main() {
     panic!("No GUI support, use the --nogui (-n) flag or build {} with 'gui' feature", NAME.unwrap_or(""));
   }
   
-  let mut gb = Gameboy::new();
+  let mut gb = Gameboy::builder().model(model).build().unwrap();
   gb.init();
   if skip_bootrom {
     gb.skip_bootrom();
   }
 
   if nogui {
-    gb.load_rom_file(
-      rom_path.expect("No ROM path specified").as_str()
-    ).expect("Failed to load the ROM file");
-    gb.run().unwrap();
+    let rom = std::fs::read(rom_path.expect("No ROM path specified")).expect("Failed to read the ROM file");
+    gb.load_rom(&rom).expect("Failed to load the ROM file");
+    if events.is_some() {
+      gb.enable_event_recording(EVENT_CAPACITY);
+    }
+    let result = gb.run();
+    if let Some(path) = events {
+      let mut file = std::io::BufWriter::new(std::fs::File::create(path).expect("Failed to create the event log file"));
+      gb.get_event_recorder().unwrap().write_json_lines(&mut file).expect("Failed to write the event log file");
+    }
+    result.unwrap();
   } else {
     if let Some(rom_path) = rom_path {
-      gb.load_rom_file(
-        rom_path.as_str()
-      ).expect("Failed to load the ROM file");
+      let rom = std::fs::read(rom_path).expect("Failed to read the ROM file");
+      gb.load_rom(&rom).expect("Failed to load the ROM file");
     }
     #[cfg(feature = "gui")] {
       gui::GuiState::new(gb).init();
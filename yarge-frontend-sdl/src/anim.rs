@@ -15,9 +15,6 @@ impl Default for Animatable {
   }
 }
 impl Animatable {
-  pub fn new() -> Self {
-    Self::default()
-  }
   pub fn new_with_speed(speed: f32) -> Self {
     Self {
       speed,
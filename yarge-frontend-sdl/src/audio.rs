@@ -23,11 +23,16 @@ impl AudioDevice {
     queue.resume();
     Ok(Self { queue })
   }
+
+  ///Sample rate the device was actually opened with
+  pub fn sample_rate(&self) -> usize {
+    self.queue.spec().freq as usize
+  }
 }
 impl AudioDeviceImpl for AudioDevice {
-  fn queue_samples(&mut self, buffer: &[f32; AUDIO_BUFFER_SIZE]) {
+  fn queue_samples(&mut self, buffer: &[f32]) {
     //Out of sync by more then 250ms
-    if (self.queue.size() / size_of::<f32>() as u32) > (AUDIO_SAMPLE_RATE as u32 / 2) {
+    if (self.queue.size() / size_of::<f32>() as u32) > (self.queue.spec().freq as u32 / 2) {
       println!("[AUDIO/WARN] AUDIO OUT OF SYNC! (Too much stuff in the buffer)");
       self.queue.clear();
     }
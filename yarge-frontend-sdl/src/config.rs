@@ -30,6 +30,10 @@ impl Palette {
       Self::Custom(x) => *x
     }
   }
+  ///Palette colors as RGB triples, for use with the core renderer
+  pub fn get_rgb(&self) -> [[u8; 3]; 4] {
+    self.get_map().map(|color| [color as u8, (color >> 8) as u8, (color >> 16) as u8])
+  }
   pub const fn get_name(&self) -> &'static str {
     match self {
       Self::Grayscale     => "Grayscale",
@@ -1,13 +1,17 @@
 #![cfg_attr(target_os = "windows", cfg_attr(feature = "production", windows_subsystem = "windows"))]
 
 use yarge_core::{
-  consts::{WIDTH as GB_WIDTH, HEIGHT as GB_HEIGHT},
+  consts::{WIDTH as GB_WIDTH, HEIGHT as GB_HEIGHT, SGB_WIDTH, SGB_HEIGHT},
   Gameboy,
+  Model,
   Key as GbKey,
-  YargeError
+  YargeError,
+  TcpLink,
+  Renderer,
 };
 use sdl2::{
   pixels::{PixelFormatEnum, Color},
+  rect::Rect,
   event::Event,
   keyboard::Scancode,
   render::BlendMode,
@@ -35,6 +39,9 @@ const FONT_TEXTURE_SIZE: (u32, u32) = (256, 368);
 const FONT_CHAR_SIZE: (u32, u32) = (8, 16);
 const FONT_CHARS_PER_LINE: u32 = FONT_TEXTURE_SIZE.0 / FONT_CHAR_SIZE.0;
 
+const REWIND_INTERVAL: usize = 2; //frames
+const REWIND_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
 const GB_KEYBIND: &[(Scancode, GbKey)] = &[
   (Scancode::Z,       GbKey::A),
   (Scancode::X,       GbKey::B),
@@ -52,6 +59,16 @@ struct Args {
   rom_path: Option<String>,
   #[arg(long)] skip_bootrom: bool,
   #[arg(long)] fast: bool,
+  ///Hardware model to emulate (dmg0, dmg, mgb, sgb, sgb2 or cgb)
+  #[arg(long, default_value = "dmg")] model: Model,
+  ///Use a boot ROM image instead of the built-in one
+  #[arg(long, value_name = "PATH")] boot_rom: Option<String>,
+  ///Wait for a link cable connection on the specified port
+  #[arg(long, value_name = "PORT", conflicts_with = "link_connect")] link_listen: Option<u16>,
+  ///Address to listen on for link cable connections (use 0.0.0.0 to accept connections from other hosts)
+  #[arg(long, value_name = "ADDR", default_value = "127.0.0.1", requires = "link_listen")] link_bind: String,
+  ///Connect the link cable to another emulator
+  #[arg(long, value_name = "ADDR")] link_connect: Option<String>,
 }
 
 struct URStorage {
@@ -70,6 +87,11 @@ impl Default for URStorage {
   }
 }
 
+///Placeholder screen image, converted to RGBA
+pub(crate) fn fat_texture_rgba() -> Vec<u8> {
+  FAT_TEXTURE.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xff]).collect()
+}
+
 /// Load ROM file, with support for ZIP files
 pub(crate) fn load_rom_helper(gb: &mut Gameboy, data: &[u8]) -> Result<(), YargeError> {
   //TODO handle failures more gracefully
@@ -119,7 +141,21 @@ fn main() {
   println!("[INIT/INFO] Initializing emulation");
 
   //Create a Gameboy struct
-  let mut gb = Gameboy::new();
+  let mut builder = Gameboy::builder().model(args.model);
+  if let Some(path) = args.boot_rom.as_ref() {
+    builder = builder.boot_rom(std::fs::read(path).expect("Failed to load the boot ROM file"));
+  }
+  let mut gb = builder.build().expect("Invalid boot ROM file");
+  gb.enable_rewind(REWIND_INTERVAL, REWIND_MEMORY_LIMIT);
+
+  //Connect the link cable
+  if let Some(port) = args.link_listen {
+    println!("[INIT/INFO] Waiting for link cable connection on {}:{port}", args.link_bind);
+    gb.set_serial_link(TcpLink::listen((args.link_bind.as_str(), port)).expect("Link cable connection failed"));
+  } else if let Some(addr) = args.link_connect.as_ref() {
+    println!("[INIT/INFO] Connecting link cable to {addr}");
+    gb.set_serial_link(TcpLink::connect(addr.as_str()).expect("Link cable connection failed"));
+  }
 
   println!("[INIT/INFO] Loading ROM file");
 
@@ -139,6 +175,12 @@ fn main() {
     gb.skip_bootrom();
   }
 
+  //Leave space for the SGB border around the screen
+  let screen_size = match args.model.is_sgb() {
+    true => (SGB_WIDTH as u32, SGB_HEIGHT as u32),
+    false => (GB_WIDTH as u32, GB_HEIGHT as u32),
+  };
+
   println!("[INIT/INFO] Initializing SDL2");
 
   //Initialize SDL2 Context, VideoSubsystem, Window, EventPump and Canvas
@@ -147,8 +189,8 @@ fn main() {
   let window = {
     let mut builder = video_subsystem.window(
       "YargeSDL",
-      GB_WIDTH as u32 * config.scale.scale_or_default(),
-      GB_HEIGHT as u32 * config.scale.scale_or_default()
+      screen_size.0 * config.scale.scale_or_default(),
+      screen_size.1 * config.scale.scale_or_default()
     );
     builder.position_centered();
     builder.allow_highdpi();
@@ -180,11 +222,14 @@ fn main() {
 
   //Create a texture for the screen
   let mut gb_texture = texture_creator.create_texture_streaming(
-    PixelFormatEnum::RGB24,
-    GB_WIDTH as u32,
-    GB_HEIGHT as u32
+    PixelFormatEnum::RGBA32,
+    screen_size.0,
+    screen_size.1
   ).unwrap();
-  gb_texture.update(None, FAT_TEXTURE, 3 * GB_WIDTH).unwrap();
+  if !args.model.is_sgb() {
+    gb_texture.update(None, &fat_texture_rgba(), 4 * GB_WIDTH).unwrap();
+  }
+  let mut renderer = Renderer::new();
 
   //Create the font texture
   let mut font_texture = texture_creator.create_texture_static(
@@ -210,12 +255,14 @@ fn main() {
 
   //Create the audio device and assign it
   let audio_device = AudioDevice::new(&sdl_context).unwrap();
+  gb.set_audio_sample_rate(audio_device.sample_rate()).unwrap();
   gb.set_audio_device(audio_device);
 
   println!("[INIT/INFO] Creating menu");
 
   //Create a Menu object that handles the ESC-menu
   let mut menu = Menu::new(&config);
+  menu.set_screen_size(screen_size);
 
   //Check close status
   if !config.closed_properly {
@@ -265,8 +312,8 @@ fn main() {
             println!("[WARN/DPI] Not applying dpi scaling to window size as it's either fullscreen or maximized");
           } else {
             let s = (
-              GB_WIDTH as u32 * config.scale.scale_or_default(),
-              GB_HEIGHT as u32 * config.scale.scale_or_default()
+              screen_size.0 * config.scale.scale_or_default(),
+              screen_size.1 * config.scale.scale_or_default()
             );
             canvas.window_mut().set_size(
               (display_dpi_scale * s.0 as f32) as u32, 
@@ -319,26 +366,47 @@ fn main() {
         gb.set_key_state(*key, kb_state.is_scancode_pressed(*scancode));
       }
 
-      //Run emulation for one frame
-      let emu_speed = ur_store.speed * if event_pump.keyboard_state().is_scancode_pressed(Scancode::Tab) { 8 } else { 1 };
-      for _ in 0..emu_speed {
-        gb.run_for_frame().unwrap();
+      //Run emulation for one frame (or go back in time while rewinding)
+      if kb_state.is_scancode_pressed(Scancode::Grave) {
+        gb.rewind_step().unwrap();
+      } else {
+        let emu_speed = ur_store.speed * if kb_state.is_scancode_pressed(Scancode::Tab) { 8 } else { 1 };
+        for _ in 0..emu_speed {
+          gb.run_for_frame().unwrap();
+        }
       }
 
       //Copy data to texture
-      let gb_data = gb.get_display_data();
-      let palette = config.palette.get_map();
+      //(the SGB border is only rendered for SGB-enhanced ROMs, so the size can change after loading a ROM)
+      let frame_size = Renderer::screen_size(&gb);
+      let frame_size = (frame_size.0 as u32, frame_size.1 as u32);
+      let texture_info = gb_texture.query();
+      if (texture_info.width, texture_info.height) != frame_size {
+        gb_texture = texture_creator.create_texture_streaming(
+          PixelFormatEnum::RGBA32,
+          frame_size.0,
+          frame_size.1
+        ).unwrap();
+      }
+      renderer.set_palette(config.palette.get_rgb());
       gb_texture.with_lock(None, |tex_data: &mut [u8], _pitch: usize| {
-        for (index, color) in gb_data.iter().enumerate() {
-          let mapped_color = palette[*color as usize];
-          tex_data[3 * index] = mapped_color as u8;
-          tex_data[(3 * index) + 1] = (mapped_color >> 8) as u8;
-          tex_data[(3 * index) + 2] = (mapped_color >> 16) as u8;
-        }
+        renderer.render_rgba8888(&gb, tex_data);
       }).unwrap();
 
-      //Copy texture to the entire canvas
-      canvas.copy(&gb_texture, None, None).unwrap();
+      //Copy texture to the canvas, centered in the border area if there's no border
+      if frame_size == screen_size {
+        canvas.copy(&gb_texture, None, None).unwrap();
+      } else {
+        let (out_w, out_h) = canvas.output_size().unwrap();
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        canvas.copy(&gb_texture, None, Rect::new(
+          ((out_w * (screen_size.0 - frame_size.0)) / (2 * screen_size.0)) as i32,
+          ((out_h * (screen_size.1 - frame_size.1)) / (2 * screen_size.1)) as i32,
+          out_w * frame_size.0 / screen_size.0,
+          out_h * frame_size.1 / screen_size.1,
+        )).unwrap();
+      }
 
       //Allow skipping bootrom
       let overlay_color = if config.palette.is_dark() {Color::WHITE} else {Color::BLACK};
@@ -23,7 +23,7 @@ use crate::{
   text::TextRenderer,
   config::{Configuration, Palette, WindowScale, UiTheme}, 
   saves::SaveManager,
-  FAT_TEXTURE,
+  fat_texture_rgba,
   URStorage,
 };
 
@@ -86,7 +86,6 @@ enum MenuLocation {
     save_slot: u8,
     exists: bool
   },
-  AskForRestart,
   FileExplorer {
     path: PathBuf,
     items: Vec<PathBuf>
@@ -105,7 +104,6 @@ impl MenuLocation {
       Self::SpeedPicker => "Speed",
       Self::SaveSlotPicker => "Save slot",
       Self::SaveSlotConfirm { .. } => "Confirm restart",
-      Self::AskForRestart => "Restart",
       Self::FileExplorer { .. } => "File explorer",
       Self::ClosedImproperly => "Warning",
       Self::UiTheme => "Theme",
@@ -115,8 +113,7 @@ impl MenuLocation {
 
   pub fn back_operation(&self) -> &'static str {
     match self {
-      Self::SaveSlotConfirm { .. } |
-      Self::AskForRestart => "Cancel",
+      Self::SaveSlotConfirm { .. } => "Cancel",
       _ => "Back",
     }
   }
@@ -136,6 +133,7 @@ pub struct Menu {
   last_navigation_was_mouse_scroll: bool,
   frame_instant: Instant,
   dt: Duration,
+  screen_size: (u32, u32),
 }
 impl Menu {
   pub fn new(config: &Configuration) -> Self {
@@ -153,8 +151,13 @@ impl Menu {
       last_navigation_was_mouse_scroll: false,
       frame_instant: Instant::now(),
       dt: Duration::default(),
+      screen_size: (GB_WIDTH as u32, GB_HEIGHT as u32),
     }
   }
+  ///Set the size of the emulated screen (256x224 while emulating SGB)
+  pub fn set_screen_size(&mut self, size: (u32, u32)) {
+    self.screen_size = size;
+  }
   pub fn is_active(&self) -> bool {
     self.active
   }
@@ -186,7 +189,7 @@ impl Menu {
     self.cursor = 1;
   }
   pub fn skip_activation_animation(&mut self) {
-    self.activation_anim_state.value = self.activation_anim_state.target;
+    self.activation_anim_state.skip_animation();
     self.activation_anim_state.step(0.);
   }
   ///Process events
@@ -297,7 +300,9 @@ impl Menu {
     self.set_activated_state(true);
     //self.skip_activation_animation();
     gb.reset();
-    gb_texture.update(None, FAT_TEXTURE, 3 * GB_WIDTH).unwrap();
+    if self.screen_size == (GB_WIDTH as u32, GB_HEIGHT as u32) {
+      gb_texture.update(None, &fat_texture_rgba(), 4 * GB_WIDTH).unwrap();
+    }
     self.has_game = false;
     self.cursor = 0;
   }
@@ -400,6 +405,7 @@ impl Menu {
       //"Paused" text
       let mut text_color = self.theme.colors().text_faded;
       text_color.a = opa;
+      text.set_color(text_color);
       text.render(
         canvas,
         (
@@ -635,7 +641,7 @@ impl Menu {
               WindowScale::Scale(scale) => {
                 canvas.window_mut().restore();
                 canvas.window_mut().set_fullscreen(FullscreenType::Off).unwrap();
-                canvas.window_mut().set_size(mu(scale * GB_WIDTH as u32, dpi_scale), mu(scale * GB_HEIGHT as u32, dpi_scale)).unwrap();
+                canvas.window_mut().set_size(mu(scale * self.screen_size.0, dpi_scale), mu(scale * self.screen_size.1, dpi_scale)).unwrap();
               },
               WindowScale::Maximized => {
                 canvas.window_mut().set_fullscreen(FullscreenType::Off).unwrap();
@@ -705,9 +711,6 @@ impl Menu {
             // self.set_activated_state(false);
           });
         }
-        MenuLocation::AskForRestart => {
-          define_menu_item!("Restart now to apply changes", { *do_exit = true });
-        }
         MenuLocation::FileExplorer { items, path } => {
           define_menu_item!("Home", {
             self.file_explorer_goto_home();
@@ -5,7 +5,8 @@ const SUPPORTED_EVT_TYPES = new Set([
   'SPR_FETCH_END',
   'PX_FETCH_LINE_END',
   'LX_INC',
-  'SPR_FETCHER_STATE_CHANGE'
+  'SPR_FETCHER_STATE_CHANGE',
+  'BG_FETCHER_STATE_CHANGE'
 ]);
 
 const appMain = document.getElementById('app-main');
@@ -22,14 +23,9 @@ function loadData(str) {
   return str
     .replace(/\r/g,'')
     .split('\n')
-    .filter(line => line.startsWith('PPU_EVENT'))
-    .map(line => line.split(' ').slice(1))
-    .map(line => ({
-      type: line[0],
-      args: Object.fromEntries(
-        line.slice(1).map(x => x.split('=').map(x => x.trim()))
-      )
-    }));
+    .filter(line => line.startsWith('{'))
+    .map(line => JSON.parse(line))
+    .map(({ type, cycle, ...args }) => ({ type, cycle, args }));
 }
 
 function drawPoints(data, state) {
@@ -70,6 +66,10 @@ function drawPoints(data, state) {
         ctx.fillStyle = `rgb(${64 + point.args.next * 32},${64 + point.args.next * 16},128,1)`;
         ctx.fillRect(point.args.cycles * scale, point.args.ly * scale, 2, 2);
         break;
+      case 'BG_FETCHER_STATE_CHANGE':
+        ctx.fillStyle = `rgb(128,${64 + point.args.next * 32},${64 + point.args.next * 16},1)`;
+        ctx.fillRect(point.args.cycles * scale, point.args.ly * scale + scale / 2, 2, 2);
+        break;
     }
   }
   ctx.fillStyle = 'rgb(100,100,255)';
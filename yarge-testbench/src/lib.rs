@@ -64,8 +64,65 @@ macro_rules! define_test_mooneye {
   };
 }
 
+///Setup closure that creates a Gameboy emulating the specified model
+#[cfg(test)]
+macro_rules! setup_model {
+  ($model: ident) => {
+    (|gb: &mut Gameboy, rom: &[u8]| {
+      *gb = Gameboy::builder().model(::yarge_core::Model::$model).build().unwrap();
+      gb.init();
+      gb.load_rom(rom).unwrap();
+      gb.skip_bootrom();
+    })
+  };
+}
+
+///Builds a ROM that loops forever at 0x100 (`JR -2`) with `patch` (offset, bytes) applied on top
+///and boots it on `model`, skipping the boot ROM.
+///The ROM is padded to the size specified in the header
+#[cfg(test)]
+fn test_rom(patch: &[(usize, &[u8])], model: yarge_core::Model) -> yarge_core::Gameboy {
+  let size = patch.iter()
+    .map(|(offset, bytes)| offset + bytes.len())
+    .fold(0x8000, usize::max)
+    .next_power_of_two();
+  let mut rom = vec![0; size];
+  rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // JR -2
+  for (offset, bytes) in patch {
+    rom[*offset..(offset + bytes.len())].copy_from_slice(bytes);
+  }
+  //Match the ROM size in the header
+  let header_size = 0x8000 << rom[0x148];
+  if rom.len() < header_size {
+    rom.resize(header_size, 0);
+  }
+  let mut gb = yarge_core::Gameboy::builder().model(model).build().unwrap();
+  gb.init();
+  gb.load_rom(&rom).unwrap();
+  gb.skip_bootrom();
+  gb
+}
+
+///Steps up to `steps` instructions, returns the first error (usually a breakpoint)
+#[cfg(test)]
+fn step_until_error(gb: &mut yarge_core::Gameboy, steps: usize) -> Option<yarge_core::YargeError> {
+  (0..steps).find_map(|_| gb.step().err())
+}
+
 mod mooneye {
   mod acceptance {
+    mod boot {
+      define_test_mooneye!(boot_regs_dmg0, "mooneye/acceptance/boot_regs-dmg0.gb", (setup_model!(Dmg0)));
+      define_test_mooneye!(boot_regs_dmgABC, "mooneye/acceptance/boot_regs-dmgABC.gb", (setup_model!(Dmg)));
+      define_test_mooneye!(boot_regs_mgb, "mooneye/acceptance/boot_regs-mgb.gb", (setup_model!(Mgb)));
+      define_test_mooneye!(boot_regs_sgb, "mooneye/acceptance/boot_regs-sgb.gb", (setup_model!(Sgb)));
+      define_test_mooneye!(boot_regs_sgb2, "mooneye/acceptance/boot_regs-sgb2.gb", (setup_model!(Sgb2)));
+      define_test_mooneye!(boot_hwio_dmgABCmgb, "mooneye/acceptance/boot_hwio-dmgABCmgb.gb", (setup_model!(Dmg)));
+      define_test_mooneye!(boot_hwio_S, "mooneye/acceptance/boot_hwio-S.gb", (setup_model!(Sgb)));
+      define_test_mooneye!(boot_div_dmg0, "mooneye/acceptance/boot_div-dmg0.gb", (setup_model!(Dmg0)));
+      define_test_mooneye!(boot_div_dmgABCmgb, "mooneye/acceptance/boot_div-dmgABCmgb.gb", (setup_model!(Dmg)));
+      define_test_mooneye!(boot_div_S, "mooneye/acceptance/boot_div-S.gb", (setup_model!(Sgb)));
+    }
     mod oam_dma {
       define_test_mooneye!(basic, "mooneye/acceptance/oam_dma/basic.gb");
       define_test_mooneye!(reg_read, "mooneye/acceptance/oam_dma/reg_read.gb");
@@ -101,6 +158,40 @@ mod mooneye {
       define_test_mooneye!(ie_push, "mooneye/acceptance/interrupts/ie_push.gb"); 
     }
   }
+  mod emulator_only {
+    mod mbc1 {
+      define_test_mooneye!(bits_bank1, "mooneye/emulator-only/mbc1/bits_bank1.gb");
+      define_test_mooneye!(bits_bank2, "mooneye/emulator-only/mbc1/bits_bank2.gb");
+      define_test_mooneye!(bits_mode, "mooneye/emulator-only/mbc1/bits_mode.gb");
+      define_test_mooneye!(bits_ramg, "mooneye/emulator-only/mbc1/bits_ramg.gb");
+      define_test_mooneye!(multicart_rom_8Mb, "mooneye/emulator-only/mbc1/multicart_rom_8Mb.gb");
+      define_test_mooneye!(ram_64kb, "mooneye/emulator-only/mbc1/ram_64kb.gb");
+      define_test_mooneye!(ram_256kb, "mooneye/emulator-only/mbc1/ram_256kb.gb");
+      define_test_mooneye!(rom_512kb, "mooneye/emulator-only/mbc1/rom_512kb.gb");
+      define_test_mooneye!(rom_1Mb, "mooneye/emulator-only/mbc1/rom_1Mb.gb");
+      define_test_mooneye!(rom_2Mb, "mooneye/emulator-only/mbc1/rom_2Mb.gb");
+      define_test_mooneye!(rom_4Mb, "mooneye/emulator-only/mbc1/rom_4Mb.gb");
+      define_test_mooneye!(rom_8Mb, "mooneye/emulator-only/mbc1/rom_8Mb.gb");
+      define_test_mooneye!(rom_16Mb, "mooneye/emulator-only/mbc1/rom_16Mb.gb");
+    }
+    mod mbc2 {
+      define_test_mooneye!(bits_ramg, "mooneye/emulator-only/mbc2/bits_ramg.gb");
+      define_test_mooneye!(bits_romb, "mooneye/emulator-only/mbc2/bits_romb.gb");
+      define_test_mooneye!(bits_unused, "mooneye/emulator-only/mbc2/bits_unused.gb");
+      define_test_mooneye!(ram, "mooneye/emulator-only/mbc2/ram.gb");
+      define_test_mooneye!(rom_512kb, "mooneye/emulator-only/mbc2/rom_512kb.gb");
+      define_test_mooneye!(rom_1Mb, "mooneye/emulator-only/mbc2/rom_1Mb.gb");
+      define_test_mooneye!(rom_2Mb, "mooneye/emulator-only/mbc2/rom_2Mb.gb");
+    }
+    mod mbc5 {
+      define_test_mooneye!(rom_512kb, "mooneye/emulator-only/mbc5/rom_512kb.gb");
+      define_test_mooneye!(rom_1Mb, "mooneye/emulator-only/mbc5/rom_1Mb.gb");
+      define_test_mooneye!(rom_2Mb, "mooneye/emulator-only/mbc5/rom_2Mb.gb");
+      define_test_mooneye!(rom_4Mb, "mooneye/emulator-only/mbc5/rom_4Mb.gb");
+      define_test_mooneye!(rom_8Mb, "mooneye/emulator-only/mbc5/rom_8Mb.gb");
+      define_test_mooneye!(rom_16Mb, "mooneye/emulator-only/mbc5/rom_16Mb.gb");
+    }
+  }
 }
 
 mod acid {
@@ -108,4 +199,1445 @@ mod acid {
     let hash = fxhash::hash64(gb.get_display_data());
     assert_eq!(hash, 6523616297985761018);
   }));
+  define_test!(cgb_acid2, "acid/cgb-acid2.gbc", (|gb: &mut Gameboy| {
+    let hash = fxhash::hash64(gb.get_display_data_cgb());
+    assert_eq!(hash, 1236939589518168832);
+  }), (setup_model!(Cgb)));
+}
+
+#[cfg(test)]
+mod mbc5 {
+  use yarge_core::Model;
+  use super::test_rom;
+
+  #[test]
+  fn save_data() {
+    //MBC5+RAM+BATTERY with 4 RAM banks, short save file
+    let mut gb = test_rom(&[(0x147, &[0x1B, 0x00, 0x03])], Model::Dmg);
+    assert!(gb.has_save_data());
+    gb.set_save_data(vec![0x42; 100]);
+    let data = gb.get_save_data().unwrap();
+    assert_eq!(data.len(), 0x8000);
+    assert_eq!((data[99], data[100]), (0x42, 0x00));
+    //Highest RAM bank is accessible
+    gb.write_mem(0x0000, 0x0A);
+    gb.write_mem(0x4000, 0x03);
+    assert_eq!(gb.read_mem(0xBFFF), 0x00);
+
+    //MBC5 without RAM
+    assert!(!test_rom(&[(0x147, &[0x19])], Model::Dmg).has_save_data());
+  }
+}
+
+#[cfg(test)]
+mod save_state {
+  use yarge_core::{Gameboy, YargeError, Model};
+  use super::test_rom;
+  const ROM: &[u8] = include_bytes!("./../../roms/tests/acid/dmg-acid2.gb");
+
+  fn run_until_breakpoint(gb: &mut Gameboy) {
+    loop {
+      match gb.step() {
+        Ok(_) => (),
+        Err(YargeError::LdBreakpoint { .. }) => break,
+        Err(error) => panic!("{error}"),
+      }
+    }
+  }
+
+  #[test]
+  fn round_trip() {
+    let mut gb = Gameboy::new();
+    gb.init();
+    gb.load_rom(ROM).unwrap();
+    gb.skip_bootrom();
+    for _ in 0..5 {
+      gb.run_for_frame().unwrap();
+    }
+    let state = gb.save_state();
+    run_until_breakpoint(&mut gb);
+    let hash = fxhash::hash64(gb.get_display_data());
+
+    let mut gb = Gameboy::new();
+    gb.init();
+    gb.load_rom(ROM).unwrap();
+    gb.load_state(&state).unwrap();
+    assert_eq!(gb.save_state(), state);
+    run_until_breakpoint(&mut gb);
+    assert_eq!(fxhash::hash64(gb.get_display_data()), hash);
+  }
+
+  #[test]
+  fn invalid_data() {
+    let mut gb = Gameboy::new();
+    gb.init();
+    gb.load_rom(ROM).unwrap();
+    gb.skip_bootrom();
+    let state = gb.save_state();
+    gb.run_for_frame().unwrap();
+    let pc = gb.get_reg_pc();
+    assert!(matches!(
+      gb.load_state(&state[..state.len() - 1]),
+      Err(YargeError::InvalidSaveState(_))
+    ));
+    assert_eq!(gb.get_reg_pc(), pc);
+    let mut other_rom = ROM.to_vec();
+    other_rom[0x14E] ^= 0xFF;
+    let mut other_gb = Gameboy::new();
+    other_gb.load_rom(&other_rom).unwrap();
+    assert!(matches!(
+      other_gb.load_state(&state),
+      Err(YargeError::InvalidSaveState(_))
+    ));
+  }
+
+  ///Saves the state with two different register values and replaces the byte that changed with `value`
+  fn mutate_state(gb: &mut Gameboy, addr: u16, values: (u8, u8), value: u8) -> Vec<u8> {
+    gb.write_mem(addr, values.0);
+    let mut state = gb.save_state();
+    gb.write_mem(addr, values.1);
+    let other = gb.save_state();
+    let changed: Vec<usize> = (0..state.len()).filter(|&i| state[i] != other[i]).collect();
+    assert_eq!(changed.len(), 1);
+    state[changed[0]] = value;
+    state
+  }
+
+  #[test]
+  fn invalid_values() {
+    //CGB WRAM bank
+    let mut gb = test_rom(&[(0x143, &[0x80])], Model::Cgb);
+    let state = mutate_state(&mut gb, 0xFF70, (2, 3), 0xFF);
+    assert!(matches!(gb.load_state(&state), Err(YargeError::InvalidSaveState(_))));
+    assert_eq!(gb.read_mem(0xFF70), 0xFB);
+    gb.run_for_frame().unwrap();
+
+    //Wave channel output level
+    let mut gb = test_rom(&[], Model::Dmg);
+    gb.write_mem(0xFF26, 0x80);
+    let state = mutate_state(&mut gb, 0xFF1C, (0x20, 0x40), 0xFF);
+    assert!(matches!(gb.load_state(&state), Err(YargeError::InvalidSaveState(_))));
+    gb.run_for_frame().unwrap();
+
+    //Truncated anywhere
+    let state = gb.save_state();
+    for len in (0..state.len()).step_by(499) {
+      assert!(matches!(gb.load_state(&state[..len]), Err(YargeError::InvalidSaveState(_))));
+    }
+    gb.load_state(&state).unwrap();
+  }
+}
+
+#[cfg(test)]
+mod model {
+  use yarge_core::{Gameboy, Model};
+  use super::test_rom;
+
+  #[test]
+  fn boot_rom() {
+    let mut gb = Gameboy::new();
+    gb.load_rom(&[0; 0x8000]).unwrap();
+    gb.step().unwrap();
+    assert!(!gb.get_bios_disabled() && gb.get_reg_pc() < 0x100);
+    //There's no built-in boot ROM for these, they start right after it
+    for model in [Model::Sgb, Model::Sgb2, Model::Cgb] {
+      let mut gb = Gameboy::builder().model(model).build().unwrap();
+      gb.load_rom(&[0; 0x8000]).unwrap();
+      assert!(gb.get_bios_disabled());
+      assert_eq!(gb.get_reg_pc(), 0x100);
+      gb.skip_bootrom();
+      gb.step().unwrap();
+      assert_eq!(gb.get_reg_pc(), 0x101);
+    }
+  }
+
+  #[test]
+  fn save_state_index() {
+    //Magic, version, checksum and MBC type come before the model
+    assert_eq!(test_rom(&[], Model::Dmg).save_state()[9], 0);
+    assert_eq!(test_rom(&[], Model::Cgb).save_state()[9], 1);
+  }
+}
+
+#[cfg(test)]
+mod rewind {
+  use yarge_core::Gameboy;
+  const ROM: &[u8] = include_bytes!("./../../roms/tests/acid/dmg-acid2.gb");
+
+  #[test]
+  fn rewind_step() {
+    let mut gb = Gameboy::new();
+    gb.init();
+    gb.load_rom(ROM).unwrap();
+    gb.skip_bootrom();
+    gb.enable_rewind(1, usize::MAX);
+    let mut states = Vec::new();
+    for _ in 0..10 {
+      states.push(gb.save_state());
+      gb.run_for_frame().unwrap();
+    }
+    assert_eq!(gb.get_rewind_length(), 10);
+    //Every step has to go back one frame, including the first one
+    while let Some(state) = states.pop() {
+      assert!(gb.save_state() != state);
+      assert!(gb.rewind_step().unwrap());
+      assert!(gb.save_state() == state);
+    }
+    assert!(!gb.rewind_step().unwrap());
+  }
+
+  #[test]
+  fn memory_limit() {
+    let mut gb = Gameboy::new();
+    gb.init();
+    gb.load_rom(ROM).unwrap();
+    gb.skip_bootrom();
+    let limit = gb.save_state().len() + 1024;
+    gb.enable_rewind(1, limit);
+    for _ in 0..10 {
+      gb.run_for_frame().unwrap();
+      assert!(gb.get_rewind_memory_usage() <= limit);
+    }
+    assert!(gb.get_rewind_length() < 10);
+  }
+}
+
+#[cfg(test)]
+mod rtc {
+  use yarge_core::{Gameboy, Model, consts::CPU_CLOCK_SPEED};
+  use super::test_rom;
+
+  ///MBC3+TIMER+RAM+BATTERY, 8 KiB of RAM
+  const HEADER: &[(usize, &[u8])] = &[(0x147, &[0x10, 0x00, 0x02])];
+
+  fn write_reg(gb: &mut Gameboy, reg: u8, value: u8) {
+    gb.write_mem(0x4000, reg);
+    gb.write_mem(0xA000, value);
+  }
+
+  fn latch(gb: &mut Gameboy) {
+    gb.write_mem(0x6000, 0x00);
+    gb.write_mem(0x6000, 0x01);
+  }
+
+  ///Latches and reads seconds, minutes, hours, day low and day high/flags
+  fn read_regs(gb: &mut Gameboy) -> [u8; 5] {
+    latch(gb);
+    [0x08, 0x09, 0x0A, 0x0B, 0x0C].map(|reg| {
+      gb.write_mem(0x4000, reg);
+      gb.read_mem(0xA000)
+    })
+  }
+
+  #[test]
+  fn latch_registers() {
+    let mut gb = test_rom(HEADER, Model::Dmg);
+    gb.write_mem(0x0000, 0x0A);
+    write_reg(&mut gb, 0x08, 5);
+    latch(&mut gb);
+    gb.run_for_cycles(CPU_CLOCK_SPEED).unwrap();
+    //Reads return the latched value until the next latch
+    gb.write_mem(0x4000, 0x08);
+    assert_eq!(gb.read_mem(0xA000), 5);
+    assert_eq!(read_regs(&mut gb)[0], 6);
+  }
+
+  #[test]
+  fn halt() {
+    let mut gb = test_rom(HEADER, Model::Dmg);
+    gb.write_mem(0x0000, 0x0A);
+    write_reg(&mut gb, 0x08, 5);
+    write_reg(&mut gb, 0x0C, 0x40);
+    gb.run_for_cycles(CPU_CLOCK_SPEED).unwrap();
+    assert_eq!(read_regs(&mut gb), [5, 0, 0, 0, 0x40]);
+    write_reg(&mut gb, 0x0C, 0x00);
+    gb.run_for_cycles(CPU_CLOCK_SPEED).unwrap();
+    assert_eq!(read_regs(&mut gb)[0], 6);
+  }
+
+  #[test]
+  fn day_carry() {
+    let mut gb = test_rom(HEADER, Model::Dmg);
+    gb.write_mem(0x0000, 0x0A);
+    for (reg, value) in [(0x09, 59), (0x0A, 23), (0x0B, 0xFF), (0x0C, 0x01), (0x08, 59)] {
+      write_reg(&mut gb, reg, value);
+    }
+    gb.run_for_cycles(CPU_CLOCK_SPEED).unwrap();
+    //Day counter overflows from 511 to 0 and sets the carry flag
+    assert_eq!(read_regs(&mut gb), [0, 0, 0, 0, 0x80]);
+  }
+
+  #[test]
+  fn footer() {
+    let mut gb = test_rom(HEADER, Model::Dmg);
+    gb.write_mem(0x0000, 0x0A);
+    for (reg, value) in [(0x08, 12), (0x09, 34), (0x0A, 5), (0x0B, 0x2A), (0x0C, 0x41)] {
+      write_reg(&mut gb, reg, value);
+    }
+    let regs = read_regs(&mut gb);
+    let data = gb.get_save_data().unwrap();
+    assert_eq!(data.len(), 0x2000 + 48);
+
+    let mut gb = test_rom(HEADER, Model::Dmg);
+    gb.write_mem(0x0000, 0x0A);
+    gb.set_save_data(data.clone());
+    //Latched registers are restored as well
+    gb.write_mem(0x4000, 0x09);
+    assert_eq!(gb.read_mem(0xA000), 34);
+    assert_eq!(read_regs(&mut gb), regs);
+
+    //44 byte variant with a 32-bit timestamp (low half of the 64-bit one)
+    let short = data[..(data.len() - 4)].to_vec();
+    let mut gb = test_rom(HEADER, Model::Dmg);
+    gb.write_mem(0x0000, 0x0A);
+    gb.set_save_data(short);
+    assert_eq!(read_regs(&mut gb), regs);
+  }
+
+  #[test]
+  fn stale_timestamp() {
+    let mut gb = test_rom(HEADER, Model::Dmg);
+    gb.write_mem(0x0000, 0x0A);
+    //Out-of-range values that take the slow path
+    write_reg(&mut gb, 0x08, 61);
+    write_reg(&mut gb, 0x0A, 25);
+    let mut data = gb.get_save_data().unwrap();
+    let len = data.len();
+    data[(len - 8)..].fill(0);
+    let mut gb = test_rom(HEADER, Model::Dmg);
+    gb.write_mem(0x0000, 0x0A);
+    gb.set_save_data(data);
+    let [seconds, minutes, hours, _, flags] = read_regs(&mut gb);
+    assert!(seconds < 60 && minutes < 60 && hours < 24);
+    assert_eq!(flags & 0x80, 0x80);
+  }
+}
+
+#[cfg(test)]
+mod link {
+  use std::{net::TcpListener, sync::mpsc, thread, time::{Duration, Instant}};
+  use yarge_core::{Gameboy, Model, YargeError, LocalLink, TcpLink, consts::CPU_CLOCK_SPEED};
+  use super::test_rom;
+
+  ///Program that sends `value` with the specified SC value, waits for the transfer to finish
+  ///and loads the received byte into A before hitting a LD B,B breakpoint
+  fn transfer(value: u8, sc: u8) -> [u8; 19] {
+    [
+      0x3E, value, // LD A, value
+      0xE0, 0x01,  // LDH (SB), A
+      0x3E, sc,    // LD A, sc
+      0xE0, 0x02,  // LDH (SC), A
+      0xF0, 0x02,  // LDH A, (SC)
+      0xCB, 0x7F,  // BIT 7, A
+      0x20, 0xFA,  // JR NZ, -6
+      0xF0, 0x01,  // LDH A, (SB)
+      0x40,        // LD B, B
+      0x18, 0xFE,  // JR -2
+    ]
+  }
+
+  ///Returns true if the breakpoint was hit
+  fn step(gb: &mut Gameboy, done: bool) -> bool {
+    if done { return true }
+    match gb.step() {
+      Ok(_) => false,
+      Err(YargeError::LdBreakpoint { .. }) => true,
+      Err(error) => panic!("{error}"),
+    }
+  }
+
+  #[test]
+  fn exchange_byte() {
+    let mut master = test_rom(&[(0x100, &transfer(0x42, 0x81))], Model::Dmg);
+    let mut slave = test_rom(&[(0x100, &transfer(0x99, 0x80))], Model::Dmg);
+    let (link_a, link_b) = LocalLink::new_pair();
+    master.set_serial_link(link_a);
+    slave.set_serial_link(link_b);
+    let (mut master_done, mut slave_done) = (false, false);
+    for _ in 0..100_000 {
+      master_done = step(&mut master, master_done);
+      slave_done = step(&mut slave, slave_done);
+      if master_done && slave_done { break }
+    }
+    assert!(master_done && slave_done, "transfer timed out");
+    assert_eq!(master.get_reg_a(), 0x99);
+    assert_eq!(slave.get_reg_a(), 0x42);
+  }
+
+  #[test]
+  fn exchange_byte_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accept = thread::spawn(move || TcpLink::from_stream(listener.accept().unwrap().0).unwrap());
+    let link_a = TcpLink::connect(addr).unwrap();
+    let link_b = accept.join().unwrap();
+    //Both sides have to run at the same time, as the master waits for the reply
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let slave = thread::spawn(move || {
+      let mut slave = test_rom(&[(0x100, &transfer(0x99, 0x80))], Model::Dmg);
+      slave.set_serial_link(link_b);
+      //Start the transfer (SB and SC writes) before letting the master run
+      for _ in 0..4 {
+        slave.step().unwrap();
+      }
+      ready_tx.send(()).unwrap();
+      let mut done = false;
+      for _ in 0..1_000_000 {
+        done = step(&mut slave, done);
+        if done { break }
+      }
+      assert!(done, "transfer timed out");
+      slave.get_reg_a()
+    });
+    let mut master = test_rom(&[(0x100, &transfer(0x42, 0x81))], Model::Dmg);
+    master.set_serial_link(link_a);
+    ready_rx.recv().unwrap();
+    let mut done = false;
+    for _ in 0..100_000 {
+      done = step(&mut master, done);
+      if done { break }
+    }
+    assert!(done, "transfer timed out");
+    assert_eq!(master.get_reg_a(), 0x99);
+    assert_eq!(slave.join().unwrap(), 0x42);
+  }
+
+  #[test]
+  fn tcp_no_reply() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accept = thread::spawn(move || TcpLink::from_stream(listener.accept().unwrap().0).unwrap());
+    let link = TcpLink::connect(addr).unwrap();
+    //The other side never runs, the transfer has to time out instead of blocking
+    let _peer = accept.join().unwrap();
+    let mut gb = test_rom(&[(0x100, &transfer(0x42, 0x81))], Model::Dmg);
+    gb.set_serial_link(link);
+    let mut cycles = 0;
+    loop {
+      match gb.step() {
+        Ok(elapsed) => cycles += elapsed,
+        Err(YargeError::LdBreakpoint { .. }) => break,
+        Err(error) => panic!("{error}"),
+      }
+      assert!(cycles < 2 * CPU_CLOCK_SPEED, "transfer timed out");
+    }
+    assert!(cycles >= CPU_CLOCK_SPEED);
+    assert_eq!(gb.get_reg_a(), 0xFF);
+  }
+
+  #[test]
+  fn tcp_peer_dropped() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accept = thread::spawn(move || TcpLink::from_stream(listener.accept().unwrap().0).unwrap());
+    let mut link = TcpLink::connect(addr).unwrap();
+    let peer = accept.join().unwrap();
+    assert!(link.is_connected());
+    drop(peer);
+    let start = Instant::now();
+    while link.is_connected() {
+      assert!(start.elapsed() < Duration::from_secs(5), "disconnect not detected");
+      thread::sleep(Duration::from_millis(1));
+    }
+    //Transfers finish right away instead of waiting for the reply timeout
+    let mut gb = test_rom(&[(0x100, &transfer(0x42, 0x81))], Model::Dmg);
+    gb.set_serial_link(link);
+    let mut cycles = 0;
+    loop {
+      match gb.step() {
+        Ok(elapsed) => cycles += elapsed,
+        Err(YargeError::LdBreakpoint { .. }) => break,
+        Err(error) => panic!("{error}"),
+      }
+      assert!(cycles < CPU_CLOCK_SPEED / 2, "transfer waited for the timeout");
+    }
+    assert_eq!(gb.get_reg_a(), 0xFF);
+  }
+
+  #[test]
+  fn disconnected() {
+    let mut gb = test_rom(&[(0x100, &transfer(0x42, 0x81))], Model::Dmg);
+    let mut done = false;
+    for _ in 0..100_000 {
+      done = step(&mut gb, done);
+      if done { break }
+    }
+    assert!(done, "transfer timed out");
+    assert_eq!(gb.get_reg_a(), 0xFF);
+  }
+}
+
+#[cfg(test)]
+mod hdma {
+  use yarge_core::{Gameboy, Model, io_registers::PpuMode};
+  use super::test_rom;
+
+  ///CGB-only ROM
+  const HEADER: &[(usize, &[u8])] = &[(0x143, &[0xC0])];
+
+  fn setup_source(gb: &mut Gameboy) {
+    for i in 0..0x100 {
+      gb.write_mem(0xC000 + i, i as u8);
+    }
+    //Source 0xC000, destination 0x8000
+    gb.write_mem(0xFF51, 0xC0);
+    gb.write_mem(0xFF52, 0x00);
+    gb.write_mem(0xFF53, 0x00);
+    gb.write_mem(0xFF54, 0x00);
+  }
+
+  #[test]
+  fn general_purpose() {
+    let mut gb = test_rom(HEADER, Model::Cgb);
+    setup_source(&mut gb);
+    gb.write_mem(0xFF55, 0x01); //2 blocks
+    gb.step().unwrap();
+    assert_eq!(gb.read_mem(0xFF55), 0xFF);
+    for i in 0..0x20 {
+      assert_eq!(gb.read_mem(0x8000 + i), i as u8);
+    }
+    assert_eq!(gb.read_mem(0x8020), 0);
+  }
+
+  #[test]
+  fn hblank() {
+    let mut gb = test_rom(HEADER, Model::Cgb);
+    setup_source(&mut gb);
+    gb.write_mem(0xFF40, 0x80);
+    gb.write_mem(0xFF55, 0x82); //3 blocks
+    assert_eq!(gb.read_mem(0xFF55), 0x02);
+    for _ in 0..1000 {
+      if gb.read_mem(0xFF55) != 0x02 { break }
+      gb.step().unwrap();
+    }
+    assert_eq!(gb.read_mem(0xFF55), 0x01);
+    //Cancel the transfer
+    gb.write_mem(0xFF55, 0x00);
+    assert_eq!(gb.read_mem(0xFF55), 0x81);
+    gb.run_for_frame().unwrap();
+    assert_eq!(gb.read_mem(0xFF55), 0x81);
+    gb.write_mem(0xFF40, 0x00);
+    for i in 0..0x10 {
+      assert_eq!(gb.read_mem(0x8000 + i), i as u8);
+    }
+    assert_eq!(gb.read_mem(0x8010), 0);
+  }
+
+  #[test]
+  fn hblank_immediate() {
+    let mut gb = test_rom(HEADER, Model::Cgb);
+    setup_source(&mut gb);
+    gb.write_mem(0xFF40, 0x80);
+    while gb.get_io_registers().ppu.mode != PpuMode::HBlank {
+      gb.step().unwrap();
+    }
+    //Started during HBlank, the first block is copied right away instead of on the next HBlank
+    gb.write_mem(0xFF55, 0x81);
+    gb.run_for_cycles(64).unwrap();
+    assert_eq!(gb.read_mem(0xFF55), 0x00);
+  }
+}
+
+#[cfg(test)]
+mod sgb {
+  use yarge_core::{Gameboy, Model, Renderer, consts::SGB_WIDTH};
+  use super::test_rom;
+
+  const BG_PIXEL: usize = (40 * SGB_WIDTH) + 48;
+
+  ///SGB-enhanced ROM
+  const HEADER: &[(usize, &[u8])] = &[(0x146, &[0x03]), (0x14B, &[0x33])];
+
+  fn send_packet(gb: &mut Gameboy, data: &[u8]) {
+    let mut packet = [0; 16];
+    packet[..data.len()].copy_from_slice(data);
+    gb.write_mem(0xFF00, 0x00);
+    gb.write_mem(0xFF00, 0x30);
+    for bit in 0..128 {
+      let value = (packet[bit >> 3] >> (bit & 7)) & 1;
+      gb.write_mem(0xFF00, if value != 0 { 0x10 } else { 0x20 });
+      gb.write_mem(0xFF00, 0x30);
+    }
+    gb.write_mem(0xFF00, 0x20);
+    gb.write_mem(0xFF00, 0x30);
+    gb.step().unwrap();
+  }
+
+  #[test]
+  fn pal01() {
+    let mut gb = test_rom(HEADER, Model::Sgb);
+    send_packet(&mut gb, &[
+      0x01,
+      0x1F, 0x00, //color 0
+      0xE0, 0x03, 0x00, 0x7C, 0xFF, 0x7F, //palette 0
+      0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //palette 1
+    ]);
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    assert_eq!(gb.get_display_data_sgb()[0], 0x001F);
+    assert_eq!(gb.get_display_data_sgb()[BG_PIXEL], 0x001F);
+    //Map color 0 to shade 2
+    gb.write_mem(0xFF47, 0x02);
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    assert_eq!(gb.get_display_data_sgb()[BG_PIXEL], 0x7C00);
+  }
+
+  #[test]
+  fn attr_blk() {
+    let mut gb = test_rom(HEADER, Model::Sgb);
+    gb.write_mem(0xFF47, 0x01);
+    send_packet(&mut gb, &[
+      0x09,
+      0x00, 0x00,
+      0x11, 0x11, 0x00, 0x00, 0x00, 0x00, //palette 2
+      0x22, 0x22, 0x00, 0x00, 0x00, 0x00, //palette 3
+    ]);
+    //Set the palette of the top left tile to 2, and the rest of the screen to 3
+    send_packet(&mut gb, &[
+      0x21, 0x01,
+      0x07, 0b11_10_10, 0, 0, 0, 0,
+    ]);
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    assert_eq!(gb.get_display_data_sgb()[BG_PIXEL], 0x1111);
+    assert_eq!(gb.get_display_data_sgb()[BG_PIXEL + 8], 0x2222);
+    assert_eq!(gb.get_display_data_sgb()[BG_PIXEL + (8 * SGB_WIDTH)], 0x2222);
+  }
+
+  #[test]
+  fn non_sgb_rom() {
+    //No SGB flag in the header
+    let mut gb = test_rom(&[], Model::Sgb);
+    assert!(!gb.is_sgb_mode());
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    assert!(gb.get_display_data_sgb().iter().all(|&x| x == 0));
+    assert_eq!(Renderer::screen_size(&gb), (160, 144));
+    assert_eq!(Renderer::screen_size(&test_rom(HEADER, Model::Sgb)), (SGB_WIDTH, 224));
+  }
+
+  #[test]
+  fn mlt_req() {
+    let mut gb = test_rom(HEADER, Model::Sgb);
+    gb.write_mem(0xFF00, 0x30);
+    assert_eq!(gb.read_mem(0xFF00), 0xFF);
+    send_packet(&mut gb, &[0x89, 0x01]);
+    for id in [0xFF, 0xFE, 0xFF, 0xFE] {
+      assert_eq!(gb.read_mem(0xFF00), id);
+      gb.write_mem(0xFF00, 0x10);
+      gb.write_mem(0xFF00, 0x30);
+    }
+  }
+}
+
+#[cfg(test)]
+mod renderer {
+  use yarge_core::{Model, Renderer};
+  use super::test_rom;
+
+  #[test]
+  fn dmg_palette() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    //Blank screen
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    let mut renderer = Renderer::new();
+    let mut buffer = vec![0; 160 * 144 * 4];
+    renderer.render_rgba8888(&gb, &mut buffer);
+    assert_eq!(buffer[0..4], [0xFF, 0xFF, 0xFF, 0xFF]);
+    renderer.set_palette([[0x12, 0x34, 0x56], [0; 3], [0; 3], [0; 3]]);
+    renderer.render_rgba8888(&gb, &mut buffer);
+    assert_eq!(buffer[0..4], [0x12, 0x34, 0x56, 0xFF]);
+    let mut buffer = vec![0; 160 * 144];
+    renderer.render_rgb565(&gb, &mut buffer);
+    assert_eq!(buffer[0], (0x12 >> 3 << 11) | (0x34 >> 2 << 5) | (0x56 >> 3));
+  }
+
+  #[test]
+  fn gamma() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    //Blank screen
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    gb.write_mem(0xFF47, 0x01);
+    gb.run_for_frame().unwrap();
+    let mut renderer = Renderer::new();
+    let mut buffer = vec![0; 160 * 144 * 4];
+    renderer.set_gamma(2.);
+    renderer.render_rgba8888(&gb, &mut buffer);
+    assert_eq!(buffer[0..4], [113, 113, 113, 0xFF]);
+  }
+
+  #[test]
+  fn color_correction() {
+    //CGB palettes are white on startup
+    let mut gb = test_rom(&[(0x143, &[0xC0])], Model::Cgb);
+    //Blank screen
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    let mut renderer = Renderer::new();
+    let mut buffer = vec![0; 160 * 144 * 4];
+    renderer.render_rgba8888(&gb, &mut buffer);
+    assert_eq!(buffer[0..4], [0xFF, 0xFF, 0xFF, 0xFF]);
+    renderer.set_color_correction(true);
+    renderer.render_rgba8888(&gb, &mut buffer);
+    assert_eq!(buffer[0..4], [240, 240, 240, 0xFF]);
+  }
+
+  #[test]
+  fn cgb_shades() {
+    let mut gb = test_rom(&[(0x143, &[0xC0])], Model::Cgb);
+    //Blank screen
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    assert_eq!(gb.get_display_data()[0], 0);
+    //Set BG palette 0 color 0 to black
+    gb.write_mem(0xFF68, 0x80);
+    gb.write_mem(0xFF69, 0x00);
+    gb.write_mem(0xFF69, 0x00);
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    assert_eq!(gb.get_display_data_cgb()[0], 0);
+    assert_eq!(gb.get_display_data()[0], 3);
+  }
+}
+
+#[cfg(test)]
+mod events {
+  use yarge_core::{Model, Event};
+  use super::test_rom;
+
+  ///MBC1 ROM that switches to bank 2, starts the timer and loops forever
+  const ROM: &[(usize, &[u8])] = &[
+    (0x147, &[0x01, 0x01]),
+    (0x100, &[
+      0x3E, 0x02,       // LD A, 2
+      0xEA, 0x00, 0x20, // LD (0x2000), A
+      0x3E, 0x05,       // LD A, 5
+      0xE0, 0x07,       // LDH (TAC), A
+      0x18, 0xFE,       // JR -2
+    ]),
+  ];
+
+  #[test]
+  fn frame() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    //VBlank is still pending after the boot ROM
+    gb.write_mem(0xFF0F, 0x00);
+    gb.enable_event_recording(1 << 20);
+    gb.run_for_frame().unwrap();
+    gb.run_for_frame().unwrap();
+    let events = gb.get_event_recorder().unwrap();
+    let count = |f: fn(&Event) -> bool| events.iter().filter(|x| f(&x.event)).count();
+    assert_eq!(count(|e| matches!(e, Event::PpuFrameEnd)), 1);
+    assert!(count(|e| matches!(e, Event::PpuLineEnd { .. })) >= 144);
+    assert!(count(|e| matches!(e, Event::InterruptRequested { interrupt: 0 })) >= 1);
+    assert!(count(|e| matches!(e, Event::TimerOverflow)) >= 1);
+    assert!(count(|e| matches!(e, Event::InterruptRequested { interrupt: 2 })) >= 1);
+    assert_eq!(count(|e| matches!(e, Event::RomBankSwitch { bank: 2 })), 1);
+    assert!(events.iter().zip(events.iter().skip(1)).all(|(a, b)| a.cycle <= b.cycle));
+  }
+
+  #[test]
+  fn rtc_select() {
+    //MBC3+TIMER+RAM ROM that switches to RAM bank 2, then selects an RTC register
+    let mut gb = test_rom(&[
+      (0x147, &[0x10, 0x00, 0x03]),
+      (0x100, &[
+        0x3E, 0x02,       // LD A, 2
+        0xEA, 0x00, 0x40, // LD (0x4000), A
+        0x3E, 0x08,       // LD A, 8
+        0xEA, 0x00, 0x40, // LD (0x4000), A
+        0x18, 0xFE,       // JR -2
+      ]),
+    ], Model::Dmg);
+    gb.enable_event_recording(1 << 10);
+    for _ in 0..8 {
+      gb.step().unwrap();
+    }
+    let events = gb.get_event_recorder().unwrap();
+    let banks: Vec<_> = events.iter().filter_map(|x| match x.event {
+      Event::RamBankSwitch { bank } => Some(bank),
+      _ => None,
+    }).collect();
+    assert_eq!(banks, [2]);
+  }
+
+  #[test]
+  fn ring_buffer() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.enable_event_recording(100);
+    gb.run_for_frame().unwrap();
+    assert_eq!(gb.get_event_recorder().unwrap().len(), 100);
+    assert!(gb.disable_event_recording().is_some());
+    assert!(gb.get_event_recorder().is_none());
+  }
+
+  #[test]
+  fn json_lines() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.enable_event_recording(1 << 20);
+    for _ in 0..4 {
+      gb.step().unwrap();
+    }
+    let mut out = Vec::new();
+    gb.get_event_recorder().unwrap().write_json_lines(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let line = out.lines().find(|line| line.contains("ROM_BANK_SWITCH")).unwrap();
+    assert!(line.starts_with("{\"cycle\":"));
+    assert!(line.ends_with(",\"type\":\"ROM_BANK_SWITCH\",\"bank\":2}"));
+    assert!(out.lines().all(|line| line.starts_with('{') && line.ends_with('}')));
+  }
+}
+
+#[cfg(test)]
+mod watchpoints {
+  use yarge_core::{Model, Watchpoint, YargeError};
+  use super::{test_rom, step_until_error};
+
+  const ROM: &[(usize, &[u8])] = &[(0x100, &[
+    0x3E, 0x42,       // LD A, 0x42
+    0xEA, 0x00, 0xC0, // LD (0xC000), A
+    0xFA, 0x00, 0xC0, // LD A, (0xC000)
+    0x18, 0xFE,       // JR -2
+  ])];
+
+  #[test]
+  fn write() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.add_watchpoint(Watchpoint::new(0xC000..=0xC0FF, false, true));
+    let error = step_until_error(&mut gb, 100).unwrap();
+    assert!(error.is_recoverable());
+    assert!(matches!(error, YargeError::MmuBreakpoint { is_write: true, addr: 0xC000, value: 0x42 }));
+    //Stops after the instruction is done
+    assert_eq!(gb.get_reg_pc(), 0x105);
+    assert_eq!(gb.read_mem(0xC000), 0x42);
+    //Resumes normally
+    assert!(step_until_error(&mut gb, 100).is_none());
+  }
+
+  #[test]
+  fn read() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.add_watchpoint(Watchpoint::new(0xC000..=0xC000, true, false));
+    let error = step_until_error(&mut gb, 100).unwrap();
+    assert!(matches!(error, YargeError::MmuBreakpoint { is_write: false, addr: 0xC000, value: 0x42 }));
+    assert_eq!(gb.get_reg_pc(), 0x108);
+  }
+
+  #[test]
+  fn value_match() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.add_watchpoint(Watchpoint::new(0xC000..=0xC000, true, true).with_value(0x00));
+    assert!(step_until_error(&mut gb, 100).is_none());
+    gb.clear_watchpoints();
+    gb.add_watchpoint(Watchpoint::new(0xC000..=0xC000, true, true).with_value(0x42));
+    gb.reset();
+    assert_eq!(gb.get_watchpoints().len(), 1);
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.add_watchpoint(Watchpoint::new(0xC000..=0xC000, true, true).with_value(0x42));
+    let error = step_until_error(&mut gb, 100).unwrap();
+    assert!(matches!(error, YargeError::MmuBreakpoint { is_write: true, addr: 0xC000, value: 0x42 }));
+  }
+
+  #[test]
+  fn oam_dma() {
+    let mut gb = test_rom(&[(0x100, &[
+      0x3E, 0x42,       // LD A, 0x42
+      0xEA, 0x05, 0xC0, // LD (0xC005), A
+      0x3E, 0xC0,       // LD A, 0xC0
+      0xE0, 0x46,       // LDH (DMA), A
+      0x18, 0xFE,       // JR -2
+    ])], Model::Dmg);
+    gb.add_watchpoint(Watchpoint::new(0xFE00..=0xFE9F, false, true).with_value(0x42));
+    let error = step_until_error(&mut gb, 100).unwrap();
+    assert!(matches!(error, YargeError::MmuBreakpoint { is_write: true, addr: 0xFE05, value: 0x42 }));
+  }
+}
+
+#[cfg(test)]
+mod breakpoints {
+  use yarge_core::{Model, Breakpoint, BreakpointCondition, Register, Compare, YargeError};
+  use super::{test_rom, step_until_error};
+
+  ///MBC1 ROM that switches to bank 2 and calls a subroutine incrementing B
+  ///(which starts at 0 after the boot ROM) in a loop
+  const ROM: &[(usize, &[u8])] = &[
+    (0x147, &[0x01, 0x01]),
+    (0x100, &[
+      0x3E, 0x02,       // LD A, 2
+      0xEA, 0x00, 0x20, // LD (0x2000), A
+      0xCD, 0x00, 0x40, // CALL 0x4000
+      0x18, 0xFB,       // JR -5
+    ]),
+    (0x4000, &[0x0C, 0xC9]), // INC C; RET
+    (0x8000, &[0x04, 0xC9]), // INC B; RET
+  ];
+
+  #[test]
+  fn before_execution() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.add_breakpoint(Breakpoint::new(0x102));
+    let error = step_until_error(&mut gb, 1000).unwrap();
+    assert!(error.is_recoverable());
+    assert!(matches!(error, YargeError::PcBreakpoint { addr: 0x102, bank: 0, instr: 0xEA }));
+    assert_eq!(gb.get_reg_pc(), 0x102);
+    assert_eq!(gb.get_bank_at(0x4000), 1);
+    //Resuming skips the breakpoint once
+    gb.step().unwrap();
+    assert_eq!(gb.get_reg_pc(), 0x105);
+    assert_eq!(gb.get_bank_at(0x4000), 2);
+  }
+
+  #[test]
+  fn bank() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.add_breakpoint(Breakpoint::new(0x4000).with_bank(1));
+    assert!(step_until_error(&mut gb, 1000).is_none());
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.add_breakpoint(Breakpoint::new(0x4000).with_bank(2));
+    let error = step_until_error(&mut gb, 1000).unwrap();
+    assert!(matches!(error, YargeError::PcBreakpoint { addr: 0x4000, bank: 2, instr: 0x04 }));
+    assert_eq!(gb.get_reg_b(), 0);
+  }
+
+  #[test]
+  fn ignore_count() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.add_breakpoint(Breakpoint::new(0x4000).with_ignore_count(2));
+    assert!(step_until_error(&mut gb, 1000).is_some());
+    assert_eq!(gb.get_reg_b(), 2);
+    assert_eq!(gb.get_breakpoints()[0].hits, 3);
+    //Triggers on every hit afterwards
+    assert!(step_until_error(&mut gb, 1000).is_some());
+    assert_eq!(gb.get_reg_b(), 3);
+  }
+
+  #[test]
+  fn conditions() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.add_breakpoint(
+      Breakpoint::new(0x4000)
+        .with_condition(BreakpointCondition::Register(Register::B, Compare::Ge, 5))
+        .with_condition(BreakpointCondition::Memory(0x4000, Compare::Eq, 0x04))
+    );
+    assert!(step_until_error(&mut gb, 1000).is_some());
+    assert_eq!(gb.get_reg_b(), 5);
+    assert_eq!(gb.get_breakpoints()[0].hits, 1);
+  }
+}
+
+#[cfg(test)]
+mod debugger {
+  use yarge_core::{Model, CallFrame};
+  use super::test_rom;
+
+  const ROM: &[(usize, &[u8])] = &[
+    (0x100, &[
+      0xCD, 0x10, 0x01, // CALL 0x110
+      0x04,             // INC B
+      0x18, 0xFA,       // JR -6
+    ]),
+    (0x110, &[
+      0xCD, 0x20, 0x01, // CALL 0x120
+      0x0C,             // INC C
+      0xC9,             // RET
+    ]),
+    (0x120, &[0x14, 0xC9]), // INC D; RET
+  ];
+
+  #[test]
+  fn step_over() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.set_reg_bc(0);
+    gb.set_reg_de(0);
+    assert!(gb.step_over(10000).unwrap());
+    assert_eq!(gb.get_reg_pc(), 0x103);
+    assert_eq!((gb.get_reg_c(), gb.get_reg_d()), (1, 1));
+    assert!(gb.get_call_stack().is_empty());
+    //Not a call
+    assert!(gb.step_over(10000).unwrap());
+    assert_eq!(gb.get_reg_pc(), 0x104);
+  }
+
+  #[test]
+  fn step_over_interrupt() {
+    let mut gb = test_rom(&[
+      (0x50, &[0x1C, 0xD9]), // INC E; RETI
+      (0x100, &[
+        0xFB,       // EI
+        0x00,       // NOP
+        0x18, 0xFD, // JR -3
+      ]),
+    ], Model::Dmg);
+    gb.set_reg_e(0);
+    gb.write_mem(0xFFFF, 0x04);
+    gb.write_mem(0xFF0F, 0x04);
+    //The timer interrupt handler runs during one of these steps, but is never stepped into
+    for _ in 0..4 {
+      assert!(gb.step_over(10000).unwrap());
+      assert!(gb.get_reg_pc() >= 0x100);
+      assert!(gb.get_call_stack().is_empty());
+    }
+    assert_eq!(gb.get_reg_e(), 1);
+  }
+
+  #[test]
+  fn step_out() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    gb.set_reg_bc(0);
+    assert!(!gb.step_out(10000).unwrap());
+    gb.step().unwrap();
+    assert_eq!(gb.get_call_stack(), &[CallFrame { target: 0x110, return_addr: 0x103, sp: 0xFFFC, interrupt: false }]);
+    gb.step().unwrap();
+    assert_eq!(gb.get_call_stack().len(), 2);
+    assert!(gb.step_out(10000).unwrap());
+    assert_eq!(gb.get_reg_pc(), 0x113);
+    assert_eq!(gb.get_call_stack().len(), 1);
+    assert!(gb.step_out(10000).unwrap());
+    assert_eq!(gb.get_reg_pc(), 0x103);
+    assert_eq!(gb.get_reg_c(), 1);
+  }
+
+  #[test]
+  fn run_to_pc() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    assert!(gb.run_to_pc(0x121, 10000).unwrap());
+    assert_eq!(gb.get_reg_pc(), 0x121);
+    assert!(!gb.run_to_pc(0x200, 10000).unwrap());
+  }
+
+  #[test]
+  fn run_for_cycles() {
+    let mut gb = test_rom(ROM, Model::Dmg);
+    let cycles = gb.run_for_cycles(100).unwrap();
+    assert!((100..124).contains(&cycles));
+  }
+}
+
+#[cfg(test)]
+mod disasm {
+  use yarge_core::{Model, Symbols, disasm::disassemble};
+  use super::test_rom;
+
+  fn decode(addr: u16, bytes: &[u8]) -> yarge_core::Instruction {
+    disassemble(addr, |x| bytes.get(x.wrapping_sub(addr) as usize).copied().unwrap_or(0))
+  }
+
+  #[test]
+  fn instructions() {
+    let instr = decode(0, &[0x01, 0x34, 0x12]);
+    assert_eq!((instr.to_string().as_str(), instr.length, instr.cycles), ("LD BC,$1234", 3, 12));
+    let instr = decode(0x100, &[0x20, 0xFE]);
+    assert_eq!(instr.to_string(), "JR NZ,$0100");
+    assert_eq!((instr.cycles, instr.cycles_branch), (8, Some(12)));
+    assert_eq!(decode(0, &[0xF8, 0xFD]).to_string(), "LD HL,SP-$03");
+    assert_eq!(decode(0, &[0xE8, 0x05]).to_string(), "ADD SP,$05");
+    assert_eq!(decode(0, &[0xE0, 0x40]).to_string(), "LD (FF00+$40),A");
+    assert_eq!(decode(0, &[0xC9]).mnemonic(), "RET");
+    let instr = decode(0, &[0xD3]);
+    assert_eq!((instr.to_string().as_str(), instr.length), ("ILLEGAL", 1));
+  }
+
+  #[test]
+  fn prefixed() {
+    let instr = decode(0, &[0xCB, 0x7C]);
+    assert!(instr.prefixed);
+    assert_eq!((instr.to_string().as_str(), instr.length, instr.cycles), ("BIT 7,H", 2, 8));
+    assert_eq!(decode(0, &[0xCB, 0x46]).cycles, 12);
+    let instr = decode(0, &[0xCB, 0x36]);
+    assert_eq!((instr.to_string().as_str(), instr.cycles), ("SWAP (HL)", 16));
+  }
+
+  #[test]
+  fn symbols() {
+    let symbols = Symbols::parse("; comment\n00:0150 Main\n00:FF40 rLCDC\n02:4000 Func ; bank 2\n");
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(decode(0, &[0xCD, 0x50, 0x01]).format(Some(&symbols)), "CALL Main");
+    assert_eq!(decode(0, &[0xE0, 0x40]).format(Some(&symbols)), "LD (rLCDC),A");
+    assert_eq!(decode(0x14E, &[0x18, 0x00]).format(Some(&symbols)), "JR Main");
+    //Banked symbols are resolved using the currently mapped bank
+    let mut instr = decode(0, &[0xC3, 0x00, 0x40]);
+    assert_eq!(instr.format(Some(&symbols)), "JP $4000");
+    instr.target_bank = 2;
+    assert_eq!(instr.format(Some(&symbols)), "JP Func");
+  }
+
+  #[test]
+  fn gameboy() {
+    let gb = test_rom(&[(0x100, &[0x00, 0xC3, 0x50, 0x01])], Model::Dmg); // NOP; JP 0x150
+    assert_eq!(gb.disassemble(0x100).to_string(), "NOP");
+    let instr = gb.disassemble(0x101);
+    assert_eq!((instr.to_string().as_str(), instr.target()), ("JP $0150", Some(0x150)));
+  }
+}
+
+#[cfg(test)]
+mod vram_viewer {
+  use yarge_core::{Gameboy, Model, Renderer, OamFlags, renderer::DEFAULT_PALETTE};
+  use super::test_rom;
+
+  const SHADE_1: [u8; 4] = [DEFAULT_PALETTE[1][0], DEFAULT_PALETTE[1][1], DEFAULT_PALETTE[1][2], 0xFF];
+
+  fn setup_tile(gb: &mut Gameboy) {
+    gb.write_mem(0xFF40, 0x10);
+    gb.write_mem(0xFF47, 0xE4);
+    gb.write_mem(0xFF49, 0xE4);
+    //Tile 1: top row uses color 1, the rest is color 0
+    gb.write_mem(0x8010, 0xFF);
+    gb.write_mem(0x8011, 0x00);
+  }
+
+  #[test]
+  fn tileset() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    setup_tile(&mut gb);
+    let image = gb.render_tileset();
+    assert_eq!((image.width, image.height, image.data.len()), (128, 192, 128 * 192 * 4));
+    assert_eq!(image.get_pixel(8, 0), SHADE_1);
+    assert_eq!(image.get_pixel(8, 1), [0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(image.get_pixel(0, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+  }
+
+  #[test]
+  fn tilemap() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    setup_tile(&mut gb);
+    gb.write_mem(0x9800, 1);
+    gb.write_mem(0xFF43, 200); // SCX
+    gb.write_mem(0xFF42, 8);   // SCY
+    let renderer = Renderer::new();
+    let image = renderer.render_tilemap(&gb, 0, false);
+    assert_eq!((image.width, image.height), (256, 256));
+    assert_eq!(image.get_pixel(0, 0), SHADE_1);
+    assert_eq!(image.get_pixel(200, 8), [0xFF, 0xFF, 0xFF, 0xFF]);
+
+    //Viewport wraps around the right edge
+    let image = renderer.render_tilemap(&gb, 0, true);
+    for (x, y) in [(200, 8), (255, 8), (0, 8), (103, 8), (103, 151), (200, 151)] {
+      assert_eq!(image.get_pixel(x, y), [0xFF, 0, 0, 0xFF]);
+    }
+    assert_eq!(image.get_pixel(104, 8), [0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(image.get_pixel(201, 9), [0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(renderer.render_tilemap(&gb, 1, true).get_pixel(200, 8), [0xFF, 0xFF, 0xFF, 0xFF]);
+
+    //Window uses map 1 here
+    gb.write_mem(0xFF40, 0x70);
+    gb.write_mem(0xFF4B, 107); // WX
+    gb.write_mem(0xFF4A, 44);  // WY
+    let image = renderer.render_tilemap(&gb, 1, true);
+    assert_eq!(image.get_pixel(59, 0), [0x00, 0x80, 0xFF, 0xFF]);
+    assert_eq!(image.get_pixel(59, 99), [0x00, 0x80, 0xFF, 0xFF]);
+    assert_eq!(image.get_pixel(60, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(image.get_pixel(0, 100), [0xFF, 0xFF, 0xFF, 0xFF]);
+  }
+
+  #[test]
+  fn oam() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    setup_tile(&mut gb);
+    for (i, value) in [16, 8, 1, 0x70].into_iter().enumerate() {
+      gb.write_mem(0xFE04 + i as u16, value);
+    }
+    let object = gb.get_oam()[1];
+    assert_eq!((object.id, object.y, object.x, object.tile), (1, 16, 8, 1));
+    assert_eq!(object.flags, OamFlags { priority: false, flip_y: true, flip_x: true, palette: true, hi_bits: 0 });
+
+    let images = Renderer::new().render_oam(&gb);
+    assert_eq!(images.len(), 40);
+    assert_eq!((images[1].width, images[1].height), (8, 8));
+    //Flipped vertically, color 0 is transparent
+    assert_eq!(images[1].get_pixel(0, 7), SHADE_1);
+    assert_eq!(images[1].get_pixel(0, 0)[3], 0);
+
+    gb.write_mem(0xFF40, 0x14);
+    let images = Renderer::new().render_oam(&gb);
+    //Tile index bit 0 is ignored, tile 1 is the bottom half (on top once flipped)
+    assert_eq!((images[1].width, images[1].height), (8, 16));
+    assert_eq!(images[1].get_pixel(0, 7), SHADE_1);
+    assert_eq!(images[1].get_pixel(0, 15)[3], 0);
+  }
+}
+
+#[cfg(test)]
+mod io_registers {
+  use yarge_core::{Model, CpuState, io_registers::PpuMode};
+  use super::test_rom;
+
+  #[test]
+  fn decoded() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    gb.write_mem(0xFF40, 0x00);
+    gb.write_mem(0xFF41, 0x48);
+    gb.write_mem(0xFF07, 0x05);
+    gb.write_mem(0xFF06, 0x42);
+    gb.write_mem(0xFFFF, 0x05);
+    gb.write_mem(0xFF0F, 0x02);
+    gb.write_mem(0xFF00, 0x20);
+    gb.write_mem(0xFF01, 0x5A);
+    let io = gb.get_io_registers();
+    assert_eq!(io.ppu.lcdc.into_u8(), 0x00);
+    //LYC=LY and mode 0 interrupt sources
+    assert!(io.ppu.stat.lyc_eq && io.ppu.stat.mode_0 && !io.ppu.stat.mode_1 && !io.ppu.stat.mode_2);
+    assert!(io.timers.enable);
+    assert_eq!((io.timers.rate, io.timers.frequency(), io.timers.tma), (1, 262144, 0x42));
+    assert_eq!((io.interrupts.ie, io.interrupts.iif, io.interrupts.state), (0x05, 0x02, CpuState::Running));
+    assert!(io.joypad.select_direction && !io.joypad.select_action);
+    assert_eq!(io.serial.sb, 0x5A);
+
+    gb.write_mem(0xFF40, 0x91);
+    gb.run_for_cycles(100).unwrap();
+    let io = gb.get_io_registers();
+    assert!(io.ppu.lcdc.enable_display && io.ppu.lcdc.tiledata_addr && io.ppu.lcdc.enable_bg && !io.ppu.lcdc.enable_win);
+    assert_ne!(io.ppu.mode, PpuMode::VBlank);
+    assert!(io.timers.div > 0);
+  }
+
+  #[test]
+  fn apu_channels() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    gb.write_mem(0xFF26, 0x80);
+    gb.write_mem(0xFF11, 0x80); // 50% duty
+    gb.write_mem(0xFF12, 0xF3); // volume 15, decreasing, period 3
+    gb.write_mem(0xFF13, 0x34);
+    gb.write_mem(0xFF14, 0x87); // trigger, frequency 0x734
+    gb.write_mem(0xFF1A, 0x80);
+    gb.write_mem(0xFF1C, 0x40); // 50% volume
+    gb.write_mem(0xFF30, 0x12);
+    gb.write_mem(0xFF22, 0x5B); // shift 5, short width, divider 3
+    gb.write_mem(0xFF25, 0x81);
+    let apu = gb.get_io_registers().apu;
+    assert!(apu.enabled);
+    assert!(apu.channel1.enabled && apu.channel1.dac_enabled);
+    assert_eq!((apu.channel1.duty, apu.channel1.frequency), (2, 0x734));
+    assert_eq!((apu.channel1.envelope.volume, apu.channel1.envelope.period), (15, 3));
+    assert!(!apu.channel1.envelope.increase);
+    assert!(apu.channel1.sweep.is_some() && apu.channel2.sweep.is_none());
+    assert!(!apu.channel2.enabled);
+    assert!(apu.channel3.dac_enabled && !apu.channel3.enabled);
+    assert_eq!((apu.channel3.output_level, apu.channel3.wave_ram[0]), (2, 0x12));
+    assert_eq!((apu.channel4.shift, apu.channel4.width_short, apu.channel4.divider), (5, true, 3));
+    assert_eq!(apu.right.channels, [true, false, false, false]);
+    assert_eq!(apu.left.channels, [false, false, false, true]);
+  }
+}
+
+#[cfg(test)]
+mod audio_channels {
+  use std::{rc::Rc, cell::RefCell};
+  use yarge_core::{Gameboy, AudioDevice, AudioFilter, Model, consts::{AUDIO_BUFFER_SIZE, CHANNEL_BUFFER_SIZE, CYCLES_PER_FRAME}};
+  use super::test_rom;
+
+  struct Capture(Rc<RefCell<Vec<f32>>>);
+  impl AudioDevice for Capture {
+    fn queue_samples(&mut self, buffer: &[f32]) {
+      self.0.borrow_mut().extend_from_slice(buffer);
+    }
+  }
+
+  ///Captures the audio output and starts CH2
+  fn capture_ch2(gb: &mut Gameboy) -> Rc<RefCell<Vec<f32>>> {
+    let samples = Rc::new(RefCell::new(Vec::new()));
+    gb.set_audio_device(Capture(samples.clone()));
+    gb.write_mem(0xFF26, 0x80);
+    gb.write_mem(0xFF24, 0x77);
+    gb.write_mem(0xFF25, 0xFF);
+    //CH2: 50% duty, max volume, ~1 kHz
+    gb.write_mem(0xFF16, 0x80);
+    gb.write_mem(0xFF17, 0xF0);
+    gb.write_mem(0xFF18, 0x83);
+    gb.write_mem(0xFF19, 0x87);
+    samples
+  }
+
+  fn run(gb: &mut Gameboy) {
+    gb.run_for_cycles(CYCLES_PER_FRAME * 8).unwrap();
+  }
+
+  #[test]
+  fn channel_samples() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    capture_ch2(&mut gb);
+    run(&mut gb);
+    let mut samples = vec![0.; CHANNEL_BUFFER_SIZE * 2];
+    assert_eq!(gb.get_channel_samples(1, &mut samples), CHANNEL_BUFFER_SIZE);
+    assert!(samples.iter().any(|&x| x > 0.9) && samples.iter().any(|&x| x < -0.9));
+    assert_eq!(gb.get_channel_samples(0, &mut samples[..16]), 16);
+    assert!(samples[..16].iter().all(|&x| x == 0.));
+  }
+
+  #[test]
+  fn mute_solo() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    let output = capture_ch2(&mut gb);
+    //Without the high-pass filter, silence is exactly zero
+    gb.set_audio_filter(AudioFilter::Raw);
+    run(&mut gb);
+    assert!(output.borrow().iter().any(|&x| x != 0.));
+
+    gb.set_channel_muted(1, true);
+    assert!(!gb.is_channel_audible(1));
+    run(&mut gb);
+    output.borrow_mut().clear();
+    run(&mut gb);
+    assert!(!output.borrow().is_empty());
+    assert!(output.borrow().iter().all(|&x| x == 0.));
+    //Raw channel output is still recorded while muted
+    let mut samples = [0.; 64];
+    gb.get_channel_samples(1, &mut samples);
+    assert!(samples.iter().any(|&x| x != 0.));
+
+    gb.set_channel_muted(1, false);
+    gb.set_channel_solo(0, true);
+    assert!(gb.is_channel_audible(0) && !gb.is_channel_audible(1));
+    gb.set_channel_solo(1, true);
+    assert!(gb.is_channel_audible(1) && !gb.is_channel_audible(2));
+    gb.reset();
+    assert!(gb.get_channel_solo(0) && gb.get_channel_solo(1));
+    assert_eq!(gb.get_audio_filter(), AudioFilter::Raw);
+  }
+
+  #[test]
+  fn out_of_range() {
+    let mut gb = Gameboy::new();
+    gb.set_channel_muted(4, true);
+    gb.set_channel_solo(4, true);
+    assert!(!gb.get_channel_muted(4) && !gb.get_channel_solo(4));
+    assert!(!gb.is_channel_audible(4));
+    assert!(gb.is_channel_audible(0));
+    assert_eq!(gb.get_channel_samples(4, &mut [0.; 16]), 0);
+  }
+
+  fn mean(samples: &[f32]) -> f32 {
+    samples.iter().sum::<f32>() / samples.len() as f32
+  }
+
+  #[test]
+  fn high_pass() {
+    assert_eq!(Gameboy::new().get_audio_filter(), AudioFilter::Dmg);
+    assert_eq!(Gameboy::builder().model(Model::Cgb).build().unwrap().get_audio_filter(), AudioFilter::Cgb);
+
+    //75% duty has a DC offset, which gets removed by the filter
+    let mut gb = test_rom(&[], Model::Dmg);
+    let output = capture_ch2(&mut gb);
+    gb.set_audio_filter(AudioFilter::Raw);
+    gb.write_mem(0xFF16, 0xC0);
+    run(&mut gb);
+    let raw = mean(&output.borrow()[AUDIO_BUFFER_SIZE..]);
+    assert!(raw > 0.05, "{}", raw);
+
+    let mut gb = test_rom(&[], Model::Dmg);
+    let output = capture_ch2(&mut gb);
+    gb.write_mem(0xFF16, 0xC0);
+    run(&mut gb);
+    let filtered = mean(&output.borrow()[AUDIO_BUFFER_SIZE..]);
+    assert!(filtered.abs() < 0.01, "{}", filtered);
+  }
+
+  #[test]
+  fn band_limited() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    let output = capture_ch2(&mut gb);
+    gb.set_audio_filter(AudioFilter::Raw);
+    run(&mut gb);
+    let output = output.borrow();
+    //Steps are smoothed out instead of jumping between two levels,
+    //but still settle at the exact level (max volume, 1/4 of the mix)
+    assert!(output.iter().any(|&x| x.abs() < 0.1));
+    assert!(output.contains(&0.25) && output.contains(&-0.25));
+    let peak = output.iter().fold(0., |a: f32, &x| a.max(x.abs()));
+    assert!(peak < 0.25 * 1.3, "{}", peak);
+  }
+}
+
+#[cfg(test)]
+mod audio_output {
+  use std::{rc::Rc, cell::RefCell};
+  use yarge_core::{Model, AudioDevice, YargeError, consts::{CPU_CLOCK_SPEED, CYCLES_PER_FRAME}};
+  use super::test_rom;
+
+  struct ChunkSizes(Rc<RefCell<Vec<usize>>>);
+  impl AudioDevice for ChunkSizes {
+    fn queue_samples(&mut self, buffer: &[f32]) {
+      self.0.borrow_mut().push(buffer.len());
+    }
+  }
+
+  ///Number of values (2 per stereo sample) generated in `cycles`
+  fn expected_len(cycles: usize, sample_rate: usize) -> usize {
+    cycles * sample_rate / CPU_CLOCK_SPEED * 2
+  }
+
+  #[test]
+  fn drain() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    let cycles = gb.run_for_cycles(CYCLES_PER_FRAME).unwrap();
+    let available = gb.get_audio_available();
+    assert!(available.abs_diff(expected_len(cycles, 44100)) <= 2, "{}", available);
+
+    let mut out = vec![0.; 1001];
+    assert_eq!(gb.drain_audio(&mut out), 1000);
+    assert_eq!(gb.get_audio_available(), available - 1000);
+    assert_eq!(gb.drain_audio(&mut out), available - 1000);
+    assert_eq!(gb.drain_audio(&mut out), 0);
+  }
+
+  #[test]
+  fn sample_rate() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    gb.set_audio_sample_rate(48000).unwrap();
+    assert_eq!(gb.get_audio_sample_rate(), 48000);
+    let cycles = gb.run_for_cycles(CYCLES_PER_FRAME).unwrap();
+    assert!(gb.get_audio_available().abs_diff(expected_len(cycles, 48000)) <= 2);
+
+    //Samples are discarded on sample rate change, settings are kept across resets
+    gb.set_audio_sample_rate(22050).unwrap();
+    assert_eq!(gb.get_audio_available(), 0);
+    gb.reset();
+    assert_eq!(gb.get_audio_sample_rate(), 22050);
+  }
+
+  #[test]
+  fn invalid_settings() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    assert!(matches!(gb.set_audio_sample_rate(0), Err(YargeError::InvalidSampleRate(0))));
+    assert!(gb.set_audio_sample_rate(CPU_CLOCK_SPEED + 1).is_err());
+    assert!(matches!(gb.set_audio_chunk_size(511), Err(YargeError::InvalidChunkSize(511))));
+    assert!(gb.set_audio_chunk_size(0).is_err());
+    assert_eq!(gb.get_audio_sample_rate(), 44100);
+    assert_eq!(gb.get_audio_chunk_size(), 4096);
+  }
+
+  #[test]
+  fn buffer_limit() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    gb.set_audio_sample_rate(8000).unwrap();
+    gb.run_for_cycles(CPU_CLOCK_SPEED * 2).unwrap();
+    assert_eq!(gb.get_audio_available(), 8000 * 2);
+  }
+
+  #[test]
+  fn apu_off() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    gb.write_mem(0xFF26, 0x00);
+    let cycles = gb.run_for_cycles(CYCLES_PER_FRAME).unwrap();
+    let mut out = vec![1.; 4096];
+    let len = gb.drain_audio(&mut out);
+    assert!(len.abs_diff(expected_len(cycles, 44100)) <= 2);
+    assert!(out[..len].iter().all(|&x| x == 0.));
+  }
+
+  #[test]
+  fn device_chunks() {
+    let mut gb = test_rom(&[], Model::Dmg);
+    let chunks = Rc::new(RefCell::new(Vec::new()));
+    gb.set_audio_chunk_size(512).unwrap();
+    gb.set_audio_device(ChunkSizes(chunks.clone()));
+    let cycles = gb.run_for_cycles(CYCLES_PER_FRAME * 4).unwrap();
+    let chunks = chunks.borrow();
+    assert_eq!(chunks.len(), expected_len(cycles, 44100) / 512);
+    assert!(chunks.iter().all(|&x| x == 512));
+    assert!(gb.get_audio_available() < 512);
+  }
 }